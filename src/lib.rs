@@ -14,10 +14,14 @@ pub mod rt_types;
 pub use rt_types::*;
 pub mod rt_sampler;
 pub use rt_sampler::*;
+pub mod rt_filter;
+pub use rt_filter::*;
 pub mod rt_ray;
 pub use rt_ray::*;
 pub mod rt_camera;
 pub use rt_camera::*;
+pub mod rt_bvh;
+pub use rt_bvh::*;
 
 // Object module
 pub mod rt_objects;
@@ -29,8 +33,20 @@ pub mod rt_shader_globals;
 pub use rt_shader_globals::*;
 pub mod rt_render;
 pub use rt_render::*;
+pub mod rt_render_job;
+pub use rt_render_job::*;
 pub mod rt_render_output;
 pub use rt_render_output::*;
+pub mod rt_display;
+pub use rt_display::*;
+pub mod rt_obj_loader;
+pub use rt_obj_loader::*;
+pub mod rt_image;
+pub use rt_image::*;
+pub mod rt_sky;
+pub use rt_sky::*;
+pub mod rt_ies;
+pub use rt_ies::*;
 
 pub const RT_DEFAULT_WINDOW_HEIGHT: usize = 400;
 pub const RT_DEFAULT_WINDOW_WIDTH: usize = 400;
@@ -10,16 +10,19 @@
 /// 
 /// Module description :
 ///   Defines executable that will launch a render
-///   window. 
+///   window, or render a scene from the command line.
 /// =====================================================
 
 mod render_window_params;
 mod render_window;
+mod render_cli;
 mod rt_test;
 pub use rt_test::rt_test;
 pub use render_window::RaitoRenderApp;
 use log::info;
-use clap::{Parser, ValueEnum};
+use std::process::ExitCode;
+use clap::{Parser, Subcommand, ValueEnum};
+use render_cli::RenderArgs;
 
 const WINDOW_WIDTH: f32 = 710.0;
 const MIN_WINDOW_HEIGHT: f32 = 470.0;
@@ -36,13 +39,23 @@ pub struct RaitoArgs {
     /// Execute tests
     #[arg(short, long)]
     tests: bool,
+
+    #[command(subcommand)]
+    command: Option<RaitoCommand>,
+}
+
+/// Subcommands
+#[derive(Debug, Subcommand)]
+enum RaitoCommand {
+    /// Render a scene without the render window
+    Render(RenderArgs),
 }
 
 /// Log levels
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
 enum RtLevel { Debug, Info, Warning, Error }
 
-fn main() -> Result<(), eframe::Error> {
+fn main() -> ExitCode {
     let args = RaitoArgs::parse();
 
     match args.log_level {
@@ -57,7 +70,11 @@ fn main() -> Result<(), eframe::Error> {
 
     if args.tests {
         rt_test();
-        return Ok(());
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(RaitoCommand::Render(render_args)) = &args.command {
+        return render_cli::render(render_args);
     }
 
     let native_options = eframe::NativeOptions {
@@ -71,9 +88,16 @@ fn main() -> Result<(), eframe::Error> {
         default_theme: eframe::Theme::Dark,
         ..Default::default()
     };
-    eframe::run_native(
+    let result = eframe::run_native(
         "Raito Render",
         native_options,
         Box::new(|_cc| Box::<RaitoRenderApp>::default()),
-    )
+    );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,190 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Headless rendering from the command line : renders
+///   a XML scene and writes the image, without opening
+///   the render window.
+/// =====================================================
+
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use clap::Args;
+use log::{error, info};
+
+use raito::*;
+
+/// Render a scene without the render window
+#[derive(Debug, Args)]
+pub struct RenderArgs {
+    /// XML scene to render
+    scene: PathBuf,
+
+    /// Output image (.png, .ppm or .exr)
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Samples per pixel, overrides the scene settings
+    #[arg(long)]
+    spp: Option<u8>,
+
+    /// Image width, overrides the scene settings
+    #[arg(long)]
+    width: Option<u16>,
+
+    /// Image height, overrides the scene settings
+    #[arg(long)]
+    height: Option<u16>,
+
+    /// Pixel aspect ratio (width / height of a pixel), overrides the scene settings
+    #[arg(long)]
+    pixel_aspect: Option<f32>,
+
+    /// Seed of the sampler, overrides the scene settings
+    #[arg(long)]
+    seed: Option<u32>,
+
+    /// Noise threshold of adaptive sampling (0 : off), overrides the scene settings
+    #[arg(long)]
+    adaptive_threshold: Option<f32>,
+
+    /// Maximum samples per pixel of adaptive sampling, overrides the scene settings
+    #[arg(long)]
+    max_spp: Option<u16>,
+
+    /// Pixel filter (box, triangle, gaussian, mitchell, blackman-harris), overrides the scene settings
+    #[arg(long, value_parser = parse_filter)]
+    filter: Option<RtFilterType>,
+
+    /// Width of the pixel filter in pixels, overrides the scene settings
+    #[arg(long)]
+    filter_width: Option<f32>,
+
+    /// Image written to the output (beauty, spp)
+    #[arg(long, default_value = "beauty", value_parser = parse_aov)]
+    aov: RtAov,
+
+    /// Number of render threads (0 : all cores), overrides the scene settings
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+fn parse_filter(name: &str) -> Result<RtFilterType, String> {
+    RtFilterType::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = RtFilterType::ALL.iter().map(|filter| filter.name()).collect();
+        format!("unknown filter {name}, expected one of : {}", names.join(", "))
+    })
+}
+
+fn parse_aov(name: &str) -> Result<RtAov, String> {
+    RtAov::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = RtAov::ALL.iter().map(|aov| aov.name()).collect();
+        format!("unknown AOV {name}, expected one of : {}", names.join(", "))
+    })
+}
+
+/// Render the scene and write the image
+pub fn render(args: &RenderArgs) -> ExitCode {
+    if !RtRenderResult::is_supported_format(&args.output) {
+        error!("Unsupported image format : {}", args.output.display());
+        return ExitCode::FAILURE;
+    }
+
+    let mut scene = match open_xml_scene(&args.scene.to_string_lossy()) {
+        Ok(scene) => scene,
+        Err(e) => {
+            error!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Override settings
+    let mut settings = scene.settings;
+    if let Some(spp) = args.spp {
+        settings.render_spp = spp;
+    }
+    if let Some(seed) = args.seed {
+        settings.seed = seed;
+    }
+    if let Some(threshold) = args.adaptive_threshold {
+        settings.adaptive_threshold = threshold.max(0.0);
+    }
+    if let Some(max_spp) = args.max_spp {
+        settings.max_spp = max_spp;
+    }
+    if let Some(filter) = args.filter {
+        settings.filter = filter;
+        settings.filter_width = filter.default_width();
+    }
+    if let Some(filter_width) = args.filter_width {
+        if filter_width > 0.0 {
+            settings.filter_width = filter_width;
+        } else {
+            error!("Invalid filter width {filter_width}");
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(threads) = args.threads {
+        settings.threads = threads;
+    }
+    // Keep the aspect ratio of the scene when only the width or height is given
+    let aspect_ratio = settings.width as f32 / settings.height as f32;
+    match (args.width, args.height) {
+        (Some(w), Some(h)) => (settings.width, settings.height) = (w, h),
+        (Some(w), None) => (settings.width, settings.height) = (w, (w as f32 / aspect_ratio).round() as u16),
+        (None, Some(h)) => (settings.width, settings.height) = ((h as f32 * aspect_ratio).round() as u16, h),
+        (None, None) => (),
+    }
+    (settings.width, settings.height) = (settings.width.max(1), settings.height.max(1));
+    if let Some(pixel_aspect) = args.pixel_aspect {
+        settings.pixel_aspect = pixel_aspect;
+    }
+    scene.set_settings(settings);
+
+    let camera = scene.get_camera();
+    let (width, height) = (camera.image_width as usize, camera.image_height as usize);
+    if scene.settings.is_adaptive() {
+        info!("Rendering {}x{} image, {} to {} spp (threshold {}), {} threads",
+              width, height, scene.settings.render_spp, scene.settings.get_max_spp(),
+              scene.settings.adaptive_threshold, scene.settings.get_nb_threads());
+    } else {
+        info!("Rendering {}x{} image, {} spp, {} threads",
+              width, height, scene.settings.render_spp, scene.settings.get_nb_threads());
+    }
+    let result = Arc::new(RtRenderResult::new(width, height));
+    let job = RtRenderJob::start(Arc::new(scene), result.clone());
+    // Progress on a single line on terminals, logged every 10 % otherwise (logs of render nodes, CI)
+    let show_progress = std::io::stderr().is_terminal();
+    let mut next_log_percent = 10.0;
+    while !job.wait_timeout(Duration::from_millis(250)) {
+        let progress = job.progress();
+        if show_progress {
+            eprint!("\r{}   ", progress);
+        } else if progress.percent() >= next_log_percent {
+            info!("{}", progress);
+            next_log_percent = (progress.percent() / 10.0).floor() * 10.0 + 10.0;
+        }
+    }
+    if show_progress {
+        eprintln!();
+    }
+    // The time of a finished job doesn't depend on when it is read
+    let progress = job.progress();
+    if !job.wait() {
+        error!("Render failed");
+        return ExitCode::FAILURE;
+    }
+    info!("Render done in {:.2?}, {} rays ({:.2} Mrays/s)",
+          progress.elapsed, progress.rays_traced, progress.rays_per_second * 1e-6);
+
+    match result.export_aov(&args.output, args.aov) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
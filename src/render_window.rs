@@ -7,13 +7,15 @@
 /// =====================================================
 
 use egui::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use eframe::egui;
-use egui_file_dialog::{FileDialog, DialogState};
+use egui_file_dialog::{FileDialog, DialogMode, DialogState};
 
 use log::*;
 
-use raito::{random_float, random_float_range, RtRenderScene, RtRenderSettings, RT_DEFAULT_WINDOW_HEIGHT, RT_DEFAULT_WINDOW_WIDTH};
+use raito::{RtRng, RtRenderJob, RtRenderSettings, RT_DEFAULT_WINDOW_HEIGHT, RT_DEFAULT_WINDOW_WIDTH};
 use raito::rt_types::*;
 use crate::render_window_params::*;
 use raito::rt_camera::RtCamera;
@@ -37,6 +39,7 @@ const DEFAULT_COLOR: Color32 = Color32::from_rgb(0, 0, 0);
 
 pub fn get_default_scene_0(settings: RtRenderSettings, camera: RtCamera) -> RtScene {
     let mut scene = RtScene::new(settings, camera);
+    let mut rng = RtRng::new(u64::from(settings.seed));
 
     // Ground
     scene.add_shape(Box::new(RtSphere { 
@@ -53,18 +56,18 @@ pub fn get_default_scene_0(settings: RtRenderSettings, camera: RtCamera) -> RtSc
     // Mini spheres
     for a in -11..11 {
         for b in -11..11 {
-            let choose_mat = random_float();
-            let center = RtPoint3::new(a as f32 + 0.9*random_float(), 0.2, b as f32 + 0.9*random_float());
+            let choose_mat = rng.random_float();
+            let center = RtPoint3::new(a as f32 + 0.9*rng.random_float(), 0.2, b as f32 + 0.9*rng.random_float());
             if (center - RtPoint3::new(4.0, 0.2, 0.0)).length() > 0.9 {
                 let sphere_material: Box<dyn RtShader>;
                 if choose_mat < 0.8 { // diffuse
                     sphere_material = Box::new(LambertShader {
-                        color: RtRGBA::random() * RtRGBA::random()
+                        color: RtRGBA::random(&mut rng) * RtRGBA::random(&mut rng)
                     });
                 } else if choose_mat < 0.95 { // metal
                     sphere_material = Box::new(Metal {
-                        color: RtRGBA::random_range(0.5, 1.0),
-                        fuzz: random_float_range(0.0, 0.5)
+                        color: RtRGBA::random_range(&mut rng, 0.5, 1.0),
+                        fuzz: rng.random_float_range(0.0, 0.5)
                     });
                 } else { // glass
                     sphere_material = Box::new(Glass {
@@ -118,6 +121,7 @@ pub fn get_default_scene_0(settings: RtRenderSettings, camera: RtCamera) -> RtSc
         radius: 1.0
     }));
 
+    scene.finalize();
     scene
 }
 
@@ -171,6 +175,7 @@ pub fn get_default_scene_1(settings: RtRenderSettings, camera: RtCamera) -> RtSc
         radius: 1.0
     }));
 
+    scene.finalize();
     scene
 }
 
@@ -183,6 +188,7 @@ pub fn get_default_scene_1(settings: RtRenderSettings, camera: RtCamera) -> RtSc
 enum OpeningFileStatus {
     None,
     ToOpen,
+    ToSave,
     ChoosingFile,
 }
 
@@ -190,9 +196,11 @@ enum OpeningFileStatus {
 pub struct RaitoRenderApp {
     // Parameters
     parameters: RtParameters,
-    // Render Scene
-    scene: Option<RtScene>,
-    result: RtRenderResult,
+    // Render Scene, shared with the render threads
+    scene: Option<Arc<RtScene>>,
+    result: Arc<RtRenderResult>,
+    // Render running in the background
+    render_job: Option<RtRenderJob>,
     // Displayed image
     color_image: ColorImage,
 
@@ -207,8 +215,9 @@ impl Default for RaitoRenderApp {
         Self {
             parameters: RtParameters::default(),
             scene: None,
-            result: RtRenderResult::new(
-                RT_DEFAULT_WINDOW_WIDTH, RT_DEFAULT_WINDOW_HEIGHT),
+            result: Arc::new(RtRenderResult::new(
+                RT_DEFAULT_WINDOW_WIDTH, RT_DEFAULT_WINDOW_HEIGHT)),
+            render_job: None,
             color_image: ColorImage::new(
                 [RT_DEFAULT_WINDOW_WIDTH, RT_DEFAULT_WINDOW_HEIGHT], DEFAULT_COLOR),
             opening_file_status: OpeningFileStatus::None,
@@ -232,107 +241,227 @@ impl RaitoRenderApp {
 
     /// Update the current image cache
     fn update_image(&mut self) {
-        for y in 0..self.result.height {
-            for x in 0..self.result.width {
-                // self.color_image[(x, self.result.height - y - 1)] = self.result.get_pixel_color(x, y);
-                self.color_image[(x, y)] = self.result.get_pixel_color(x, y);
-            }
+        self.parameters.display.fill_image(&self.result, &mut self.color_image);
+    }
+
+    /// Render settings from the UI parameters
+    fn get_settings(&self) -> RtRenderSettings {
+        let mut settings = RtRenderSettings::new(
+            self.parameters.render_spp, self.parameters.max_bounces);
+        settings.diffuse_depth = self.parameters.diffuse_depth;
+        settings.specular_depth = self.parameters.specular_depth;
+        settings.transmission_depth = self.parameters.transmission_depth;
+        settings.rr_depth = self.parameters.rr_depth;
+        settings.bucket_size = self.parameters.bucket_size;
+        settings.threads = self.parameters.threads;
+        settings.width = self.parameters.width;
+        settings.height = self.parameters.height;
+        settings.pixel_aspect = self.parameters.pixel_aspect;
+        settings.direct_lighting = self.parameters.direct_lighting;
+        settings.sampler = self.parameters.sampler;
+        settings.seed = self.parameters.seed;
+        settings.adaptive_threshold = self.parameters.adaptive_threshold;
+        settings.max_spp = self.parameters.max_spp;
+        settings.filter = self.parameters.filter;
+        settings.filter_width = self.parameters.filter_width;
+        settings
+    }
+
+    /// Resize the render result and the displayed image to the scene resolution
+    fn update_resolution(&mut self, width: usize, height: usize) {
+        if self.result.width != width || self.result.height != height {
+            self.result = Arc::new(RtRenderResult::new(width, height));
+            self.color_image = ColorImage::new([width, height], DEFAULT_COLOR);
         }
     }
 
     pub fn setup_default_scene(&mut self) {
-        let settings = RtRenderSettings::new(
-            self.parameters.render_spp, self.parameters.max_bounces);
+        let settings = self.get_settings();
         let camera = RtCamera::new(
-            1.0, 400, self.parameters.camera_fov, 
+            settings.width, settings.height, settings.pixel_aspect,
+            self.parameters.camera_fov, 
             self.parameters.look_from,
             self.parameters.look_at,
-            RtVec3::new(0.0, 1.0, 0.0));
-        self.scene = Some(get_default_scene_0(settings, camera));
+            RtVec3::new(0.0, 1.0, 0.0))
+            .with_projection(self.parameters.camera_projection)
+            .with_lens(self.parameters.lens);
+        self.stop_render_job();
+        self.scene = Some(Arc::new(get_default_scene_0(settings, camera)));
+    }
+
+    /// Write the current render, the format depends on the extension
+    pub fn save_image(&self, path: &Path) -> bool {
+        match self.result.export_image(path) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("{e}");
+                false
+            }
+        }
     }
 
     pub fn open_scene(&mut self, path: PathBuf) -> bool {
-        let xml_scene = open_xml_scene(path.to_str().unwrap());
-        self.scene = xml_scene;
+        self.stop_render_job();
+        let xml_scene = match open_xml_scene(&path.to_string_lossy()) {
+            Ok(xml_scene) => xml_scene,
+            Err(e) => {
+                error!("{e}");
+                return false;
+            }
+        };
+        self.scene = Some(Arc::new(xml_scene));
         
         // Setup UI from scene parameters
         //   render settings
         self.parameters.render_spp = self.scene.as_ref().unwrap().settings.render_spp;
         self.parameters.max_bounces = self.scene.as_ref().unwrap().settings.max_bounces;
+        self.parameters.diffuse_depth = self.scene.as_ref().unwrap().settings.diffuse_depth;
+        self.parameters.specular_depth = self.scene.as_ref().unwrap().settings.specular_depth;
+        self.parameters.transmission_depth = self.scene.as_ref().unwrap().settings.transmission_depth;
+        self.parameters.rr_depth = self.scene.as_ref().unwrap().settings.rr_depth;
+        self.parameters.bucket_size = self.scene.as_ref().unwrap().settings.bucket_size;
+        self.parameters.threads = self.scene.as_ref().unwrap().settings.threads;
+        self.parameters.width = self.scene.as_ref().unwrap().settings.width;
+        self.parameters.height = self.scene.as_ref().unwrap().settings.height;
+        self.parameters.pixel_aspect = self.scene.as_ref().unwrap().settings.pixel_aspect;
+        self.parameters.direct_lighting = self.scene.as_ref().unwrap().settings.direct_lighting;
+        self.parameters.sampler = self.scene.as_ref().unwrap().settings.sampler;
+        self.parameters.seed = self.scene.as_ref().unwrap().settings.seed;
+        self.parameters.adaptive_threshold = self.scene.as_ref().unwrap().settings.adaptive_threshold;
+        self.parameters.max_spp = self.scene.as_ref().unwrap().settings.max_spp;
+        self.parameters.filter = self.scene.as_ref().unwrap().settings.filter;
+        self.parameters.filter_width = self.scene.as_ref().unwrap().settings.filter_width;
         //   camera
         self.parameters.camera_fov = self.scene.as_ref().unwrap().get_camera()._vfov;
         self.parameters.look_from = self.scene.as_ref().unwrap().get_camera()._look_from;
         self.parameters.look_at = self.scene.as_ref().unwrap().get_camera()._look_at;
+        self.parameters.camera_projection = self.scene.as_ref().unwrap().get_camera().projection;
+        self.parameters.lens = self.scene.as_ref().unwrap().get_camera().lens;
 
+        if self.parameters.ipr_enabled {
+            self.restart_ipr();
+        }
         true
     }
 
-    fn update_params(&mut self) {
-        let scene = self.scene.as_mut();
-        if scene.is_none() {
-            // Panic because we shouldn't get here
-            // -> only update if the IPR is launched, i.e. the scene exists
-            panic!("No scene to update !");
-        }
+    /// Scene to edit, the render is stopped first
+    fn scene_mut(&mut self) -> Option<&mut RtScene> {
+        self.stop_render_job();
+        // The render threads are done, the scene isn't shared anymore
+        self.scene.as_mut().and_then(Arc::get_mut)
+    }
 
+    fn update_params(&mut self) {
         // Settings
-        let settings = RtRenderSettings::new(
-            self.parameters.render_spp, self.parameters.max_bounces);
-            scene.unwrap().set_settings(settings);
+        let settings = self.get_settings();
 
         // Camera
         let camera = RtCamera::new(
-            1.0, 400, self.parameters.camera_fov, 
+            settings.width, settings.height, settings.pixel_aspect,
+            self.parameters.camera_fov, 
             self.parameters.look_from,
-            RtPoint3::new(0.0, 0.0, 0.0), 
-            RtVec3::new(0.0, 1.0, 0.0));
-        let scene = self.scene.as_mut();
-        scene.unwrap().set_camera(camera);
+            self.parameters.look_at, 
+            RtVec3::new(0.0, 1.0, 0.0))
+            .with_projection(self.parameters.camera_projection)
+            .with_lens(self.parameters.lens);
+
+        let Some(scene) = self.scene_mut() else {
+            // Panic because we shouldn't get here
+            // -> only update if the IPR is launched, i.e. the scene exists
+            panic!("No scene to update !");
+        };
+        scene.set_settings(settings);
+        scene.set_camera(camera);
 
         // TODO : update other things ?
     }
 
-    fn render(&mut self) -> bool {
-        let scene = self.scene.as_ref();
-        if scene.is_some() {
-            RtRenderScene(scene.unwrap(), &mut self.result);
+    /// Prepare the scene and the result for a render, 
+    /// returns the scene to render
+    fn setup_render(&mut self) -> Option<Arc<RtScene>> {
+        if self.scene.is_none() {
+            error!("No scene loaded to render !");
+            self.setup_default_scene();
         } else {
+            self.update_params();
+        }
+        let Some(scene) = self.scene.clone() else {
             error!("No scene to render !");
             self.parameters.ipr_enabled = false;  // Make sure to disable IPR
-            return false;
-        }
-        self.update_image();
-        true
+            return None;
+        };
+        let camera = scene.get_camera();
+        let (width, height) = (usize::from(camera.image_width), usize::from(camera.image_height));
+        self.update_resolution(width, height);
+        Some(scene)
     }
 
-    /// Start the render
+    /// Start the render in the background
     pub fn launch_render(&mut self) {
-        // Setup scene
-        if self.scene.is_none() {
-            error!("No scene loaded to render !");
-            self.setup_default_scene();
-            // return;
-        } else {
-            self.update_params();
+        if self.parameters.ipr_enabled {
+            info!("> Stopping IPR");
+            self.parameters.ipr_enabled = false;
         }
-        
-        // Launch render
+        let Some(scene) = self.setup_render() else {
+            return;
+        };
         info!("Starting render");
-        let now = std::time::Instant::now();
-        if self.render() {
-            info!("Render finished in {} sec", now.elapsed().as_secs_f64());
-        }  // Else : error happened
+        self.render_job = Some(RtRenderJob::start(scene, self.result.clone()));
+    }
+
+    /// Cancel the render, keeping the samples already rendered
+    fn stop_render_job(&mut self) {
+        if let Some(job) = self.render_job.take() {
+            job.stop();
+        }
+    }
+
+    /// Stop button : cancel the render and the IPR
+    pub fn stop_render(&mut self) {
+        info!("> Render stopped");
+        self.parameters.ipr_enabled = false;
+        self.stop_render_job();
+        self.update_image();
+    }
+
+    /// Restart the progressive render of the IPR from the current parameters
+    fn restart_ipr(&mut self) {
+        let Some(scene) = self.setup_render() else {
+            return;
+        };
+        self.render_job = Some(RtRenderJob::start_progressive(scene, self.result.clone()));
     }
 
-    /// Stops the render
+    /// Refresh the image while a render runs, returns true while it is running
+    fn update_render_job(&mut self) -> bool {
+        let Some(job) = self.render_job.as_ref() else {
+            return false;
+        };
+        let finished = job.is_finished();
+        let progress = job.progress();
+        self.update_image();
+        if finished {
+            if let Some(job) = self.render_job.take() {
+                if job.wait() {
+                    info!("Render finished in {:.2?}, {} rays ({:.2} Mrays/s)", 
+                        progress.elapsed, progress.rays_traced, progress.rays_per_second * 1e-6);
+                }
+            }
+        }
+        !finished
+    }
+
+    /// Starts or stops the IPR
     pub fn toggle_ipr(&mut self) {
         if self.parameters.ipr_enabled {
             info!("> Stopping IPR");
             self.parameters.ipr_enabled = false;
+            self.stop_render_job();
+            self.update_image();
         } else {
             info!("> Starting IPR");
             self.parameters.ipr_enabled = true;
-            self.launch_render();
+            self.restart_ipr();
         }
     }
 }
@@ -363,18 +492,27 @@ fn main_ui(app: &mut RaitoRenderApp, ui: &mut Ui, ctx: &egui::Context) {
                 ImageData::from(app.color_image.clone()),
                 Default::default()
             );
-            ui.add(egui::Image::new(&img));
+            // Fit the image in the render view, keeping its aspect ratio
+            let pixel_aspect = app.scene.as_ref().map_or(1.0, |scene| scene.settings.pixel_aspect);
+            let image_width = app.color_image.width() as f32 * pixel_aspect;
+            let image_height = app.color_image.height() as f32;
+            let scale = (RT_DEFAULT_WINDOW_WIDTH as f32 / image_width)
+                .min(RT_DEFAULT_WINDOW_HEIGHT as f32 / image_height);
+            ui.add(egui::Image::new(&img)
+                .fit_to_exact_size(Vec2::new(image_width * scale, image_height * scale)));
             // info!("Image display took : {} sec", now.elapsed().as_secs_f64());
         });
 
         // Parameters
         let mut updated = false;
+        let mut display_updated = false;
         ui.vertical(|ui| {
-            setup_params_ui(ui, &mut app.parameters, &mut updated);
+            setup_params_ui(ui, &mut app.parameters, &mut updated, &mut display_updated);
         });
         if updated && app.parameters.ipr_enabled {
-            app.update_params();
-            app.render();
+            app.restart_ipr();
+        } else if display_updated {
+            app.update_image();
         }
     });
 }
@@ -387,20 +525,48 @@ impl eframe::App for RaitoRenderApp {
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Show the samples as they are rendered
+        if self.update_render_job() {
+            ctx.request_repaint_after(Duration::from_millis(50));
+        }
+
+        let mut stop_clicked = false;
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open Scene").clicked() {
                         self.opening_file_status = OpeningFileStatus::ToOpen;
                     }
+                    if ui.button("Save image").clicked() {
+                        self.opening_file_status = OpeningFileStatus::ToSave;
+                    }
                     if ui.button("Exit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
                 });
                 ui.add_space(16.0);
                 egui::widgets::global_dark_light_mode_buttons(ui);
+                if let Some(job) = self.render_job.as_ref() {
+                    let progress = job.progress();
+                    let text = if progress.nb_passes > 1 {
+                        format!("{} / {} samples | {:.2} Mrays/s", 
+                            progress.passes_done, progress.nb_passes, progress.rays_per_second * 1e-6)
+                    } else {
+                        let eta = progress.eta.map_or(String::from("-"), |eta| format!("{:.0?}", eta));
+                        format!("{:.0} % | ETA {} | {:.2} Mrays/s", 
+                            progress.percent(), eta, progress.rays_per_second * 1e-6)
+                    };
+                    ui.add_space(16.0);
+                    ui.add(ProgressBar::new(progress.fraction).desired_width(320.0).text(text));
+                    if ui.button("Stop").clicked() {
+                        stop_clicked = true;
+                    }
+                }
             });
         });
+        if stop_clicked {
+            self.stop_render();
+        }
 
         CentralPanel::default().show(ctx, |ui| {
             if self.opening_file_status == OpeningFileStatus::None {
@@ -415,6 +581,10 @@ impl eframe::App for RaitoRenderApp {
                     self.file_dialog.select_file();
                     self.opening_file_status = OpeningFileStatus::ChoosingFile;
                 }
+                if self.opening_file_status == OpeningFileStatus::ToSave {
+                    self.file_dialog.save_file();
+                    self.opening_file_status = OpeningFileStatus::ChoosingFile;
+                }
 
                 if self.file_dialog.state() == DialogState::Cancelled || 
                    self.file_dialog.state() == DialogState::Closed {
@@ -424,7 +594,11 @@ impl eframe::App for RaitoRenderApp {
                 // Update the dialog and check if the user selected a file
                 if let Some(path) = self.file_dialog.update(ctx).selected() {
                     let selected_file = path.to_path_buf();
-                    self.open_scene(selected_file);
+                    if self.file_dialog.mode() == DialogMode::SaveFile {
+                        self.save_image(&selected_file);
+                    } else {
+                        self.open_scene(selected_file);
+                    }
                     self.opening_file_status = OpeningFileStatus::None;
                 }
             }
@@ -6,7 +6,7 @@
 /// =====================================================
 
 use egui::*;
-use raito::RtPoint3;
+use raito::{RtPoint3, RtSamplerType, RtFilterType, RtLens, RtProjection, RtDirectLighting, RtDisplaySettings, RtViewTransform, RtAov, RT_DEFAULT_BUCKET_SIZE, RT_DEFAULT_IMAGE_WIDTH, RT_DEFAULT_IMAGE_HEIGHT, RT_DEFAULT_RR_DEPTH};
 
 
 fn DragFloatWidget(ui: &mut egui::Ui, updated: &mut bool,
@@ -39,11 +39,31 @@ pub struct RtParameters {
     pub ipr_enabled: bool,
     // Render settings
     pub render_spp: u8,
-    pub max_bounces: u8,
+    pub max_bounces: u16,
+    pub diffuse_depth: u16,
+    pub specular_depth: u16,
+    pub transmission_depth: u16,
+    pub rr_depth: u16,
+    pub bucket_size: u16,
+    pub threads: usize,
+    pub width: u16,
+    pub height: u16,
+    pub pixel_aspect: f32,
+    pub direct_lighting: RtDirectLighting,
+    pub sampler: RtSamplerType,
+    pub seed: u32,
+    pub adaptive_threshold: f32,
+    pub max_spp: u16,
+    pub filter: RtFilterType,
+    pub filter_width: f32,
+    // Display settings
+    pub display: RtDisplaySettings,
     // Camera params
+    pub camera_projection: RtProjection,
     pub camera_fov: f32,
     pub look_from: RtPoint3,
     pub look_at: RtPoint3,
+    pub lens: RtLens,
     // Light params
     pub light_position: RtPoint3,
     pub light_radius: f32,
@@ -63,11 +83,31 @@ impl Default for RtParameters {
             // Render settings
             render_spp: 3,
             max_bounces: 3,
+            diffuse_depth: 3,
+            specular_depth: 3,
+            transmission_depth: 3,
+            rr_depth: RT_DEFAULT_RR_DEPTH,
+            bucket_size: RT_DEFAULT_BUCKET_SIZE,
+            threads: 0,
+            width: RT_DEFAULT_IMAGE_WIDTH,
+            height: RT_DEFAULT_IMAGE_HEIGHT,
+            pixel_aspect: 1.0,
+            direct_lighting: RtDirectLighting::default(),
+            sampler: RtSamplerType::default(),
+            seed: 0,
+            adaptive_threshold: 0.0,
+            max_spp: 64,
+            filter: RtFilterType::default(),
+            filter_width: RtFilterType::default().default_width(),
+            // Display settings
+            display: RtDisplaySettings::default(),
             // Camera params
             // camera_fov: 47.0,
             camera_fov: 20.0,
+            camera_projection: RtProjection::default(),
             look_from: RtPoint3::new(13.0, 2.0, 3.0),
             look_at: RtPoint3::new(0.0, 0.0, 0.0),
+            lens: RtLens::default(),
             // Light params
             light_position: RtPoint3::new(0.0, 2.0, 0.0),
             light_radius: 1.0,
@@ -89,15 +129,203 @@ fn render_settings_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mu
     }
     ui.end_row();
     
+    ui.label("Adaptive threshold (0: off)");
+    if ui.add(DragValue::new(&mut params.adaptive_threshold)
+        .speed(0.001)
+        .min_decimals(3)
+        .clamp_range(0.0..=1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Adaptive max SPP");
+    if ui.add(egui::Slider::new(&mut params.max_spp, 1..=1024)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
     ui.label("Maximum bounces");
     if ui.add(egui::Slider::new(&mut params.max_bounces, 0..=100)
     .drag_value_speed(1.0)).changed() {
         *updated = true
     }
     ui.end_row();
+
+    ui.label("Diffuse depth");
+    if ui.add(egui::Slider::new(&mut params.diffuse_depth, 0..=100)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Specular depth");
+    if ui.add(egui::Slider::new(&mut params.specular_depth, 0..=100)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Transmission depth");
+    if ui.add(egui::Slider::new(&mut params.transmission_depth, 0..=100)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Russian roulette depth");
+    if ui.add(egui::Slider::new(&mut params.rr_depth, 0..=100)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Direct lighting");
+    egui::ComboBox::from_id_source("direct_lighting")
+        .selected_text(params.direct_lighting.name())
+        .show_ui(ui, |ui| {
+            for strategy in RtDirectLighting::ALL {
+                if ui.selectable_value(&mut params.direct_lighting, strategy, strategy.name())
+                    .changed() {
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
+    ui.label("Sampler");
+    egui::ComboBox::from_id_source("sampler")
+        .selected_text(params.sampler.name())
+        .show_ui(ui, |ui| {
+            for sampler in RtSamplerType::ALL {
+                if ui.selectable_value(&mut params.sampler, sampler, sampler.name())
+                    .changed() {
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
+    ui.label("Filter");
+    egui::ComboBox::from_id_source("filter")
+        .selected_text(params.filter.name())
+        .show_ui(ui, |ui| {
+            for filter in RtFilterType::ALL {
+                if ui.selectable_value(&mut params.filter, filter, filter.name())
+                    .changed() {
+                    params.filter_width = filter.default_width();
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
+    ui.label("Filter width");
+    if ui.add(DragValue::new(&mut params.filter_width)
+        .speed(0.05)
+        .min_decimals(1)
+        .clamp_range(0.5..=8.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Seed");
+    if ui.add(DragValue::new(&mut params.seed)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Bucket size");
+    if ui.add(egui::Slider::new(&mut params.bucket_size, 8..=128)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Threads (0: auto)");
+    if ui.add(egui::Slider::new(&mut params.threads, 0..=64)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Resolution");
+    ui.horizontal(|ui| {
+        if ui.add(DragValue::new(&mut params.width).clamp_range(1..=8192)).changed() {
+            *updated = true
+        }
+        ui.label("x");
+        if ui.add(DragValue::new(&mut params.height).clamp_range(1..=8192)).changed() {
+            *updated = true
+        }
+    });
+    ui.end_row();
+
+    ui.label("Pixel aspect");
+    if ui.add(DragValue::new(&mut params.pixel_aspect)
+        .speed(0.01)
+        .min_decimals(2)
+        .clamp_range(0.1..=10.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+}
+
+fn display_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
+    ui.label("Exposure");
+    if ui.add(egui::Slider::new(&mut params.display.exposure, -10.0..=10.0)
+    .drag_value_speed(0.1)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("View transform");
+    egui::ComboBox::from_id_source("view_transform")
+        .selected_text(params.display.view_transform.name())
+        .show_ui(ui, |ui| {
+            for transform in RtViewTransform::ALL {
+                if ui.selectable_value(&mut params.display.view_transform, transform, transform.name())
+                    .changed() {
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
+    ui.label("AOV");
+    egui::ComboBox::from_id_source("aov")
+        .selected_text(params.display.aov.name())
+        .show_ui(ui, |ui| {
+            for aov in RtAov::ALL {
+                if ui.selectable_value(&mut params.display.aov, aov, aov.name())
+                    .changed() {
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
+    ui.label("Dither");
+    if ui.checkbox(&mut params.display.dither, "").changed() {
+        *updated = true
+    }
+    ui.end_row();
 }
 
 fn camera_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
+    ui.label("Projection");
+    egui::ComboBox::from_id_source("camera_projection")
+        .selected_text(params.camera_projection.name())
+        .show_ui(ui, |ui| {
+            for projection in RtProjection::ALL {
+                if ui.selectable_value(&mut params.camera_projection, projection, projection.name())
+                    .changed() {
+                    *updated = true
+                }
+            }
+        });
+    ui.end_row();
+
     ui.label("FOV");
     // 20~=250mm, 150~=6mm
     if ui.add(egui::Slider::new(&mut params.camera_fov, 10.0..=100.0)
@@ -123,6 +351,38 @@ fn camera_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
         &mut params.look_at.z, 
     );
     ui.end_row();
+
+    ui.label("Aperture size");
+    if ui.add(DragValue::new(&mut params.lens.aperture_size)
+        .speed(0.01)
+        .min_decimals(2)
+        .clamp_range(0.0..=10.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Focus distance (0: look at)");
+    if ui.add(DragValue::new(&mut params.lens.focus_distance)
+        .speed(0.1)
+        .min_decimals(2)
+        .clamp_range(0.0..=1000.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Aperture blades");
+    if ui.add(egui::Slider::new(&mut params.lens.aperture_blades, 0..=16)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
+
+    ui.label("Aperture rotation");
+    if ui.add(egui::Slider::new(&mut params.lens.aperture_rotation, 0.0..=360.0)
+    .drag_value_speed(1.0)).changed() {
+        *updated = true
+    }
+    ui.end_row();
 }
 
 fn light_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
@@ -236,7 +496,10 @@ fn sphere_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
 }
 
 
-pub fn setup_params_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &mut bool) {
+/// Display settings only need the image to be refreshed, so they 
+/// set `display_updated` instead of `updated`
+pub fn setup_params_ui(ui: &mut egui::Ui, params: &mut RtParameters, 
+                       updated: &mut bool, display_updated: &mut bool) {
     egui::CollapsingHeader::new("Render settings")
         .default_open(true)
         .show(ui, |ui| {
@@ -247,6 +510,16 @@ pub fn setup_params_ui(ui: &mut egui::Ui, params: &mut RtParameters, updated: &m
             .show(ui, |ui| render_settings_ui(ui, params, updated));
     });
 
+    egui::CollapsingHeader::new("Display")
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new("display_settings")
+            .num_columns(2)
+            .spacing([12.0, 8.0])
+            .striped(true)
+            .show(ui, |ui| display_ui(ui, params, display_updated));
+    });
+
     egui::CollapsingHeader::new("Camera Parameters")
         .default_open(true)
         .show(ui, |ui| {
@@ -0,0 +1,421 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Bounding volume hierarchy, built with the surface
+///   area heuristic (SAH). It only knows the bounding
+///   boxes of the primitives, so it is used both for the
+///   objects of the scene and the triangles of a mesh.
+/// =====================================================
+
+use crate::rt_types::*;
+use crate::rt_ray::*;
+
+/// Number of bins used to evaluate the SAH
+const NB_BINS: usize = 12;
+/// Maximum number of primitives in a leaf
+const MAX_LEAF_SIZE: usize = 4;
+/// Relative cost of traversing a node compared to intersecting a primitive
+const TRAVERSAL_COST: f32 = 0.5;
+/// Maximum depth of the tree (size of the traversal stack)
+const MAX_DEPTH: usize = 64;
+
+
+// ========================================
+//  BVH nodes
+// ========================================
+
+/// Node of the flattened tree
+///
+/// The first child of an interior node is right after it,
+/// `offset` is the index of the second child.
+/// For leaves, `offset` is the index of the first primitive
+/// and `count` the number of primitives.
+#[derive(Debug, Clone)]
+struct RtBvhNode {
+    bbox: RtAABB,
+    offset: usize,
+    count: usize,
+    axis: usize
+}
+
+impl RtBvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Primitive info used during the build
+struct RtBvhPrimitive {
+    index: usize,
+    bbox: RtAABB,
+    centroid: RtPoint3
+}
+
+#[derive(Clone, Copy)]
+struct RtBvhBin {
+    bbox: RtAABB,
+    count: usize
+}
+
+
+// ========================================
+//  BVH
+// ========================================
+
+/// Bounding volume hierarchy over a list of primitives
+#[derive(Debug, Clone, Default)]
+pub struct RtBvh {
+    nodes: Vec<RtBvhNode>,
+    /// Primitive indices, ordered by leaves
+    prim_indices: Vec<usize>,
+}
+
+impl RtBvh {
+    /// Build the hierarchy from the bounding boxes of the primitives.
+    ///
+    /// Primitives are then referred to by their index in `bboxes`.
+    /// Primitives with empty bounding boxes are skipped.
+    pub fn build(bboxes: &[RtAABB]) -> Self {
+        let mut primitives: Vec<RtBvhPrimitive> = bboxes.iter()
+            .enumerate()
+            .filter(|(_, bbox)| !bbox.is_empty())
+            .map(|(index, bbox)| RtBvhPrimitive { index, bbox: *bbox, centroid: bbox.centroid() })
+            .collect();
+
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            prim_indices: Vec::with_capacity(primitives.len())
+        };
+        if !primitives.is_empty() {
+            bvh.build_recursive(&mut primitives, 0);
+        }
+        bvh
+    }
+
+    /// Number of nodes in the tree
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Bounding box of all the primitives
+    pub fn bounding_box(&self) -> RtAABB {
+        self.nodes.first().map(|node| node.bbox).unwrap_or(RtAABB::EMPTY)
+    }
+
+    fn make_leaf(&mut self, primitives: &[RtBvhPrimitive], bbox: RtAABB) -> usize {
+        self.nodes.push(RtBvhNode { bbox, offset: self.prim_indices.len(), count: primitives.len(), axis: 0 });
+        self.prim_indices.extend(primitives.iter().map(|p| p.index));
+        self.nodes.len() - 1
+    }
+
+    /// Returns the index of the created node
+    fn build_recursive(&mut self, primitives: &mut [RtBvhPrimitive], depth: usize) -> usize {
+        let bbox = primitives.iter().fold(RtAABB::EMPTY, |b, p| b.union(&p.bbox));
+        if primitives.len() <= MAX_LEAF_SIZE || depth >= MAX_DEPTH - 1 {
+            return self.make_leaf(primitives, bbox)
+        }
+
+        // Split along the largest axis of the centroids
+        let centroid_bbox = primitives.iter().fold(RtAABB::EMPTY, |b, p| b.union_point(&p.centroid));
+        let axis = centroid_bbox.max_extent();
+        let axis_min = centroid_bbox.min[axis];
+        let axis_extent = centroid_bbox.max[axis] - axis_min;
+        if axis_extent <= 0.0 {
+            // All centroids are at the same place
+            return self.make_leaf(primitives, bbox)
+        }
+
+        // Put the primitives in bins
+        let bin_index = |p: &RtBvhPrimitive| -> usize {
+            let b = (NB_BINS as f32 * (p.centroid[axis] - axis_min) / axis_extent) as usize;
+            b.min(NB_BINS - 1)
+        };
+        let mut bins = [RtBvhBin { bbox: RtAABB::EMPTY, count: 0 }; NB_BINS];
+        for p in primitives.iter() {
+            let bin = &mut bins[bin_index(p)];
+            bin.bbox = bin.bbox.union(&p.bbox);
+            bin.count += 1;
+        }
+
+        // Evaluate the SAH cost of splitting after each bin
+        let mut costs = [0.0_f32; NB_BINS - 1];
+        for (split, cost) in costs.iter_mut().enumerate() {
+            let (left, right) = bins.split_at(split + 1);
+            let merge = |bins: &[RtBvhBin]| bins.iter().fold((RtAABB::EMPTY, 0), |(b, c), bin| (b.union(&bin.bbox), c + bin.count));
+            let (left_bbox, left_count) = merge(left);
+            let (right_bbox, right_count) = merge(right);
+            *cost = TRAVERSAL_COST +
+                (left_count as f32 * left_bbox.surface_area() + right_count as f32 * right_bbox.surface_area())
+                / bbox.surface_area();
+        }
+        let (best_split, best_cost) = costs.iter()
+            .enumerate()
+            .fold((0, f32::INFINITY), |best, (i, &c)| if c < best.1 { (i, c) } else { best });
+
+        // Keep a leaf if splitting is more expensive
+        let leaf_cost = primitives.len() as f32;
+        if best_cost >= leaf_cost && primitives.len() <= 2 * MAX_LEAF_SIZE {
+            return self.make_leaf(primitives, bbox)
+        }
+
+        // Partition the primitives
+        let mut mid = 0;
+        for i in 0..primitives.len() {
+            if bin_index(&primitives[i]) <= best_split {
+                primitives.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == primitives.len() {
+            // Degenerated split : cut in the middle
+            mid = primitives.len() / 2;
+            primitives.sort_by(|a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+        }
+
+        // Create the node, then its children
+        let node_index = self.nodes.len();
+        self.nodes.push(RtBvhNode { bbox, offset: 0, count: 0, axis });
+        let (left, right) = primitives.split_at_mut(mid);
+        self.build_recursive(left, depth + 1);
+        self.nodes[node_index].offset = self.build_recursive(right, depth + 1);
+        node_index
+    }
+
+    /// Closest-hit traversal
+    ///
+    /// `intersect(prim_index, max_dist)` must return the distance to
+    /// the primitive if it is hit before `max_dist`.
+    /// Returns the closest primitive hit and its distance.
+    pub fn closest_hit<F>(&self, ray: &RtRay, max_dist: f32, mut intersect: F) -> Option<(usize, f32)>
+        where F: FnMut(usize, f32) -> Option<f32>
+    {
+        if self.nodes.is_empty() {
+            return None
+        }
+        let inv_dir = RtVec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+        let dir_is_neg = [ray.dir.x < 0.0, ray.dir.y < 0.0, ray.dir.z < 0.0];
+
+        let mut closest: Option<(usize, f32)> = None;
+        let mut max_dist = max_dist;
+        let mut stack = [0_usize; MAX_DEPTH];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size];
+            let node = &self.nodes[node_index];
+            if node.bbox.intersect(&ray.origin, &inv_dir, max_dist).is_none() {
+                continue;
+            }
+            if node.is_leaf() {
+                for &prim_index in &self.prim_indices[node.offset..node.offset + node.count] {
+                    if let Some(dist) = intersect(prim_index, max_dist) {
+                        if dist < max_dist {
+                            max_dist = dist;
+                            closest = Some((prim_index, dist));
+                        }
+                    }
+                }
+            } else {
+                // Visit the nearest child first
+                let (first, second) = if dir_is_neg[node.axis] {
+                    (node.offset, node_index + 1)
+                } else {
+                    (node_index + 1, node.offset)
+                };
+                stack[stack_size] = second;
+                stack[stack_size + 1] = first;
+                stack_size += 2;
+            }
+        }
+        closest
+    }
+
+    /// Any-hit traversal, for occlusion tests
+    ///
+    /// `occluded(prim_index, max_dist)` must return true if the
+    /// primitive is hit before `max_dist`.
+    pub fn any_hit<F>(&self, ray: &RtRay, max_dist: f32, mut occluded: F) -> bool
+        where F: FnMut(usize, f32) -> bool
+    {
+        if self.nodes.is_empty() {
+            return false
+        }
+        let inv_dir = RtVec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+
+        let mut stack = [0_usize; MAX_DEPTH];
+        let mut stack_size = 1;
+        while stack_size > 0 {
+            stack_size -= 1;
+            let node_index = stack[stack_size];
+            let node = &self.nodes[node_index];
+            if node.bbox.intersect(&ray.origin, &inv_dir, max_dist).is_none() {
+                continue;
+            }
+            if node.is_leaf() {
+                let prims = &self.prim_indices[node.offset..node.offset + node.count];
+                if prims.iter().any(|&prim_index| occluded(prim_index, max_dist)) {
+                    return true
+                }
+            } else {
+                stack[stack_size] = node.offset;
+                stack[stack_size + 1] = node_index + 1;
+                stack_size += 2;
+            }
+        }
+        false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small xorshift generator, to get the same boxes on every run
+    struct TestRng(u32);
+
+    impl TestRng {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+
+        fn range(&mut self, min: f32, max: f32) -> f32 {
+            min + (max - min) * self.next()
+        }
+
+        fn point(&mut self, min: f32, max: f32) -> RtPoint3 {
+            RtPoint3::new(self.range(min, max), self.range(min, max), self.range(min, max))
+        }
+
+        fn vector(&mut self, min: f32, max: f32) -> RtVec3 {
+            RtVec3::new(self.range(min, max), self.range(min, max), self.range(min, max))
+        }
+    }
+
+    /// Copy of the point with one coordinate changed
+    fn with_axis(p: RtPoint3, axis: usize, value: f32) -> RtPoint3 {
+        let mut coords = [p.x, p.y, p.z];
+        coords[axis] = value;
+        RtPoint3::new(coords[0], coords[1], coords[2])
+    }
+
+    fn make_ray(origin: RtPoint3, dir: RtVec3) -> RtRay {
+        RtRay { origin, dir, bounces: 0, x: 0, y: 0, si: 0 }
+    }
+
+    fn random_boxes(rng: &mut TestRng, count: usize) -> Vec<RtAABB> {
+        (0..count).map(|_| {
+            let center = rng.point(-10.0, 10.0);
+            let half_size = rng.vector(0.01, 1.0);
+            RtAABB::new(center + -half_size, center + half_size)
+        }).collect()
+    }
+
+    /// Random rays through the scene, and rays along the axes
+    fn test_rays(rng: &mut TestRng) -> Vec<RtRay> {
+        let mut rays: Vec<RtRay> = (0..500).map(|_| {
+            let origin = rng.point(-15.0, 15.0);
+            let target = rng.point(-10.0, 10.0);
+            make_ray(origin, (target - origin).normalize())
+        }).collect();
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                let dir = with_axis(RtPoint3::new(0.0, 0.0, 0.0), axis, sign) - RtPoint3::new(0.0, 0.0, 0.0);
+                for _ in 0..50 {
+                    let origin = with_axis(rng.point(-10.0, 10.0), axis, -15.0 * sign);
+                    rays.push(make_ray(origin, dir));
+                }
+            }
+        }
+        rays
+    }
+
+    /// Check the traversals of the BVH against a loop over all the boxes
+    fn check_against_brute_force(bboxes: &[RtAABB], rng: &mut TestRng) {
+        let bvh = RtBvh::build(bboxes);
+        for ray in test_rays(rng) {
+            let inv_dir = RtVec3::new(1.0 / ray.dir.x, 1.0 / ray.dir.y, 1.0 / ray.dir.z);
+            let intersect = |index: usize, max_dist: f32| bboxes[index].intersect(&ray.origin, &inv_dir, max_dist);
+            for max_dist in [f32::INFINITY, rng.range(0.0, 30.0)] {
+                let expected = (0..bboxes.len())
+                    .filter_map(|index| intersect(index, max_dist))
+                    .fold(None, |closest: Option<f32>, dist| Some(closest.map_or(dist, |c| c.min(dist))));
+
+                let closest = bvh.closest_hit(&ray, max_dist, intersect);
+                // Several boxes can be at the same distance, only compare the distances
+                assert_eq!(closest.map(|(_, dist)| dist), expected, "closest hit of {ray}");
+                if let Some((index, dist)) = closest {
+                    assert_eq!(intersect(index, max_dist), Some(dist));
+                }
+
+                let any_hit = bvh.any_hit(&ray, max_dist, |index, max_dist| intersect(index, max_dist).is_some());
+                assert_eq!(any_hit, expected.is_some(), "any hit of {ray}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_boxes_match_brute_force() {
+        let mut rng = TestRng(0x1234_5678);
+        for count in [1, 3, 10, 100, 1000] {
+            let bboxes = random_boxes(&mut rng, count);
+            check_against_brute_force(&bboxes, &mut rng);
+        }
+    }
+
+    #[test]
+    fn same_centroids_match_brute_force() {
+        let mut rng = TestRng(0x9e37_79b9);
+        let center = RtPoint3::new(1.0, -2.0, 0.5);
+        let bboxes: Vec<RtAABB> = (0..200).map(|_| {
+            let half_size = rng.vector(0.01, 8.0);
+            RtAABB::new(center + -half_size, center + half_size)
+        }).collect();
+        check_against_brute_force(&bboxes, &mut rng);
+
+        // The same box many times
+        let bboxes = vec![RtAABB::new(center + RtVec3::new(-1.0, -1.0, -1.0), center + RtVec3::new(1.0, 1.0, 1.0)); 100];
+        check_against_brute_force(&bboxes, &mut rng);
+    }
+
+    #[test]
+    fn flat_boxes_match_brute_force() {
+        let mut rng = TestRng(0x0bad_cafe);
+        for axis in 0..3 {
+            // Flat along one axis, in random planes and all in the same plane
+            let mut bboxes = random_boxes(&mut rng, 300);
+            for (i, bbox) in bboxes.iter_mut().enumerate() {
+                let plane = if i % 2 == 0 { bbox.min[axis] } else { 0.0 };
+                bbox.min = with_axis(bbox.min, axis, plane);
+                bbox.max = with_axis(bbox.max, axis, plane);
+            }
+            check_against_brute_force(&bboxes, &mut rng);
+            let in_plane: Vec<RtAABB> = bboxes.iter().skip(1).step_by(2).copied().collect();
+            check_against_brute_force(&in_plane, &mut rng);
+        }
+
+        // Flat along two axes (segments) and all axes (points)
+        let mut bboxes = random_boxes(&mut rng, 200);
+        for (i, bbox) in bboxes.iter_mut().enumerate() {
+            bbox.max.x = bbox.min.x;
+            bbox.max.y = bbox.min.y;
+            if i % 2 == 0 {
+                bbox.max.z = bbox.min.z;
+            }
+        }
+        check_against_brute_force(&bboxes, &mut rng);
+    }
+
+    #[test]
+    fn empty_bvh() {
+        let bvh = RtBvh::build(&[RtAABB::EMPTY]);
+        let ray = make_ray(RtPoint3::new(0.0, 0.0, 0.0), RtVec3::new(0.0, 0.0, 1.0));
+        assert_eq!(bvh.num_nodes(), 0);
+        assert!(bvh.closest_hit(&ray, f32::INFINITY, |_, _| Some(1.0)).is_none());
+        assert!(!bvh.any_hit(&ray, f32::INFINITY, |_, _| true));
+    }
+}
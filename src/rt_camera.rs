@@ -10,21 +10,83 @@ use crate::rt_ray::*;
 use crate::rt_shader_globals::*;
 use crate::rt_sampler::*;
 
+/// Thin lens of a camera, a null aperture gives a pinhole camera
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RtLens {
+    /// Radius of the aperture, in scene units
+    pub aperture_size: f32,
+    /// Distance of the plane in focus, 0 to focus on the "look at" point
+    pub focus_distance: f32,
+    /// Number of aperture blades for polygonal bokeh, round aperture below 3
+    pub aperture_blades: u16,
+    /// Rotation of the aperture blades in degrees
+    pub aperture_rotation: f32,
+}
+
+/// Projection of the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtProjection {
+    /// Pinhole or thin lens camera
+    #[default]
+    Perspective,
+    /// Parallel rays, the image has the size of the perspective 
+    /// view on the plane in focus
+    Orthographic,
+    /// Equidistant fisheye, the field of view spans the circle 
+    /// inscribed in the image
+    Fisheye,
+    /// Latitude-longitude panorama of the whole sphere
+    Equirectangular,
+}
+
+impl RtProjection {
+    pub const ALL: [RtProjection; 4] = [
+        Self::Perspective, Self::Orthographic, Self::Fisheye, Self::Equirectangular];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Perspective => "perspective",
+            Self::Orthographic => "orthographic",
+            Self::Fisheye => "fisheye",
+            Self::Equirectangular => "equirectangular",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|projection| projection.name() == name)
+    }
+}
+
 /// Describes a camera
 /// 
 /// Right handed system
 pub struct RtCamera {
     pub image_width : u16, 
     pub image_height: u16,
+    /// Width of a pixel divided by its height
+    pub pixel_aspect: f32,
+    pub projection: RtProjection,
+    pub lens: RtLens,
     center: RtPoint3,
+    /// Camera frame : right, up and backwards
+    u: RtVec3,
+    v: RtVec3,
+    w: RtVec3,
+    /// Distance of the viewport
+    focus_distance: f32,
     pixel00_loc: RtPoint3,
     pixel_delta_u: RtVec3,
     pixel_delta_v: RtVec3,
+    /// Aperture radius along the horizontal and vertical axes
+    lens_delta_u: RtVec3,
+    lens_delta_v: RtVec3,
 
     // Additional parameters that are not used
     pub _vfov: f32,
     pub _look_from: RtPoint3,
     pub _look_at: RtPoint3,
+    pub _vup: RtVec3,
 }
 
 fn degrees_to_radians(degrees: f32) -> f32 {
@@ -32,63 +94,174 @@ fn degrees_to_radians(degrees: f32) -> f32 {
 }
 
 impl RtCamera {
-    /// Creates a new camera
-    pub fn new(aspect_ratio: f32, image_width: u16, vfov: f32, 
+    /// Creates a new pinhole camera
+    /// 
+    /// `vfov` is the vertical field of view in degrees, the horizontal 
+    /// one follows the image and pixel aspect ratios.
+    pub fn new(image_width: u16, image_height: u16, pixel_aspect: f32, vfov: f32, 
                lookfrom: RtPoint3, lookat: RtPoint3, vup: RtVec3) -> Self {
-        let image_height = (image_width as f32 / aspect_ratio) as u16;
-        let image_height = if image_height < 1 { 1 } else { image_height };
+        let mut camera = Self {
+            image_width: image_width.max(1), 
+            image_height: image_height.max(1), 
+            pixel_aspect: if pixel_aspect > 0.0 { pixel_aspect } else { 1.0 },
+            projection: RtProjection::default(),
+            lens: RtLens::default(),
+            center: lookfrom,
+            u: RtVec3::default(),
+            v: RtVec3::default(),
+            w: RtVec3::default(),
+            focus_distance: 0.0,
+            pixel00_loc: RtPoint3::default(),
+            pixel_delta_u: RtVec3::default(),
+            pixel_delta_v: RtVec3::default(),
+            lens_delta_u: RtVec3::default(),
+            lens_delta_v: RtVec3::default(),
+            _vfov: vfov, _look_from: lookfrom, _look_at: lookat, _vup: vup
+        };
+        camera.update_viewport();
+        camera
+    }
 
-        let center = lookfrom;
+    /// Place the viewport on the plane in focus
+    fn update_viewport(&mut self) {
+        let (lookfrom, lookat) = (self._look_from, self._look_at);
 
         // Determine viewport dimensions.
-        let focal_length = (lookfrom - lookat).length();
-        let theta = degrees_to_radians(vfov);
+        let focus_distance = if self.lens.focus_distance > 0.0 { 
+            self.lens.focus_distance 
+        } else { 
+            (lookfrom - lookat).length() 
+        };
+        let theta = degrees_to_radians(self._vfov);
         let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h * focal_length;
-        let viewport_width = viewport_height * (image_width as f32 / image_height as f32);
+        let viewport_height = 2.0 * h * focus_distance;
+        let viewport_width = viewport_height * 
+            (self.image_width as f32 * self.pixel_aspect / self.image_height as f32);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
         let w = (lookfrom - lookat).normalize();
-        let u = (RtVec3::cross(vup, w)).normalize();
+        let u = (RtVec3::cross(self._vup, w)).normalize();
         let v = RtVec3::cross(w, u);
+        (self.u, self.v, self.w) = (u, v, w);
+        self.focus_distance = focus_distance;
 
         // Calculate the vectors across the horizontal and down the vertical viewport edges.
         let viewport_u = viewport_width * u;
         let viewport_v = viewport_height * -v;
 
         // Calculate the horizontal and vertical delta vectors from pixel to pixel.
-        let pixel_delta_u = viewport_u / image_width as f32;
-        let pixel_delta_v = viewport_v / image_height as f32;
+        self.pixel_delta_u = viewport_u / self.image_width as f32;
+        self.pixel_delta_v = viewport_v / self.image_height as f32;
 
         // Calculate the location of the upper left pixel.
-        let viewport_upper_left = center - (focal_length * w).to_point3() - viewport_u/2.0 - viewport_v/2.0;
+        let viewport_upper_left = self.center - (focus_distance * w).to_point3() - viewport_u/2.0 - viewport_v/2.0;
 
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
-        let pixel00_loc = pixel00_loc.to_point3();
+        let pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        self.pixel00_loc = pixel00_loc.to_point3();
 
-        Self {
-            image_width, image_height, center, 
-            pixel00_loc, pixel_delta_u, pixel_delta_v,
-            _vfov: vfov, _look_from: lookfrom, _look_at: lookat
+        // Lens axes
+        let aperture = self.lens.aperture_size.max(0.0);
+        self.lens_delta_u = aperture * u;
+        self.lens_delta_v = aperture * v;
+    }
+
+    /// Same camera with another resolution
+    pub fn with_resolution(&self, image_width: u16, image_height: u16, pixel_aspect: f32) -> Self {
+        Self::new(image_width, image_height, pixel_aspect, 
+                  self._vfov, self._look_from, self._look_at, self._vup)
+            .with_projection(self.projection)
+            .with_lens(self.lens)
+    }
+
+    /// Same camera with another projection
+    pub fn with_projection(mut self, projection: RtProjection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// Same camera with another lens
+    pub fn with_lens(mut self, lens: RtLens) -> Self {
+        self.lens = lens;
+        self.update_viewport();
+        self
+    }
+
+    /// Returns true if the camera renders images of this resolution
+    pub fn has_resolution(&self, image_width: u16, image_height: u16, pixel_aspect: f32) -> bool {
+        self.image_width == image_width && 
+        self.image_height == image_height && 
+        self.pixel_aspect == pixel_aspect
+    }
+
+    /// Point of the aperture for the lens sample (lu, lv) in [0, 1)
+    fn sample_aperture(&self, lu: f32, lv: f32) -> RtPoint3 {
+        if self.lens.aperture_size <= 0.0 {
+            return self.center
+        }
+        let (dx, dy) = if self.lens.aperture_blades >= 3 {
+            sample_regular_polygon(self.lens.aperture_blades, 
+                degrees_to_radians(self.lens.aperture_rotation), lu, lv)
+        } else {
+            sample_concentric_disk(lu, lv)
+        };
+        self.center + dx * self.lens_delta_u + dy * self.lens_delta_v
+    }
+
+    /// Direction of the fisheye camera through the raster position (sx, sy),
+    /// None outside of the image circle
+    fn fisheye_direction(&self, sx: f32, sy: f32) -> Option<RtVec3> {
+        // Coordinates in the unit circle inscribed in the image
+        let aspect = self.image_width as f32 * self.pixel_aspect / self.image_height as f32;
+        let (mut fx, mut fy) = (2.0 * sx / self.image_width as f32 - 1.0, 1.0 - 2.0 * sy / self.image_height as f32);
+        if aspect >= 1.0 { fx *= aspect } else { fy /= aspect }
+        let r = (fx * fx + fy * fy).sqrt();
+        if r > 1.0 {
+            return None
         }
+        let theta = r * degrees_to_radians(self._vfov) / 2.0;
+        let phi = fy.atan2(fx);
+        Some(theta.sin() * (phi.cos() * self.u + phi.sin() * self.v) - theta.cos() * self.w)
+    }
+
+    /// Direction of the equirectangular camera through the raster position (sx, sy)
+    fn equirectangular_direction(&self, sx: f32, sy: f32) -> RtVec3 {
+        // Longitude in [-pi, pi], 0 at the center of the image, latitude in [-pi/2, pi/2]
+        let phi = 2.0 * RT_PI * (sx / self.image_width as f32 - 0.5);
+        let theta = RT_PI * (0.5 - sy / self.image_height as f32);
+        theta.cos() * (phi.sin() * self.u - phi.cos() * self.w) + theta.sin() * self.v
     }
 
     /// Sample camera ray
     /// 
-    /// We shoot the ray at the center of the pixel for each pixel in the grid
-    /// 
     /// (x: column, y: row) : pixel position
-    pub fn get_camera_ray(&self, x: u16, y: u16, px: f32, py: f32) -> RtRay {
+    /// (px, py) : position in the pixel, in [0, 1)
+    /// (lu, lv) : position on the aperture, in [0, 1)
+    /// 
+    /// Returns None when the position is outside of the projection (fisheye)
+    pub fn get_camera_ray(&self, x: u16, y: u16, px: f32, py: f32, lu: f32, lv: f32) -> Option<RtRay> {
+        let (sx, sy) = (x as f32 + px, y as f32 + py);
         let pixel_center = self.pixel00_loc + 
-            ((x as f32 + px) * self.pixel_delta_u) + 
-            ((y as f32 + py) * self.pixel_delta_v);
-        let ray_direction = pixel_center - self.center;
+            ((sx - 0.5) * self.pixel_delta_u) + 
+            ((sy - 0.5) * self.pixel_delta_v);
+        let (origin, ray_direction) = match self.projection {
+            RtProjection::Perspective => {
+                // Rays through the same pixel converge on the plane in focus
+                let origin = self.sample_aperture(lu, lv);
+                (origin, pixel_center - origin)
+            }
+            RtProjection::Orthographic => {
+                // From the plane of the camera center
+                (pixel_center + self.focus_distance * self.w, -self.w)
+            }
+            RtProjection::Fisheye => (self.center, self.fisheye_direction(sx, sy)?),
+            RtProjection::Equirectangular => (self.center, self.equirectangular_direction(sx, sy)),
+        };
         // Create shader globals
         let sg = RtShaderGlobals::default(x, y);
-        // Create the ray from the center
-        let mut ray = RtRay::new(&sg, self.center, ray_direction.normalize());
+        // Create the ray
+        let mut ray = RtRay::new(&sg, origin, ray_direction.normalize());
         ray.bounces = 0;
-        ray
+        Some(ray)
     }
 }
 
@@ -108,13 +281,18 @@ impl RtPixel {
         Self { x, y }
     }
 
-    pub fn get_ray(&self, camera: &RtCamera) -> RtRay {
-        camera.get_camera_ray(
-            self.x, 
-            self.y,
-            random_float(),
-            random_float()
-        )
+    /// Position of the sample `si` in the pixel, in [0, 1)
+    pub fn get_film_sample(&self, sampler: &RtSampler, si: u32) -> (f32, f32) {
+        sampler.get_2d(self.x, self.y, si, RT_DIM_PIXEL)
+    }
+
+    /// Camera ray of the sample `si` of the pixel
+    pub fn get_ray(&self, camera: &RtCamera, sampler: &RtSampler, si: u32) -> Option<RtRay> {
+        let (px, py) = self.get_film_sample(sampler, si);
+        let (lu, lv) = sampler.get_2d(self.x, self.y, si, RT_DIM_LENS);
+        let mut ray = camera.get_camera_ray(self.x, self.y, px, py, lu, lv)?;
+        ray.si = si;
+        Some(ray)
     }
 
     pub fn x(&self) -> usize {
@@ -171,3 +349,87 @@ impl<'a> Iterator for RtCameraRayIterator<'a> {
         Some(pixel)
     }
 }
+
+
+// ========================================
+//  Buckets
+// (split the image in tiles that can be 
+//  rendered independently)
+// ========================================
+
+/// Rectangular tile of the image
+#[derive(Debug, Clone, Copy)]
+pub struct RtBucket {
+    /// Top left pixel
+    pub x: u16,
+    pub y: u16,
+    /// Size in pixels
+    pub width: u16,
+    pub height: u16
+}
+
+impl RtBucket {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn num_pixels(&self) -> usize {
+        usize::from(self.width) * usize::from(self.height)
+    }
+
+    /// Iterate on the pixels of the bucket, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = RtPixel> + '_ {
+        (self.y..self.y + self.height).flat_map(move |y| {
+            (self.x..self.x + self.width).map(move |x| RtPixel::new(x, y))
+        })
+    }
+}
+
+/// Iterates on the buckets of the image, from top left to bottom right.
+/// Buckets on the right and bottom borders can be smaller.
+pub struct RtBucketIterator {
+    image_width: u16,
+    image_height: u16,
+    bucket_size: u16,
+    /// Current position
+    current_x: u16,
+    current_y: u16,
+}
+
+impl RtBucketIterator {
+    /// Creates an iterator for the buckets of the camera image
+    pub fn new(camera: &RtCamera, bucket_size: u16) -> Self {
+        Self {
+            image_width: camera.image_width,
+            image_height: camera.image_height,
+            bucket_size: bucket_size.max(1),
+            current_x: 0,
+            current_y: 0
+        }
+    }
+}
+
+impl Iterator for RtBucketIterator {
+    type Item = RtBucket;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current_y >= self.image_height || self.image_width == 0 {
+            return None
+        }
+
+        let width = self.bucket_size.min(self.image_width - self.current_x);
+        let height = self.bucket_size.min(self.image_height - self.current_y);
+        let bucket = RtBucket::new(self.current_x, self.current_y, width, height);
+
+        // Compute next bucket position
+        if self.image_width - self.current_x <= self.bucket_size {
+            // Pick first bucket of the next row
+            self.current_x  = 0;
+            self.current_y += height;
+        } else {
+            self.current_x += width;
+        }
+
+        Some(bucket)
+    }
+}
@@ -0,0 +1,141 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Display pipeline : converts the linear radiance of
+///   the render result to 8-bit colors for the render
+///   view. Applies the exposure, the view transform and
+///   dithering.
+/// =====================================================
+
+use egui::{Color32, ColorImage};
+
+use crate::rt_types::*;
+use crate::rt_sampler::hash_u32;
+use crate::rt_render_output::{RtRenderResult, RtAov};
+
+
+// ========================================
+//  View transforms
+// ========================================
+
+/// Transform from scene linear values to display values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtViewTransform {
+    /// sRGB transfer function, values above 1.0 are clipped
+    #[default]
+    Srgb,
+    /// Rec.709 transfer function, values above 1.0 are clipped
+    Rec709,
+    /// ACES filmic tonemap, then sRGB transfer function
+    Aces,
+}
+
+impl RtViewTransform {
+    pub const ALL: [RtViewTransform; 3] = [Self::Srgb, Self::Rec709, Self::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Srgb => "sRGB",
+            Self::Rec709 => "Rec.709",
+            Self::Aces => "ACES filmic",
+        }
+    }
+
+    /// Transform a linear, non-negative component to [0, 1]
+    pub fn apply(&self, linear: f32) -> f32 {
+        let display = match self {
+            Self::Srgb => linear_to_srgb(linear),
+            Self::Rec709 => linear_to_rec709(linear),
+            Self::Aces => linear_to_srgb(aces_filmic(linear)),
+        };
+        display.clamp(0.0, 1.0)
+    }
+}
+
+/// Rec.709 transfer function (ITU-R BT.709)
+pub fn linear_to_rec709(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        0.0
+    } else if linear < 0.018 {
+        4.5 * linear
+    } else {
+        1.099 * linear.powf(0.45) - 0.099
+    }
+}
+
+/// ACES filmic tonemap curve (Krzysztof Narkowicz fit)
+pub fn aces_filmic(linear: f32) -> f32 {
+    // The fit expects values exposed for the ACES reference
+    let x = 0.6 * linear.max(0.0);
+    let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+    mapped.clamp(0.0, 1.0)
+}
+
+
+// ========================================
+//  Display settings
+// ========================================
+
+/// Settings of the display pipeline
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RtDisplaySettings {
+    /// Exposure in stops, the linear values are multiplied by 2^exposure
+    pub exposure: f32,
+    pub view_transform: RtViewTransform,
+    /// Add noise before the 8-bit quantization to hide banding
+    pub dither: bool,
+    /// Image of the render result that is displayed
+    pub aov: RtAov,
+}
+
+impl Default for RtDisplaySettings {
+    fn default() -> Self {
+        Self {
+            exposure: 0.0,
+            view_transform: RtViewTransform::default(),
+            dither: true,
+            aov: RtAov::default(),
+        }
+    }
+}
+
+/// Triangular noise in [-1, 1] that only depends on the pixel
+fn dither_noise(x: usize, y: usize) -> f32 {
+    let h1 = hash_u32((x as u32) ^ hash_u32(y as u32));
+    let h2 = hash_u32(h1);
+    let u1 = (h1 >> 8) as f32 / (1 << 24) as f32;
+    let u2 = (h2 >> 8) as f32 / (1 << 24) as f32;
+    u1 + u2 - 1.0
+}
+
+impl RtDisplaySettings {
+    /// Display values in [0, 1] of a linear color
+    pub fn apply(&self, color: RtRGBA) -> RtRGBA {
+        let scale = self.exposure.exp2();
+        RtRGBA {
+            r: self.view_transform.apply(color.r * scale),
+            g: self.view_transform.apply(color.g * scale),
+            b: self.view_transform.apply(color.b * scale),
+            a: color.a.clamp(0.0, 1.0)
+        }
+    }
+
+    /// 8-bit display color of the pixel (x, y)
+    pub fn to_color32(&self, color: RtRGBA, x: usize, y: usize) -> Color32 {
+        let display = self.apply(color);
+        let noise = if self.dither { dither_noise(x, y) } else { 0.0 };
+        let quantize = |v: f32| (v * 255.0 + noise).round().clamp(0.0, 255.0) as u8;
+        Color32::from_rgb(quantize(display.r), quantize(display.g), quantize(display.b))
+    }
+
+    /// Fill the image displayed in the render view
+    pub fn fill_image(&self, result: &RtRenderResult, image: &mut ColorImage) {
+        let pixels = result.get_aov(self.aov);
+        for (y, row) in pixels.iter().enumerate().take(image.height()) {
+            for (x, color) in row.iter().enumerate().take(image.width()) {
+                image[(x, y)] = self.to_color32(*color, x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Defines the pixel reconstruction filters. Each
+///   sample is splatted to the pixels around it,
+///   weighted by the filter.
+/// =====================================================
+
+use crate::rt_types::*;
+
+
+// ========================================
+//  Filter types
+// ========================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtFilterType {
+    /// Constant weight, a width of 1 averages the samples of each pixel
+    #[default]
+    Box,
+    /// Weight decreasing linearly to the border
+    Triangle,
+    /// Gaussian with a standard deviation of a sixth of the width
+    Gaussian,
+    /// Mitchell-Netravali cubic (B = C = 1/3), with negative lobes
+    Mitchell,
+    /// Blackman-Harris window
+    BlackmanHarris,
+}
+
+impl RtFilterType {
+    pub const ALL: [RtFilterType; 5] = [Self::Box, Self::Triangle, Self::Gaussian, Self::Mitchell, Self::BlackmanHarris];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Box => "box",
+            Self::Triangle => "triangle",
+            Self::Gaussian => "gaussian",
+            Self::Mitchell => "mitchell",
+            Self::BlackmanHarris => "blackman-harris",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+
+    /// Usual width of the filter in pixels
+    pub fn default_width(&self) -> f32 {
+        match self {
+            Self::Box => 1.0,
+            Self::Triangle => 2.0,
+            Self::Gaussian => 3.0,
+            Self::Mitchell => 4.0,
+            Self::BlackmanHarris => 3.0,
+        }
+    }
+}
+
+
+// ========================================
+//  Filter
+// ========================================
+
+/// Separable filter of a given width in pixels
+#[derive(Debug, Clone, Copy)]
+pub struct RtFilter {
+    pub filter_type: RtFilterType,
+    pub width: f32,
+}
+
+impl RtFilter {
+    pub fn new(filter_type: RtFilterType, width: f32) -> RtFilter {
+        RtFilter { filter_type, width }
+    }
+
+    /// Half of the width
+    pub fn radius(&self) -> f32 {
+        0.5 * self.width
+    }
+
+    /// Number of pixels reached by the samples of a pixel on each side
+    pub fn margin(&self) -> usize {
+        (self.radius() - 0.5).ceil().max(0.0) as usize
+    }
+
+    /// Weight of a sample at `x` pixels from the center of the filter, on one axis
+    fn eval_1d(&self, x: f32) -> f32 {
+        let r = self.radius();
+        // Half open support, a sample on the border between two pixels
+        // only goes to one of them
+        if x < -r || x >= r {
+            return 0.0
+        }
+        match self.filter_type {
+            RtFilterType::Box => 1.0,
+            RtFilterType::Triangle => 1.0 - x.abs() / r,
+            RtFilterType::Gaussian => {
+                // Shifted down to reach 0 on the border
+                let sigma = r / 3.0;
+                let gaussian = |v: f32| (-v * v / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            RtFilterType::Mitchell => {
+                const B: f32 = 1.0 / 3.0;
+                const C: f32 = 1.0 / 3.0;
+                let t = 2.0 * x.abs() / r;
+                let value = if t < 1.0 {
+                    (12.0 - 9.0 * B - 6.0 * C) * t * t * t
+                        + (-18.0 + 12.0 * B + 6.0 * C) * t * t
+                        + (6.0 - 2.0 * B)
+                } else {
+                    (-B - 6.0 * C) * t * t * t
+                        + (6.0 * B + 30.0 * C) * t * t
+                        + (-12.0 * B - 48.0 * C) * t
+                        + (8.0 * B + 24.0 * C)
+                };
+                value / 6.0
+            }
+            RtFilterType::BlackmanHarris => {
+                let t = RT_PI * x / r;
+                0.35875 + 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() + 0.01168 * (3.0 * t).cos()
+            }
+        }
+    }
+
+    /// Weight of a sample at (dx, dy) pixels from the center of a pixel
+    pub fn eval(&self, dx: f32, dy: f32) -> f32 {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+}
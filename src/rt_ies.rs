@@ -0,0 +1,283 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Reads IES photometric files (IESNA LM-63), giving
+///   the intensity profiles of the IES lights.
+/// =====================================================
+
+use std::path::Path;
+
+use crate::rt_types::*;
+
+
+// ========================================
+//  Photometric profile
+// ========================================
+
+/// Intensity profile of a luminaire, with type C photometry
+///
+/// Vertical angles go from 0 (nadir, the direction of the light) to 180,
+/// horizontal angles turn around the nadir from 0 to 360. The symmetries
+/// of the profile are given by its last horizontal angle.
+#[derive(Debug, Clone)]
+pub struct RtIesProfile {
+    /// Vertical angles, in degrees
+    pub vertical_angles: Vec<f32>,
+    /// Horizontal angles, in degrees
+    pub horizontal_angles: Vec<f32>,
+    /// Intensities in candelas, one row of vertical angles per horizontal angle
+    pub candela: Vec<Vec<f32>>,
+}
+
+impl RtIesProfile {
+    pub fn open(path: &Path) -> Result<RtIesProfile, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {} : {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("Cannot read {} : {}", path.display(), e))
+    }
+
+    /// Parse the content of an IES file
+    pub fn parse(text: &str) -> Result<RtIesProfile, String> {
+        // Keywords, up to the TILT line
+        let mut lines = text.lines();
+        let tilt = loop {
+            let Some(line) = lines.next() else {
+                return Err(String::from("No TILT line"));
+            };
+            if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+
+        // Numbers, separated by blanks or commas
+        let mut values = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<f32>().map_err(|e| format!("Bad value {value} : {e}")));
+        let mut next = || values.next().unwrap_or(Err(String::from("Unexpected end of file")));
+
+        match tilt.as_str() {
+            "NONE" => (),
+            "INCLUDE" => {
+                // Lamp to luminaire geometry, then angles and multiplying factors
+                next()?;
+                let count = next()? as usize;
+                for _ in 0..count.saturating_mul(2) {
+                    next()?;
+                }
+            }
+            other => return Err(format!("Unsupported TILT={other}"))
+        }
+
+        // Lamps, lumens per lamp, candela multiplier, number of angles
+        let (_lamps, _lumens, multiplier) = (next()?, next()?, next()?);
+        let (num_vertical, num_horizontal) = (next()? as usize, next()? as usize);
+        if num_vertical == 0 || num_horizontal == 0 {
+            return Err(String::from("No angles"));
+        }
+        let photometric_type = next()? as u32;
+        if photometric_type != 1 {
+            return Err(String::from("Only type C photometry is supported"));
+        }
+        // Units, width, length, height, ballast factor, future use, input watts
+        for _ in 0..7 {
+            next()?;
+        }
+
+        let mut read_values = |count: usize| (0..count).map(|_| next()).collect::<Result<Vec<f32>, String>>();
+        let vertical_angles = read_values(num_vertical)?;
+        let horizontal_angles = read_values(num_horizontal)?;
+        let candela = (0..num_horizontal)
+            .map(|_| read_values(num_vertical).map(|row| row.iter().map(|cd| cd * multiplier).collect()))
+            .collect::<Result<Vec<Vec<f32>>, String>>()?;
+
+        let is_sorted = |angles: &[f32]| angles.windows(2).all(|pair| pair[0] <= pair[1]);
+        if !is_sorted(&vertical_angles) || !is_sorted(&horizontal_angles) {
+            return Err(String::from("Angles are not sorted"));
+        }
+        Ok(RtIesProfile { vertical_angles, horizontal_angles, candela })
+    }
+
+    /// Highest intensity of the profile, in candelas
+    pub fn max_candela(&self) -> f32 {
+        self.candela.iter().flatten().fold(0.0, |max, &cd| max.max(cd))
+    }
+
+    /// Intensity in candelas at the angles (in degrees) `vertical` from
+    /// the nadir and `horizontal` around it, interpolated bilinearly
+    pub fn eval(&self, vertical: f32, horizontal: f32) -> f32 {
+        if vertical < self.vertical_angles[0] || vertical > self.vertical_angles[self.vertical_angles.len() - 1] {
+            return 0.0
+        }
+        let (v0, v1, tv) = Self::find_interval(&self.vertical_angles, vertical);
+        let (h0, h1, th) = Self::find_interval(&self.horizontal_angles, self.apply_symmetry(horizontal));
+        let row = |h: usize| (1.0 - tv) * self.candela[h][v0] + tv * self.candela[h][v1];
+        (1.0 - th) * row(h0) + th * row(h1)
+    }
+
+    /// Horizontal angle in the range of the profile
+    fn apply_symmetry(&self, horizontal: f32) -> f32 {
+        let horizontal = horizontal.rem_euclid(360.0);
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        if last <= 0.0 {
+            // Rotationally symmetric
+            0.0
+        } else if last <= 90.0 {
+            // Symmetric in each quadrant
+            let horizontal = if horizontal > 180.0 { 360.0 - horizontal } else { horizontal };
+            if horizontal > 90.0 { 180.0 - horizontal } else { horizontal }
+        } else if last <= 180.0 {
+            // Symmetric about the 0-180 plane
+            if horizontal > 180.0 { 360.0 - horizontal } else { horizontal }
+        } else {
+            horizontal
+        }
+    }
+
+    /// Indices of the angles around `angle`, and its position between them
+    fn find_interval(angles: &[f32], angle: f32) -> (usize, usize, f32) {
+        let i1 = angles.partition_point(|&a| a < angle).min(angles.len() - 1);
+        let i0 = i1.saturating_sub(1);
+        if i0 == i1 || angles[i1] <= angles[i0] {
+            return (i1, i1, 0.0)
+        }
+        let t = ((angle - angles[i0]) / (angles[i1] - angles[i0])).clamp(0.0, 1.0);
+        (i0, i1, t)
+    }
+
+    /// Integral of the intensity over the sphere of directions,
+    /// the luminous flux of the luminaire in lumens
+    pub fn flux(&self) -> f32 {
+        const STEPS_VERTICAL: usize = 180;
+        const STEPS_HORIZONTAL: usize = 360;
+        let (d_vertical, d_horizontal) = (180.0 / STEPS_VERTICAL as f32, 360.0 / STEPS_HORIZONTAL as f32);
+        let mut flux = 0.0;
+        for i in 0..STEPS_VERTICAL {
+            let vertical = (i as f32 + 0.5) * d_vertical;
+            // Solid angle of the band of directions
+            let band = (vertical - 0.5 * d_vertical).to_radians().cos() - (vertical + 0.5 * d_vertical).to_radians().cos();
+            let mean = (0..STEPS_HORIZONTAL)
+                .map(|j| self.eval(vertical, (j as f32 + 0.5) * d_horizontal))
+                .sum::<f32>() / STEPS_HORIZONTAL as f32;
+            flux += mean * band * 2.0 * RT_PI;
+        }
+        flux
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn wallwasher_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes/assets/wallwasher.ies")
+    }
+
+    /// Profile with a rotating intensity, for the symmetry tests
+    fn make_profile(horizontal_angles: Vec<f32>) -> RtIesProfile {
+        let vertical_angles = vec![0.0, 90.0, 180.0];
+        let candela = horizontal_angles.iter()
+            .map(|h| vec![100.0 + h, 200.0 + h, 0.0])
+            .collect();
+        RtIesProfile { vertical_angles, horizontal_angles, candela }
+    }
+
+    #[test]
+    fn open_wallwasher() {
+        let profile = RtIesProfile::open(&wallwasher_path()).unwrap();
+        assert_eq!(profile.vertical_angles.len(), 10);
+        assert_eq!(profile.horizontal_angles, [0.0, 45.0, 90.0, 135.0, 180.0]);
+        assert_eq!(profile.max_candela(), 590.0);
+        // Measured with a finer integration
+        let flux = profile.flux();
+        assert!((flux - 1269.8).abs() < 2.0, "flux {flux}");
+    }
+
+    #[test]
+    fn eval_wallwasher() {
+        let profile = RtIesProfile::open(&wallwasher_path()).unwrap();
+        // Values of the file, interpolated between them
+        assert_eq!(profile.eval(40.0, 0.0), 590.0);
+        assert_eq!(profile.eval(0.0, 90.0), 420.0);
+        assert!((profile.eval(45.0, 0.0) - 565.0).abs() < 1e-3);
+        assert!((profile.eval(40.0, 22.5) - 547.5).abs() < 1e-3);
+        // Nothing above the last vertical angle
+        assert_eq!(profile.eval(120.0, 0.0), 0.0);
+        // Symmetric about the 0-180 plane
+        assert_eq!(profile.eval(30.0, 300.0), profile.eval(30.0, 60.0));
+    }
+
+    #[test]
+    fn symmetries() {
+        let vertical = 45.0;
+
+        // Last angle 0 : same intensity all around
+        let profile = make_profile(vec![0.0]);
+        for horizontal in [0.0, 30.0, 90.0, 200.0, 359.0] {
+            assert_eq!(profile.eval(vertical, horizontal), 150.0);
+        }
+
+        // Last angle 90 : symmetric in each quadrant
+        let profile = make_profile(vec![0.0, 45.0, 90.0]);
+        let reference = profile.eval(vertical, 30.0);
+        assert!(reference > profile.eval(vertical, 0.0));
+        for horizontal in [150.0, 210.0, 330.0, -30.0, 390.0] {
+            assert_eq!(profile.eval(vertical, horizontal), reference, "horizontal {horizontal}");
+        }
+
+        // Last angle 180 : symmetric about the 0-180 plane only
+        let profile = make_profile(vec![0.0, 90.0, 180.0]);
+        let reference = profile.eval(vertical, 30.0);
+        assert_eq!(profile.eval(vertical, 330.0), reference);
+        assert_ne!(profile.eval(vertical, 150.0), reference);
+
+        // Last angle 360 : no symmetry
+        let profile = make_profile(vec![0.0, 90.0, 180.0, 270.0, 360.0]);
+        let values: Vec<f32> = [30.0, 150.0, 210.0, 330.0].iter().map(|&h| profile.eval(vertical, h)).collect();
+        for (i, a) in values.iter().enumerate() {
+            for b in &values[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(profile.eval(vertical, 405.0), profile.eval(vertical, 45.0));
+    }
+
+    #[test]
+    fn truncated_files() {
+        let text = std::fs::read_to_string(wallwasher_path()).unwrap();
+        assert!(RtIesProfile::parse(&text).is_ok());
+        // Cut before the last value
+        let last_value = text.trim_end().rfind(char::is_whitespace).unwrap();
+        for end in 0..=last_value {
+            if text.is_char_boundary(end) {
+                assert!(RtIesProfile::parse(&text[..end]).is_err(), "file cut at {end}");
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_files() {
+        let text = std::fs::read_to_string(wallwasher_path()).unwrap();
+        let invalid = [
+            // Unsorted angles
+            text.replace("0 10 20 30 40", "0 20 10 30 40"),
+            text.replace("0 45 90 135 180", "0 90 45 135 180"),
+            // Bad numbers and counts
+            text.replace("1.0 1.0 15", "1.0 1.0 abc"),
+            text.replace("1 1200 1.0 10 5 1", "1 1200 1.0 0 5 1"),
+            text.replace("1 1200 1.0 10 5 1", "1 1200 1.0 1e30 5 1"),
+            text.replace("1 1200 1.0 10 5 1", "1 1200 1.0 10 -5 1"),
+            // Type B photometry
+            text.replace("1 1200 1.0 10 5 1", "1 1200 1.0 10 5 2"),
+            // Tilt
+            text.replace("TILT=NONE", ""),
+            text.replace("TILT=NONE", "TILT=lamp.tlt"),
+            text.replace("TILT=NONE\n", "TILT=INCLUDE\n1 1e30\n"),
+        ];
+        for text in invalid {
+            assert!(RtIesProfile::parse(&text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,177 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Reads HDR images (Radiance .hdr and OpenEXR),
+///   e.g. the environment maps of the skydome lights.
+/// =====================================================
+
+use std::io::{BufRead, BufReader, Read};
+use std::fs::File;
+use std::path::Path;
+
+use crate::rt_types::*;
+
+
+// ========================================
+//  Image
+// ========================================
+
+/// Linear RGB image, stored row by row from the top
+#[derive(Debug, Clone)]
+pub struct RtImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<RtRGBA>,
+}
+
+impl RtImage {
+    /// Image filled with a color
+    pub fn new(width: usize, height: usize, color: RtRGBA) -> Self {
+        Self { width, height, pixels: vec![color; width * height] }
+    }
+
+    /// Read an image, in a format depending on the file extension
+    pub fn open(path: &Path) -> Result<RtImage, String> {
+        let extension = path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let image = match extension.as_str() {
+            "hdr" => Self::open_hdr(path),
+            "exr" => Self::open_exr(path),
+            _ => Err(format!("Unsupported image format {extension}")),
+        };
+        image.map_err(|e| format!("Cannot read {} : {}", path.display(), e))
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> RtRGBA {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Pixel containing the coordinates (u, v) in [0, 1]²,
+    /// (0, 0) being the top left corner
+    pub fn lookup(&self, u: f32, v: f32) -> RtRGBA {
+        let x = ((u * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v * self.height as f32) as usize).min(self.height - 1);
+        self.get_pixel(x, y)
+    }
+
+    /// OpenEXR image, from its first RGB(A) layer
+    fn open_exr(path: &Path) -> Result<RtImage, String> {
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            path,
+            |resolution, _| RtImage::new(resolution.width(), resolution.height(), RtRGBA::BLACK),
+            |image: &mut RtImage, position, (r, g, b, _a): (f32, f32, f32, f32)| {
+                let index = position.y() * image.width + position.x();
+                image.pixels[index] = RtRGBA::new(r, g, b);
+            },
+        ).map_err(|e| e.to_string())?;
+        Ok(image.layer_data.channel_data.pixels)
+    }
+
+    /// Radiance RGBE image, flat or run-length encoded
+    ///
+    /// Only the usual orientation (-Y height +X width) is supported.
+    fn open_hdr(path: &Path) -> Result<RtImage, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let mut reader = BufReader::new(file);
+
+        // Header, ended by an empty line
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        if !line.starts_with("#?") {
+            return Err(String::from("Not a Radiance HDR file"));
+        }
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+                return Err(String::from("Unexpected end of header"));
+            }
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix("FORMAT=") {
+                if format != "32-bit_rle_rgbe" {
+                    return Err(format!("Unsupported format {format}"));
+                }
+            }
+        }
+
+        // Resolution
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let (height, width) = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["-Y", height, "+X", width] => (
+                height.parse::<usize>().map_err(|e| e.to_string())?,
+                width.parse::<usize>().map_err(|e| e.to_string())?,
+            ),
+            _ => return Err(format!("Unsupported resolution line {}", line.trim())),
+        };
+
+        let mut image = RtImage::new(width, height, RtRGBA::BLACK);
+        let mut scanline = vec![[0u8; 4]; width];
+        for y in 0..height {
+            Self::read_hdr_scanline(&mut reader, &mut scanline)?;
+            for (x, rgbe) in scanline.iter().enumerate() {
+                image.pixels[y * width + x] = Self::rgbe_to_rgb(rgbe);
+            }
+        }
+        Ok(image)
+    }
+
+    fn read_hdr_scanline(reader: &mut impl Read, scanline: &mut [[u8; 4]]) -> Result<(), String> {
+        let width = scanline.len();
+        let mut read = |buffer: &mut [u8]| reader.read_exact(buffer).map_err(|e| e.to_string());
+        let mut start = [0u8; 4];
+        read(&mut start)?;
+        let is_rle = (8..0x8000).contains(&width) && start[0] == 2 && start[1] == 2 && start[2] & 0x80 == 0;
+        if !is_rle {
+            // Flat pixels
+            scanline[0] = start;
+            for pixel in scanline.iter_mut().skip(1) {
+                read(pixel)?;
+            }
+            return Ok(())
+        }
+        if ((start[2] as usize) << 8 | start[3] as usize) != width {
+            return Err(String::from("Wrong scanline width"));
+        }
+        // Each channel is run-length encoded separately
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let mut count = [0u8; 1];
+                read(&mut count)?;
+                let (run, count) = if count[0] > 128 { (true, (count[0] - 128) as usize) } else { (false, count[0] as usize) };
+                if count == 0 || x + count > width {
+                    return Err(String::from("Bad scanline data"));
+                }
+                if run {
+                    let mut value = [0u8; 1];
+                    read(&mut value)?;
+                    scanline[x..x + count].iter_mut().for_each(|pixel| pixel[channel] = value[0]);
+                } else {
+                    let mut values = vec![0u8; count];
+                    read(&mut values)?;
+                    scanline[x..x + count].iter_mut().zip(values).for_each(|(pixel, value)| pixel[channel] = value);
+                }
+                x += count;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shared exponent to float color
+    fn rgbe_to_rgb(rgbe: &[u8; 4]) -> RtRGBA {
+        if rgbe[3] == 0 {
+            return RtRGBA::BLACK
+        }
+        let scale = 2.0_f32.powi(rgbe[3] as i32 - (128 + 8));
+        RtRGBA::new(
+            (rgbe[0] as f32 + 0.5) * scale,
+            (rgbe[1] as f32 + 0.5) * scale,
+            (rgbe[2] as f32 + 0.5) * scale,
+        )
+    }
+}
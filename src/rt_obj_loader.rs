@@ -0,0 +1,428 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Wavefront OBJ/MTL importer. Each OBJ object (or group)
+///   becomes one polymesh per material used.
+/// =====================================================
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use log::{debug, info, warn};
+
+use crate::rt_types::*;
+use crate::rt_scene::RtScene;
+use crate::rt_objects::rt_object_base::*;
+use crate::rt_objects::rt_geometries::RtPolymesh;
+use crate::rt_shaders::rt_shader_base::RtShader;
+use crate::rt_shaders::lambert::LambertShader;
+use crate::rt_shaders::metal::Metal;
+use crate::rt_shaders::glass::Glass;
+use crate::rt_shaders::lightShader::LightShader;
+
+/// Shader used by faces without material
+const DEFAULT_OBJ_SHADER: LambertShader = LambertShader { color: RtRGBA::from_rgb(0.8, 0.8, 0.8) };
+
+
+// ========================================
+//  MTL materials
+// ========================================
+
+/// Material as described in a MTL file
+#[derive(Clone, Debug)]
+struct MtlMaterial {
+    /// Diffuse color
+    Kd: RtRGBA,
+    /// Specular color
+    Ks: RtRGBA,
+    /// Emissive color
+    Ke: RtRGBA,
+    /// Specular exponent
+    Ns: f32,
+    /// Index of refraction
+    Ni: f32,
+    /// Opacity
+    d: f32,
+    /// Illumination model
+    illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            Kd: RtRGBA::from_rgb(0.8, 0.8, 0.8),
+            Ks: RtRGBA::BLACK,
+            Ke: RtRGBA::BLACK,
+            Ns: 0.0,
+            Ni: 1.5,
+            d: 1.0,
+            illum: 2
+        }
+    }
+}
+
+fn is_black(color: &RtRGBA) -> bool {
+    color.r <= 0.0 && color.g <= 0.0 && color.b <= 0.0
+}
+
+impl MtlMaterial {
+    /// Convert the material to the closest Raito shader
+    ///
+    /// - emissive materials (Ke) become light shaders
+    /// - transparent / refractive materials (d < 1, illum 4, 6, 7) become glass
+    /// - reflective materials (illum 3, or specular without diffuse) become metal
+    /// - everything else is lambert
+    fn to_shader(&self) -> Box<dyn RtShader> {
+        if !is_black(&self.Ke) {
+            Box::new(LightShader { color: self.Ke, intensity: 1.0 })
+        } else if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7) {
+            Box::new(Glass { ior: self.Ni })
+        } else if self.illum == 3 || (is_black(&self.Kd) && !is_black(&self.Ks)) {
+            // Convert the Phong exponent to a roughness
+            let fuzz = (2.0 / (self.Ns + 2.0)).sqrt().clamp(0.0, 1.0);
+            Box::new(Metal { color: self.Ks, fuzz })
+        } else {
+            Box::new(LambertShader { color: self.Kd })
+        }
+    }
+}
+
+fn parse_floats(values: &[&str], line_nb: usize) -> Result<Vec<f32>, String> {
+    values.iter()
+        .map(|v| v.parse().map_err(|_| format!("Line {} : cannot parse {} to float", line_nb, v)))
+        .collect()
+}
+
+fn parse_color(values: &[&str], line_nb: usize) -> Result<RtRGBA, String> {
+    let values = parse_floats(values, line_nb)?;
+    match values.len() {
+        // A single value is a grey color
+        1 => Ok(RtRGBA::from_rgb(values[0], values[0], values[0])),
+        3 => Ok(RtRGBA::from_rgb(values[0], values[1], values[2])),
+        _ => Err(format!("Line {} : expected a color", line_nb))
+    }
+}
+
+fn parse_float(values: &[&str], line_nb: usize) -> Result<f32, String> {
+    let values = parse_floats(values, line_nb)?;
+    values.first().copied().ok_or_else(|| format!("Line {} : expected a value", line_nb))
+}
+
+/// Read all materials from a MTL file
+fn read_mtl_file(path: &Path) -> Result<HashMap<String, MtlMaterial>, String> {
+    debug!("Reading material library {}", path.display());
+    let file = File::open(path).map_err(|e| format!("Cannot open {} : {}", path.display(), e))?;
+
+    let mut materials: HashMap<String, MtlMaterial> = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (line_nb, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+        if tokens[0] == "newmtl" {
+            if let Some((name, mtl)) = current.take() {
+                materials.insert(name, mtl);
+            }
+            current = Some((tokens[1..].join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let Some((_, mtl)) = current.as_mut() else {
+            continue;
+        };
+        let values = &tokens[1..];
+        match tokens[0] {
+            "Kd" => mtl.Kd = parse_color(values, line_nb + 1)?,
+            "Ks" => mtl.Ks = parse_color(values, line_nb + 1)?,
+            "Ke" => mtl.Ke = parse_color(values, line_nb + 1)?,
+            "Ns" => mtl.Ns = parse_float(values, line_nb + 1)?,
+            "Ni" => mtl.Ni = parse_float(values, line_nb + 1)?,
+            "d"  => mtl.d = parse_float(values, line_nb + 1)?,
+            "Tr" => mtl.d = 1.0 - parse_float(values, line_nb + 1)?,
+            "illum" => mtl.illum = parse_float(values, line_nb + 1)? as u32,
+            // Textures and other statements are not supported yet
+            _ => ()
+        }
+    }
+    if let Some((name, mtl)) = current.take() {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+
+// ========================================
+//  OBJ meshes
+// ========================================
+
+/// Faces of one object using the same material
+///
+/// Indices are the global (OBJ file) indices, starting at 0.
+struct ObjGroup {
+    name: String,
+    material: Option<String>,
+    nsides: Vec<u32>,
+    vidxs: Vec<u32>,
+    nidxs: Vec<Option<u32>>,
+    uvidxs: Vec<Option<u32>>,
+}
+
+impl ObjGroup {
+    fn new(name: String, material: Option<String>) -> Self {
+        Self { name, material, nsides: Vec::new(), vidxs: Vec::new(), nidxs: Vec::new(), uvidxs: Vec::new() }
+    }
+}
+
+/// Remap global indices to indices in a compact list
+fn remap<T: Copy>(idxs: &[u32], values: &[T]) -> (Vec<T>, Vec<u32>) {
+    let mut mapping: HashMap<u32, u32> = HashMap::new();
+    let mut list = Vec::new();
+    let local = idxs.iter().map(|&i| {
+        *mapping.entry(i).or_insert_with(|| {
+            list.push(values[i as usize]);
+            (list.len() - 1) as u32
+        })
+    }).collect();
+    (list, local)
+}
+
+/// Resolve an OBJ index (1-based, negative values are relative to the end)
+fn resolve_index(token: &str, count: usize, line_nb: usize) -> Result<u32, String> {
+    let idx: i64 = token.parse().map_err(|_| format!("Line {} : invalid index {}", line_nb, token))?;
+    let resolved = if idx < 0 { count as i64 + idx } else { idx - 1 };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("Line {} : index {} out of range", line_nb, token));
+    }
+    Ok(resolved as u32)
+}
+
+/// Load an OBJ file as a list of meshes
+///
+/// Meshes are named `<name_prefix>/<object>/<material>`.
+/// If `shader` is given, it is used on every mesh instead of the
+/// materials from the MTL files.
+pub fn load_obj_meshes(path: &Path, name_prefix: &str, shader: Option<&dyn RtShader>) -> Result<Vec<RtPolymesh>, String> {
+    let file = File::open(path).map_err(|e| format!("Cannot open {} : {}", path.display(), e))?;
+    let obj_dir = path.parent().map(PathBuf::from).unwrap_or_default();
+
+    let mut vlist: Vec<RtPoint3> = Vec::new();
+    let mut nlist: Vec<RtVec3> = Vec::new();
+    let mut uvlist: Vec<[f32; 2]> = Vec::new();
+    let mut materials: HashMap<String, MtlMaterial> = HashMap::new();
+
+    let mut groups: Vec<ObjGroup> = Vec::new();
+    // (object, material) -> index in groups
+    let mut group_ids: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut object_name = String::from("default");
+    let mut material: Option<String> = None;
+
+    for (line_nb, line) in BufReader::new(file).lines().enumerate() {
+        let line_nb = line_nb + 1;
+        let line = line.map_err(|e| e.to_string())?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() || tokens[0].starts_with('#') {
+            continue;
+        }
+        let values = &tokens[1..];
+        match tokens[0] {
+            "v" => {
+                let p = parse_floats(values, line_nb)?;
+                if p.len() < 3 {
+                    return Err(format!("Line {} : vertex needs 3 coordinates", line_nb));
+                }
+                vlist.push(RtPoint3::new(p[0], p[1], p[2]));
+            },
+            "vn" => {
+                let n = parse_floats(values, line_nb)?;
+                if n.len() < 3 {
+                    return Err(format!("Line {} : normal needs 3 coordinates", line_nb));
+                }
+                nlist.push(RtVec3::new(n[0], n[1], n[2]));
+            },
+            "vt" => {
+                let uv = parse_floats(values, line_nb)?;
+                if uv.is_empty() {
+                    return Err(format!("Line {} : texture coordinate needs a value", line_nb));
+                }
+                uvlist.push([uv[0], uv.get(1).copied().unwrap_or(0.0)]);
+            },
+            "o" | "g" => {
+                object_name = if values.is_empty() { String::from("default") } else { values.join(" ") };
+            },
+            "usemtl" => {
+                material = Some(values.join(" "));
+            },
+            "mtllib" => {
+                // Material libraries are only needed without shader override
+                if shader.is_some() {
+                    continue;
+                }
+                for lib in values {
+                    match read_mtl_file(&obj_dir.join(lib)) {
+                        Ok(lib_materials) => materials.extend(lib_materials),
+                        Err(e) => warn!("Could not read material library {} : {}", lib, e)
+                    }
+                }
+            },
+            "f" => {
+                if values.len() < 3 {
+                    return Err(format!("Line {} : face needs at least 3 vertices", line_nb));
+                }
+                // Find the group for this object and material, the faces
+                // of a group can be spread over the file
+                let group_id = *group_ids.entry((object_name.clone(), material.clone())).or_insert_with(|| {
+                    groups.push(ObjGroup::new(object_name.clone(), material.clone()));
+                    groups.len() - 1
+                });
+                let group = &mut groups[group_id];
+                // Vertices are formatted as v, v/vt, v//vn or v/vt/vn
+                for vertex in values {
+                    let mut indices = vertex.split('/');
+                    let v = indices.next().unwrap_or_default();
+                    group.vidxs.push(resolve_index(v, vlist.len(), line_nb)?);
+                    let uv = match indices.next() {
+                        Some(vt) if !vt.is_empty() => Some(resolve_index(vt, uvlist.len(), line_nb)?),
+                        _ => None
+                    };
+                    group.uvidxs.push(uv);
+                    let n = match indices.next() {
+                        Some(vn) if !vn.is_empty() => Some(resolve_index(vn, nlist.len(), line_nb)?),
+                        _ => None
+                    };
+                    group.nidxs.push(n);
+                }
+                group.nsides.push(values.len() as u32);
+            },
+            // Smoothing groups, lines, curves... are ignored
+            _ => ()
+        }
+    }
+
+    // Create the meshes
+    let mut meshes = Vec::with_capacity(groups.len());
+    for group in groups {
+        let material_name = group.material.clone().unwrap_or_else(|| String::from("default"));
+        let name = format!("{}/{}/{}", name_prefix, group.name, material_name);
+        let mesh_shader = match (shader, &group.material) {
+            (Some(shader), _) => shader.clone_dyn(),
+            (None, Some(mtl)) if materials.contains_key(mtl) => materials[mtl].to_shader(),
+            (None, Some(mtl)) => {
+                warn!("Material {} not found for {}", mtl, name);
+                Box::new(DEFAULT_OBJ_SHADER.clone())
+            },
+            (None, None) => Box::new(DEFAULT_OBJ_SHADER.clone())
+        };
+
+        let (mesh_vlist, mesh_vidxs) = remap(&group.vidxs, &vlist);
+        let mut mesh = RtPolymesh::new(
+            ObjectParams::new(name.clone(), String::from("polymesh"), mesh_shader),
+            mesh_vlist, &group.nsides, &mesh_vidxs)?;
+
+        // Normals and UVs are only kept if every face has them
+        let nidxs: Option<Vec<u32>> = group.nidxs.iter().copied().collect();
+        if let Some(nidxs) = nidxs {
+            let (mesh_nlist, mesh_nidxs) = remap(&nidxs, &nlist);
+            mesh.set_normals(mesh_nlist, &group.nsides, &mesh_nidxs)?;
+        } else if group.nidxs.iter().any(|n| n.is_some()) {
+            warn!("Mesh {} : some faces have no normals, using flat shading", name);
+        }
+        let uvidxs: Option<Vec<u32>> = group.uvidxs.iter().copied().collect();
+        if let Some(uvidxs) = uvidxs {
+            let (mesh_uvlist, mesh_uvidxs) = remap(&uvidxs, &uvlist);
+            mesh.set_uvs(mesh_uvlist, &group.nsides, &mesh_uvidxs)?;
+        }
+
+        debug!("Mesh {} : {} triangles", name, mesh.num_triangles());
+        meshes.push(mesh);
+    }
+
+    Ok(meshes)
+}
+
+/// Import an OBJ file (and its MTL files) in the scene
+///
+/// Returns the number of meshes added to the scene
+pub fn open_obj_file(path: &str, scene: &mut RtScene) -> Result<usize, String> {
+    info!("Opening OBJ file : {path}");
+    let now = std::time::Instant::now();
+
+    let name_prefix = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let meshes = load_obj_meshes(Path::new(path), &name_prefix, None)?;
+    let nb_meshes = meshes.len();
+    for mesh in meshes {
+        scene.add_shape(Box::new(mesh));
+    }
+
+    info!("> OBJ file took {} sec to open ({} meshes)", now.elapsed().as_secs_f64(), nb_meshes);
+    Ok(nb_meshes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assets_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes/assets")
+    }
+
+    #[test]
+    fn load_cubes() {
+        let meshes = load_obj_meshes(&assets_dir().join("cubes.obj"), "cubes", None).unwrap();
+        let names: Vec<String> = meshes.iter().map(|mesh| mesh.object_params.name.clone()).collect();
+        assert_eq!(names, ["cubes/cube_diffuse/red_diffuse", "cubes/cube_mirror/mirror", "cubes/cube_glass/glass"]);
+        for mesh in &meshes {
+            // 6 quads, with their own 8 vertices
+            assert_eq!(mesh.num_triangles(), 12);
+            assert_eq!(mesh.vlist.len(), 8);
+        }
+        // Only the first cube has normals and UVs
+        assert_eq!((meshes[0].nidxs.len(), meshes[0].uvidxs.len()), (12, 12));
+        assert_eq!((meshes[1].nidxs.len(), meshes[1].uvidxs.len()), (0, 0));
+    }
+
+    #[test]
+    fn load_cubes_with_shader() {
+        let shader = LambertShader { color: RtRGBA::WHITE };
+        let meshes = load_obj_meshes(&assets_dir().join("cubes.obj"), "cubes", Some(&shader)).unwrap();
+        assert_eq!(meshes.len(), 3);
+    }
+
+    #[test]
+    fn faces_grouped_by_object_and_material() {
+        let path = std::env::temp_dir().join(format!("raito_groups_{}.obj", std::process::id()));
+        std::fs::write(&path, "\
+v 0 0 0
+v 1 0 0
+v 0 1 0
+o a
+usemtl red
+f 1 2 3
+usemtl blue
+f 1 2 3
+o b
+f 1 2 3
+o a
+usemtl red
+f 1 2 3
+usemtl blue
+f 1 2 3
+f 1 2 3
+").unwrap();
+        let meshes = load_obj_meshes(&path, "groups", None);
+        std::fs::remove_file(&path).unwrap();
+
+        let meshes = meshes.unwrap();
+        let counts: Vec<(String, usize)> = meshes.iter()
+            .map(|mesh| (mesh.object_params.name.clone(), mesh.num_triangles()))
+            .collect();
+        assert_eq!(counts, [
+            (String::from("groups/a/red"), 2),
+            (String::from("groups/a/blue"), 3),
+            (String::from("groups/b/blue"), 1),
+        ]);
+    }
+}
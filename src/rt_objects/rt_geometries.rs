@@ -7,6 +7,8 @@
 
 use crate::rt_types::*;
 use crate::rt_ray::*;
+use crate::rt_shader_globals::*;
+use crate::rt_bvh::RtBvh;
 use crate::rt_objects::rt_object_base::*;
 
 
@@ -24,9 +26,271 @@ impl RtSphere {
     const _RT_TYPE: &'static str = "<RtGeometry : Sphere>";
 }
 
+/// Polygon mesh
+/// 
+/// Polygons are triangulated on creation (as a fan around their
+/// first vertex), so the mesh only stores triangles. 
+/// Normals and UVs are optional and can be indexed separately 
+/// from the vertices.
+pub struct RtPolymesh {
+    pub object_params: ObjectParams,
+    /// Vertex positions
+    pub vlist: Vec<RtPoint3>,
+    /// Vertex indices, 3 per triangle
+    pub vidxs: Vec<[u32; 3]>,
+    /// Vertex normals (smooth shading)
+    pub nlist: Vec<RtVec3>,
+    /// Normal indices, 3 per triangle
+    pub nidxs: Vec<[u32; 3]>,
+    /// UV coordinates
+    pub uvlist: Vec<[f32; 2]>,
+    /// UV indices, 3 per triangle
+    pub uvidxs: Vec<[u32; 3]>,
+    /// Acceleration structure over the triangles
+    bvh: RtBvh,
+}
+
+impl RtPolymesh {
+    const _RT_TYPE: &'static str = "<RtGeometry : Polymesh>";
+
+    /// Creates a mesh from a list of polygons
+    /// 
+    /// - vlist  : vertex positions
+    /// - nsides : number of vertices of each polygon
+    /// - vidxs  : vertex indices of each polygon (sum of nsides values)
+    pub fn new(object_params: ObjectParams, vlist: Vec<RtPoint3>, 
+               nsides: &[u32], vidxs: &[u32]) -> Result<Self, String> {
+        let vidxs = Self::triangulate(nsides, vidxs, vlist.len())?;
+        let mut mesh = Self {
+            object_params, vlist, vidxs,
+            nlist: Vec::new(), nidxs: Vec::new(),
+            uvlist: Vec::new(), uvidxs: Vec::new(),
+            bvh: RtBvh::default()
+        };
+        mesh.update_bvh();
+        Ok(mesh)
+    }
+
+    /// Rebuild the acceleration structure, 
+    /// needed if the vertices are modified
+    pub fn update_bvh(&mut self) {
+        let bboxes: Vec<RtAABB> = (0..self.num_triangles())
+            .map(|prim_id| RtAABB::from_points(&self.get_vertices(prim_id)))
+            .collect();
+        self.bvh = RtBvh::build(&bboxes);
+    }
+
+    /// Set vertex normals, with one index per polygon vertex
+    /// (same layout as the vertex indices)
+    pub fn set_normals(&mut self, nlist: Vec<RtVec3>, nsides: &[u32], nidxs: &[u32]) -> Result<(), String> {
+        let nidxs = Self::triangulate(nsides, nidxs, nlist.len())?;
+        if nidxs.len() != self.vidxs.len() {
+            return Err(format!("Expected normals for {} triangles, got {}", self.vidxs.len(), nidxs.len()));
+        }
+        self.nlist = nlist.into_iter().map(|n| n.normalize()).collect();
+        self.nidxs = nidxs;
+        Ok(())
+    }
+
+    /// Set UV coordinates, with one index per polygon vertex
+    /// (same layout as the vertex indices)
+    pub fn set_uvs(&mut self, uvlist: Vec<[f32; 2]>, nsides: &[u32], uvidxs: &[u32]) -> Result<(), String> {
+        let uvidxs = Self::triangulate(nsides, uvidxs, uvlist.len())?;
+        if uvidxs.len() != self.vidxs.len() {
+            return Err(format!("Expected UVs for {} triangles, got {}", self.vidxs.len(), uvidxs.len()));
+        }
+        self.uvlist = uvlist;
+        self.uvidxs = uvidxs;
+        Ok(())
+    }
+
+    /// Number of triangles in the mesh
+    pub fn num_triangles(&self) -> usize {
+        self.vidxs.len()
+    }
+
+    /// Split polygons into triangles (fan triangulation)
+    fn triangulate(nsides: &[u32], idxs: &[u32], nb_values: usize) -> Result<Vec<[u32; 3]>, String> {
+        let expected: u32 = nsides.iter().sum();
+        if expected as usize != idxs.len() {
+            return Err(format!("Polygons reference {} indices, got {}", expected, idxs.len()));
+        }
+        if let Some(idx) = idxs.iter().find(|&&i| i as usize >= nb_values) {
+            return Err(format!("Index {} out of range ({} values)", idx, nb_values));
+        }
+        let mut triangles = Vec::with_capacity(idxs.len());
+        let mut offset = 0;
+        for &n in nsides {
+            let n = n as usize;
+            if n < 3 {
+                return Err(format!("Polygon with {} vertices", n));
+            }
+            let polygon = &idxs[offset..offset + n];
+            for i in 1..n - 1 {
+                triangles.push([polygon[0], polygon[i], polygon[i + 1]]);
+            }
+            offset += n;
+        }
+        Ok(triangles)
+    }
+
+    pub fn get_vertices(&self, prim_id: usize) -> [RtPoint3; 3] {
+        let [i0, i1, i2] = self.vidxs[prim_id];
+        [self.vlist[i0 as usize], self.vlist[i1 as usize], self.vlist[i2 as usize]]
+    }
+
+    /// Watertight ray/triangle intersection
+    /// 
+    /// From "Watertight Ray/Triangle Intersection", Woop, Benthin & Wald (2013).
+    /// Returns the distance and barycentric coordinates (b1, b2) of the hit.
+    fn intersect_triangle(&self, ray: &RtRay, prim_id: usize, max_dist: f32) -> Option<(f32, f32, f32)> {
+        let [p0, p1, p2] = self.get_vertices(prim_id);
+
+        // Permute axes so that the ray direction is mostly along z
+        let abs_dir = RtVec3::new(ray.dir.x.abs(), ray.dir.y.abs(), ray.dir.z.abs());
+        let kz = if abs_dir.x > abs_dir.y {
+            if abs_dir.x > abs_dir.z { 0 } else { 2 }
+        } else if abs_dir.y > abs_dir.z { 1 } else { 2 };
+        let mut kx = (kz + 1) % 3;
+        let mut ky = (kx + 1) % 3;
+        // Keep the winding direction of the triangle
+        if ray.dir[kz] < 0.0 {
+            std::mem::swap(&mut kx, &mut ky);
+        }
+
+        // Shear constants
+        let sx = ray.dir[kx] / ray.dir[kz];
+        let sy = ray.dir[ky] / ray.dir[kz];
+        let sz = 1.0 / ray.dir[kz];
+
+        // Vertices relative to the ray origin
+        let a = p0 - ray.origin;
+        let b = p1 - ray.origin;
+        let c = p2 - ray.origin;
+
+        // Shear and scale the vertices
+        let ax = a[kx] - sx * a[kz];
+        let ay = a[ky] - sy * a[kz];
+        let bx = b[kx] - sx * b[kz];
+        let by = b[ky] - sy * b[kz];
+        let cx = c[kx] - sx * c[kz];
+        let cy = c[ky] - sy * c[kz];
+
+        // Scaled barycentric coordinates
+        let mut u = cx * by - cy * bx;
+        let mut v = ax * cy - ay * cx;
+        let mut w = bx * ay - by * ax;
+
+        // Fallback to double precision on edges
+        if u == 0.0 || v == 0.0 || w == 0.0 {
+            u = (cx as f64 * by as f64 - cy as f64 * bx as f64) as f32;
+            v = (ax as f64 * cy as f64 - ay as f64 * cx as f64) as f32;
+            w = (bx as f64 * ay as f64 - by as f64 * ax as f64) as f32;
+        }
+
+        // Edge tests
+        if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+            return None
+        }
+        let det = u + v + w;
+        if det == 0.0 {
+            return None
+        }
+
+        // Scaled hit distance, checked against the ray range
+        let az = sz * a[kz];
+        let bz = sz * b[kz];
+        let cz = sz * c[kz];
+        let t = u * az + v * bz + w * cz;
+        if (det < 0.0 && (t >= 0.0 || t < max_dist * det)) ||
+           (det > 0.0 && (t <= 0.0 || t > max_dist * det)) {
+            return None
+        }
+
+        let inv_det = 1.0 / det;
+        Some((t * inv_det, v * inv_det, w * inv_det))
+    }
+
+    /// Find the triangle on which lies a point, with the barycentric
+    /// coordinates of the point on that triangle
+    fn find_triangle(&self, point: &RtPoint3) -> Option<(usize, f32, f32)> {
+        let mut closest: Option<(usize, f32, f32)> = None;
+        let mut min_dist = f32::MAX;
+        for prim_id in 0..self.num_triangles() {
+            let [p0, p1, p2] = self.get_vertices(prim_id);
+            let e1 = p1 - p0;
+            let e2 = p2 - p0;
+            let n = RtVec3::cross(e1, e2);
+            let area2 = n.length_squared();
+            if area2 == 0.0 {
+                continue;
+            }
+            // Distance to the triangle plane
+            let dp = *point - p0;
+            let dist = (dp * n).abs() / area2.sqrt();
+            if dist >= min_dist {
+                continue;
+            }
+            // Barycentric coordinates of the projected point
+            let b1 = RtVec3::cross(dp, e2) * n / area2;
+            let b2 = RtVec3::cross(e1, dp) * n / area2;
+            let tolerance = RT_EPSILON;
+            if b1 >= -tolerance && b2 >= -tolerance && b1 + b2 <= 1.0 + tolerance {
+                min_dist = dist;
+                closest = Some((prim_id, b1, b2));
+            }
+        }
+        closest
+    }
+
+    pub fn get_geometric_normal(&self, prim_id: usize) -> RtVec3 {
+        let [p0, p1, p2] = self.get_vertices(prim_id);
+        RtVec3::cross(p1 - p0, p2 - p0).normalize()
+    }
+
+    /// Geometric normal flipped to agree with the shading normal
+    pub fn get_facing_normal(&self, prim_id: usize, b1: f32, b2: f32) -> RtVec3 {
+        let Ng = self.get_geometric_normal(prim_id);
+        if RtVec3::dot(Ng, self.get_shading_normal(prim_id, b1, b2)) < 0.0 { -Ng } else { Ng }
+    }
+
+    pub fn get_triangle_area(&self, prim_id: usize) -> f32 {
+        let [p0, p1, p2] = self.get_vertices(prim_id);
+        0.5 * RtVec3::cross(p1 - p0, p2 - p0).length()
+    }
+
+    /// Interpolated (smooth) normal, or geometric normal if the mesh 
+    /// has no normals
+    pub fn get_shading_normal(&self, prim_id: usize, b1: f32, b2: f32) -> RtVec3 {
+        if self.nidxs.is_empty() {
+            return self.get_geometric_normal(prim_id)
+        }
+        let [i0, i1, i2] = self.nidxs[prim_id];
+        let n = (1.0 - b1 - b2) * self.nlist[i0 as usize] + 
+            b1 * self.nlist[i1 as usize] + 
+            b2 * self.nlist[i2 as usize];
+        n.normalize()
+    }
+
+    fn get_uv(&self, prim_id: usize, b1: f32, b2: f32) -> [f32; 2] {
+        if self.uvidxs.is_empty() {
+            // Default parametrization of the triangle
+            return [b1, b2]
+        }
+        let [i0, i1, i2] = self.uvidxs[prim_id];
+        let b0 = 1.0 - b1 - b2;
+        let (uv0, uv1, uv2) = (self.uvlist[i0 as usize], self.uvlist[i1 as usize], self.uvlist[i2 as usize]);
+        [
+            b0 * uv0[0] + b1 * uv1[0] + b2 * uv2[0],
+            b0 * uv0[1] + b1 * uv1[1] + b2 * uv2[1]
+        ]
+    }
+}
+
 /// Define geometry types
 pub enum RtGeometryTypes {
-    Sphere(RtSphere)
+    Sphere(RtSphere),
+    Polymesh(RtPolymesh)
 }
 
 
@@ -63,4 +327,62 @@ impl RtObject for RtSphere {
     fn get_normal(&self, point: &RtPoint3) -> RtVec3 {
         (*point - self.center).normalize()
     }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        let r = RtVec3::new(self.radius, self.radius, self.radius);
+        RtAABB::new(self.center + -r, self.center + r)
+    }
+}
+
+impl RtObject for RtPolymesh {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit> {
+        self.get_intersection_in_range(ray, f32::INFINITY)
+    }
+
+    fn get_intersection_in_range(&self, ray: &RtRay, max_dist: f32) -> Option<RtRayHit> {
+        let mut barycentrics = (0.0, 0.0);
+        let (prim_id, t) = self.bvh.closest_hit(ray, max_dist, |prim_id, max_dist| {
+            let (t, b1, b2) = self.intersect_triangle(ray, prim_id, max_dist)?;
+            if t >= max_dist {
+                return None
+            }
+            barycentrics = (b1, b2);
+            Some(t)
+        })?;
+        let (b1, b2) = barycentrics;
+        Some(RtRayHit::on_primitive(t, ray.origin + t * ray.dir, prim_id, b1, b2))
+    }
+
+    fn is_occluding(&self, ray: &RtRay, max_dist: f32) -> bool {
+        self.bvh.any_hit(ray, max_dist, |prim_id, max_dist| {
+            self.intersect_triangle(ray, prim_id, max_dist).is_some()
+        })
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        self.bvh.bounding_box()
+    }
+
+    fn get_normal(&self, point: &RtPoint3) -> RtVec3 {
+        match self.find_triangle(point) {
+            Some((prim_id, b1, b2)) => self.get_shading_normal(prim_id, b1, b2),
+            None => RtVec3::default()
+        }
+    }
+
+    fn get_sg(&self, ray: &RtRay, hit: &RtRayHit) -> RtShaderGlobals {
+        let mut sg = RtShaderGlobals::from_intersection(
+            ray, 
+            self.get_name(),
+            hit.P.unwrap()
+        );
+        sg.N = self.get_shading_normal(hit.prim_id, hit.b1, hit.b2);
+        sg.Ng = self.get_facing_normal(hit.prim_id, hit.b1, hit.b2);
+        [sg.u, sg.v] = self.get_uv(hit.prim_id, hit.b1, hit.b2);
+        sg
+    }
 }
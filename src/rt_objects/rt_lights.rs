@@ -7,38 +7,570 @@
 
 use crate::rt_types::*;
 use crate::rt_ray::*;
+use crate::rt_sampler::*;
+use crate::rt_shader_globals::*;
 use crate::rt_objects::rt_object_base::*;
+use crate::rt_objects::rt_geometries::RtPolymesh;
+use crate::rt_image::RtImage;
+use crate::rt_sky::RtPreethamSky;
+use crate::rt_ies::RtIesProfile;
+
+
+// ========================================
+//  Light sampling
+// ========================================
+
+/// Sample of the direction from a shading point towards a light
+pub struct RtLightSample {
+    /// Normalized direction from the shading point to the light
+    pub wi: RtVec3,
+    /// Distance to the sampled point on the light
+    pub dist: f32,
+    /// Sampled point on the light
+    pub P: RtPoint3,
+    /// Primitive of the light on which lies P (e.g. triangle of a mesh light)
+    pub prim_id: usize,
+    /// Barycentric coordinates of P on the primitive
+    pub b1: f32,
+    pub b2: f32,
+    /// Density of `wi` in solid angle (1 for delta lights)
+    pub pdf: f32,
+    /// Delta lights (points) can't be hit by rays
+    pub is_delta: bool,
+    /// Scale applied to the emitted radiance, e.g. 1/d² for delta lights
+    pub scale: f32,
+    /// Incoming radiance, evaluated by RtLightsGetSample
+    pub Li: RtRGBA,
+}
+
+impl RtLightSample {
+    /// Sample of the point `light_P` seen from `P` with the density `pdf`,
+    /// None if the points are the same
+    pub fn from_points(P: &RtPoint3, light_P: RtPoint3, pdf: f32) -> Option<Self> {
+        let to_light = light_P - *P;
+        let dist = to_light.length();
+        if dist <= 0.0 {
+            return None
+        }
+        Some(Self {
+            wi: to_light / dist,
+            dist,
+            P: light_P,
+            prim_id: 0, b1: 0.0, b2: 0.0,
+            pdf,
+            is_delta: false,
+            scale: 1.0,
+            Li: RtRGBA::BLACK
+        })
+    }
+
+    /// Sample of the direction `wi` towards an infinitely far light,
+    /// `dist` is infinite and `P` is the shading point
+    pub fn from_direction(P: &RtPoint3, wi: RtVec3, pdf: f32) -> Self {
+        Self {
+            wi,
+            dist: f32::INFINITY,
+            P: *P,
+            prim_id: 0, b1: 0.0, b2: 0.0,
+            pdf,
+            is_delta: false,
+            scale: 1.0,
+            Li: RtRGBA::BLACK
+        }
+    }
+
+    /// Intersection of the shadow ray with the light
+    pub fn get_hit(&self) -> RtRayHit {
+        RtRayHit::on_primitive(self.dist, self.P, self.prim_id, self.b1, self.b2)
+    }
+}
+
+/// Lights that can be sampled for direct lighting
+pub trait RtLight: RtObject {
+    /// Sample a direction towards the light from `P`,
+    /// `u1` and `u2` are uniform samples in [0, 1)
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample>;
+
+    /// Density in solid angle of sampling `wi` from `P`, 
+    /// `hit` being the intersection of the ray (P, wi) with the light
+    fn pdf(&self, P: &RtPoint3, wi: &RtVec3, hit: &RtRayHit) -> f32;
+
+    /// Radiance emitted towards `wo` (normalized, pointing away
+    /// from the light) at the point described by `sg`
+    fn get_emission(&self, sg: &RtShaderGlobals, _wo: &RtVec3) -> RtRGBA {
+        self.get_shader().evaluate(sg).emission
+    }
+
+    /// Radiance arriving from the direction `dir` (normalized),
+    /// for the infinite lights that rays reach when they leave the scene
+    fn get_radiance(&self, _dir: &RtVec3) -> RtRGBA {
+        RtRGBA::BLACK
+    }
+
+    /// Delta lights (points) are only reached by sampling them
+    fn is_delta(&self) -> bool {
+        false
+    }
+
+    /// Infinite lights (skydomes) are not hit by rays, they
+    /// light the rays that leave the scene
+    fn is_infinite(&self) -> bool {
+        false
+    }
+}
+
+
+// ========================================
+//  Light emission
+// ========================================
+
+/// Units of the emission of the light shaders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtLightUnits {
+    /// The shader gives the emitted radiance (intensity for points)
+    #[default]
+    Radiance,
+    /// The shader gives the total emitted power, the radiance
+    /// decreases with the area of the light
+    Power,
+}
+
+impl RtLightUnits {
+    pub const ALL: [RtLightUnits; 2] = [Self::Radiance, Self::Power];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Radiance => "radiance",
+            Self::Power => "power",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|units| units.name() == name)
+    }
+}
+
+/// Strategy used to sample the points of an area light
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtLightSampling {
+    /// Uniform points on the surface of the light
+    Uniform,
+    /// Uniform directions in the solid angle covered by the light,
+    /// less noisy close to the light. Only used by the quad and
+    /// sphere lights, the others sample their area.
+    #[default]
+    SolidAngle,
+}
+
+impl RtLightSampling {
+    pub const ALL: [RtLightSampling; 2] = [Self::Uniform, Self::SolidAngle];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Uniform => "uniform",
+            Self::SolidAngle => "solid_angle",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|sampling| sampling.name() == name)
+    }
+}
+
+/// How an area light emits the radiance of its shader
+#[derive(Debug, Clone, Copy)]
+pub struct RtEmission {
+    pub units: RtLightUnits,
+    /// Emit from both sides of the surface, instead of the side of its normal
+    pub two_sided: bool,
+    /// Angular spread of the emission, in (0, 1]. 1 is a diffuse emitter,
+    /// lower values focus the light around the normal.
+    pub spread: f32,
+}
+
+impl Default for RtEmission {
+    fn default() -> Self {
+        Self { units: RtLightUnits::default(), two_sided: false, spread: 1.0 }
+    }
+}
+
+impl RtEmission {
+    /// Is the light emitting in a direction at `cos_theta` from its normal
+    pub fn is_emitting(&self, cos_theta: f32) -> bool {
+        cos_theta > 0.0 || (self.two_sided && cos_theta < 0.0)
+    }
+
+    /// Scale of the shader emission in a direction at `cos_theta` from
+    /// the normal of a light of area `area`
+    ///
+    /// The spread gives a cos^k profile, normalized to keep the power
+    /// of the light, so the power units give the same total power.
+    pub fn eval(&self, cos_theta: f32, area: f32) -> f32 {
+        if !self.is_emitting(cos_theta) {
+            return 0.0
+        }
+        let exponent = 1.0 / self.spread.clamp(0.001, 1.0) - 1.0;
+        let profile = if exponent > 0.0 {
+            cos_theta.abs().powf(exponent) * (exponent + 2.0) * 0.5
+        } else {
+            1.0
+        };
+        match self.units {
+            RtLightUnits::Radiance => profile,
+            RtLightUnits::Power => {
+                let sides = if self.two_sided { 2.0 } else { 1.0 };
+                profile / (RT_PI * area * sides)
+            }
+        }
+    }
+}
 
 
 // ========================================
 //  Define objects
 // ========================================
 
+/// Skydome light
+/// 
+/// Infinitely far sphere lighting the scene from all directions, the 
+/// emission of its shader is multiplied by a lat-long environment map. 
+/// The center of the map faces -z and its top row +y. The map is 
+/// importance sampled according to the luminance of its pixels.
 pub struct RtSkydomeLight {
     pub object_params: ObjectParams,
-    pub color: RtRGBA,
-    pub intensity: f32
+    /// Lat-long environment map, a white pixel without map
+    map: RtImage,
+    /// Directions sampled according to the luminance of the map
+    directions: RtLatLongDistribution,
 }
 
 impl RtSkydomeLight {
     const _RT_TYPE: &'static str = "<RtLight : Skydome>";
+
+    /// Skydome with the map rotated by `rotation` degrees around the y axis
+    pub fn new(object_params: ObjectParams, map: Option<RtImage>, rotation: f32) -> Self {
+        let map = map.unwrap_or_else(|| RtImage::new(1, 1, RtRGBA::WHITE));
+        let weights: Vec<f32> = map.pixels.iter().map(|pixel| pixel.luminance()).collect();
+        let directions = RtLatLongDistribution::new(&weights, map.width, map.height, rotation);
+        Self { object_params, map, directions }
+    }
+}
+
+/// Physical sky light
+/// 
+/// Daylight sky of the Preetham model, for a sun at `sun_elevation` 
+/// degrees over the horizon and `sun_azimuth` degrees from -z towards +x.
+/// The emission of the shader scales the sky. The sun is a separate 
+/// distant light, given by `get_sun_light`, so that the sky and the 
+/// sun are sampled separately.
+pub struct RtPhysicalSkyLight {
+    pub object_params: ObjectParams,
+    pub sky: RtPreethamSky,
+    /// Directions sampled according to the luminance of the sky
+    directions: RtLatLongDistribution,
+}
+
+impl RtPhysicalSkyLight {
+    const _RT_TYPE: &'static str = "<RtLight : PhysicalSky>";
+
+    /// Resolution of the table of the sky used for sampling
+    const SAMPLING_WIDTH: usize = 128;
+    const SAMPLING_HEIGHT: usize = 64;
+
+    pub fn new(object_params: ObjectParams, sun_elevation: f32, sun_azimuth: f32, turbidity: f32) -> Self {
+        let sky = RtPreethamSky::new(Self::get_sun_direction(sun_elevation, sun_azimuth), turbidity);
+        let (width, height) = (Self::SAMPLING_WIDTH, Self::SAMPLING_HEIGHT);
+        let weights: Vec<f32> = (0..width * height)
+            .map(|index| {
+                let u = ((index % width) as f32 + 0.5) / width as f32;
+                let v = ((index / width) as f32 + 0.5) / height as f32;
+                let (dir, _) = RtLatLongDistribution::get_direction(u, v, 0.0);
+                sky.get_radiance(&dir).luminance()
+            })
+            .collect();
+        let directions = RtLatLongDistribution::new(&weights, width, height, 0.0);
+        Self { object_params, sky, directions }
+    }
+
+    /// Direction towards a sun at `elevation` and `azimuth` degrees
+    pub fn get_sun_direction(elevation: f32, azimuth: f32) -> RtVec3 {
+        let (elevation, azimuth) = (elevation.to_radians(), azimuth.to_radians());
+        RtVec3::new(elevation.cos() * azimuth.sin(), elevation.sin(), -elevation.cos() * azimuth.cos())
+    }
+
+    /// Sun matching the sky, with an angular diameter of `angle` degrees.
+    /// It shares the shader of the sky.
+    pub fn get_sun_light(&self, name: String, angle: f32) -> RtDistantLight {
+        let object_params = ObjectParams::new(name, String::from("sun"), self.get_shader().clone_dyn());
+        RtDistantLight {
+            object_params,
+            direction: self.sky.sun_dir,
+            angle,
+            irradiance: self.sky.get_sun_irradiance(),
+        }
+    }
+}
+
+/// Distant light
+/// 
+/// Light infinitely far in the direction `direction`, covering a disk
+/// of `angle` degrees of angular diameter, as the sun does. An angle of
+/// 0 gives parallel rays. The emission of the shader times `irradiance` 
+/// is the irradiance of surfaces facing the light.
+pub struct RtDistantLight {
+    pub object_params: ObjectParams,
+    /// Normalized direction towards the light
+    pub direction: RtVec3,
+    pub angle: f32,
+    pub irradiance: RtRGBA,
+}
+
+impl RtDistantLight {
+    const _RT_TYPE: &'static str = "<RtLight : Distant>";
+
+    /// sin² and cosine of the half angle of the cone covered by the light
+    fn get_cone(&self) -> (f32, f32) {
+        let half_angle = 0.5 * self.angle.to_radians();
+        (half_angle.sin() * half_angle.sin(), half_angle.cos())
+    }
+
+    /// Irradiance of surfaces facing the light
+    fn get_irradiance(&self, dir: &RtVec3) -> RtRGBA {
+        infinite_light_emission(self, dir) * self.irradiance
+    }
 }
 
 /// Point light
+/// 
+/// Spherical light of radius `radius`, a radius of 0 gives a 
+/// point light where the shader color is the intensity.
 pub struct RtPointLight {
     pub object_params: ObjectParams,
     pub center: RtPoint3,
-    pub radius: f32
+    pub radius: f32,
+    pub emission: RtEmission,
+    pub sampling: RtLightSampling,
 }
 
 impl RtPointLight {
     const _RT_TYPE: &'static str = "<RtLight : Point>";
+
+    /// sin² of the half angle of the cone of the sphere seen from P,
+    /// None if P is inside the sphere
+    fn get_sin2_max(&self, P: &RtPoint3) -> Option<f32> {
+        let dist2 = (self.center - *P).length_squared();
+        let radius2 = self.radius * self.radius;
+        if dist2 <= radius2 { None } else { Some(radius2 / dist2) }
+    }
+
+    fn get_area(&self) -> f32 {
+        4.0 * RT_PI * self.radius * self.radius
+    }
+}
+
+/// Spot light
+/// 
+/// Point light at `position` shining towards `direction` inside a cone
+/// of `cone_angle` degrees, fading out over the last `penumbra_angle` 
+/// degrees of the cone. The shader color is the intensity in the axis 
+/// of the cone, or the power of the light with the power units.
+pub struct RtSpotLight {
+    pub object_params: ObjectParams,
+    pub position: RtPoint3,
+    /// Normalized axis of the cone
+    pub direction: RtVec3,
+    pub cone_angle: f32,
+    pub penumbra_angle: f32,
+    pub emission: RtEmission,
+}
+
+impl RtSpotLight {
+    const _RT_TYPE: &'static str = "<RtLight : Spot>";
+
+    /// Cosines of the half angles of the cone and of its part without penumbra
+    fn get_cone_cosines(&self) -> (f32, f32) {
+        let outer = 0.5 * self.cone_angle.clamp(0.0, 360.0).to_radians();
+        let inner = (outer - self.penumbra_angle.max(0.0).to_radians()).max(0.0);
+        (outer.cos(), inner.cos())
+    }
+
+    /// Fraction of the intensity at `cos_theta` from the axis
+    fn get_falloff(&self, cos_theta: f32) -> f32 {
+        let (cos_outer, cos_inner) = self.get_cone_cosines();
+        if cos_theta <= cos_outer {
+            return 0.0
+        }
+        if cos_theta >= cos_inner {
+            return 1.0
+        }
+        let t = (cos_theta - cos_outer) / (cos_inner - cos_outer);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// Solid angle of the cone, weighted by the falloff
+    fn get_solid_angle(&self) -> f32 {
+        let (cos_outer, cos_inner) = self.get_cone_cosines();
+        // The falloff averages to 1/2 over the penumbra
+        2.0 * RT_PI * ((1.0 - cos_inner) + 0.5 * (cos_inner - cos_outer))
+    }
+}
+
+/// IES light
+/// 
+/// Point light at `position` with the intensity profile of an IES file,
+/// the nadir of the profile pointing towards `direction`. The horizontal
+/// angle 0 of the profile follows the first vector of the coordinate 
+/// system of `direction` (+x for a light pointing down). The profile is
+/// normalized to a peak of 1 : the shader color is the peak intensity,
+/// or the power of the light with the power units.
+pub struct RtIesLight {
+    pub object_params: ObjectParams,
+    pub position: RtPoint3,
+    /// Normalized direction of the nadir of the profile
+    pub direction: RtVec3,
+    pub emission: RtEmission,
+    profile: RtIesProfile,
+    /// Peak intensity and flux of the profile
+    max_candela: f32,
+    flux: f32,
+}
+
+impl RtIesLight {
+    const _RT_TYPE: &'static str = "<RtLight : IES>";
+
+    pub fn new(object_params: ObjectParams, position: RtPoint3, direction: RtVec3, 
+               profile: RtIesProfile, emission: RtEmission) -> Self {
+        let (max_candela, flux) = (profile.max_candela(), profile.flux());
+        Self { object_params, position, direction, emission, profile, max_candela, flux }
+    }
+
+    /// Intensity of the profile towards `wo` (normalized), relative to its peak
+    fn get_profile(&self, wo: &RtVec3) -> f32 {
+        if self.max_candela <= 0.0 {
+            return 0.0
+        }
+        let (t, b) = self.direction.coordinate_system();
+        let vertical = RtVec3::dot(*wo, self.direction).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = RtVec3::dot(*wo, b).atan2(RtVec3::dot(*wo, t)).to_degrees();
+        self.profile.eval(vertical, horizontal) / self.max_candela
+    }
+}
+
+/// Quad light
+/// 
+/// Parallelogram from `corner` along the edges `edge_u` and `edge_v`,
+/// emitting on the side of edge_u x edge_v.
+pub struct RtQuadLight {
+    pub object_params: ObjectParams,
+    pub corner: RtPoint3,
+    pub edge_u: RtVec3,
+    pub edge_v: RtVec3,
+    pub emission: RtEmission,
+    pub sampling: RtLightSampling,
+}
+
+impl RtQuadLight {
+    const _RT_TYPE: &'static str = "<RtLight : Quad>";
+
+    fn get_normal_vector(&self) -> RtVec3 {
+        RtVec3::cross(self.edge_u, self.edge_v)
+    }
+
+    fn get_area(&self) -> f32 {
+        self.get_normal_vector().length()
+    }
+
+    /// Spherical rectangle seen from P, when it is used for sampling
+    fn get_spherical_rectangle(&self, P: &RtPoint3) -> Option<RtSphericalRectangle> {
+        if self.sampling != RtLightSampling::SolidAngle {
+            return None
+        }
+        // Only rectangles map to spherical rectangles
+        let (len_u, len_v) = (self.edge_u.length(), self.edge_v.length());
+        if RtVec3::dot(self.edge_u, self.edge_v).abs() > RT_EPSILON * len_u * len_v {
+            return None
+        }
+        RtSphericalRectangle::new(P, &self.corner, &self.edge_u, &self.edge_v)
+    }
+}
+
+/// Disk light
+/// 
+/// Disk of radius `radius` around `center`, emitting on the side of `normal`.
+pub struct RtDiskLight {
+    pub object_params: ObjectParams,
+    pub center: RtPoint3,
+    /// Normalized normal
+    pub normal: RtVec3,
+    pub radius: f32,
+    pub emission: RtEmission,
+}
+
+impl RtDiskLight {
+    const _RT_TYPE: &'static str = "<RtLight : Disk>";
+
+    fn get_area(&self) -> f32 {
+        RT_PI * self.radius * self.radius
+    }
+}
+
+/// Mesh light
+/// 
+/// Emitting polymesh, the triangles are sampled according to their area.
+/// Emits on the side of the normals of the mesh.
+pub struct RtMeshLight {
+    pub mesh: RtPolymesh,
+    pub emission: RtEmission,
+    /// Cumulated area of the triangles
+    triangles_cdf: Vec<f32>,
+}
+
+impl RtMeshLight {
+    const _RT_TYPE: &'static str = "<RtLight : Mesh>";
+
+    pub fn new(mesh: RtPolymesh, emission: RtEmission) -> Self {
+        let triangles_cdf = (0..mesh.num_triangles())
+            .scan(0.0, |area, prim_id| {
+                *area += mesh.get_triangle_area(prim_id);
+                Some(*area)
+            })
+            .collect();
+        Self { mesh, emission, triangles_cdf }
+    }
+
+    fn get_area(&self) -> f32 {
+        self.triangles_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Pick a triangle according to its area,
+    /// returns it with u remapped to [0, 1)
+    fn pick_triangle(&self, u: f32) -> Option<(usize, f32)> {
+        let area = self.get_area();
+        if area <= 0.0 {
+            return None
+        }
+        let target = u * area;
+        let prim_id = self.triangles_cdf.partition_point(|&cdf| cdf <= target)
+            .min(self.triangles_cdf.len() - 1);
+        let start = if prim_id > 0 { self.triangles_cdf[prim_id - 1] } else { 0.0 };
+        let u = (target - start) / (self.triangles_cdf[prim_id] - start);
+        Some((prim_id, u.clamp(0.0, 1.0 - f32::EPSILON)))
+    }
 }
 
 /// Defines light types
 pub enum RtLightTypes {
     Skydome(RtSkydomeLight),
-    Point(RtPointLight)
+    PhysicalSky(RtPhysicalSkyLight),
+    Distant(RtDistantLight),
+    Point(RtPointLight),
+    Spot(RtSpotLight),
+    Ies(RtIesLight),
+    Quad(RtQuadLight),
+    Disk(RtDiskLight),
+    Mesh(RtMeshLight)
 }
 
 
@@ -50,7 +582,7 @@ impl RtObject for RtPointLight {
     fn getObjectParams(&self) -> &ObjectParams {
         &self.object_params
     }
-    
+
     fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit> {
         let a = RtVec3::dot(ray.dir, ray.dir);
         let b = 2.0 * RtVec3::dot(ray.dir, ray.origin - self.center);
@@ -75,4 +607,938 @@ impl RtObject for RtPointLight {
     fn get_normal(&self, point: &RtPoint3) -> RtVec3 {
         (*point - self.center).normalize()
     }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        let r = RtVec3::new(self.radius, self.radius, self.radius);
+        RtAABB::new(self.center + -r, self.center + r)
+    }
+}
+
+impl RtLight for RtPointLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        let to_center = self.center - *P;
+        let dist_center = to_center.length();
+        let axis = to_center / dist_center;
+
+        if self.is_delta() {
+            let mut sample = RtLightSample::from_points(P, self.center, 1.0)?;
+            sample.is_delta = true;
+            sample.scale = 1.0 / (dist_center * dist_center);
+            return Some(sample)
+        }
+
+        if self.sampling == RtLightSampling::Uniform {
+            // Uniform point on the sphere, the back of the sphere is hidden by its front
+            let z = 1.0 - 2.0 * u1;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let phi = 2.0 * RT_PI * u2;
+            let n = RtVec3::new(r * phi.cos(), r * phi.sin(), z);
+            let mut sample = RtLightSample::from_points(P, self.center + self.radius * n, 0.0)?;
+            let cos_light = RtVec3::dot(n, -sample.wi);
+            if cos_light <= 0.0 {
+                return None
+            }
+            sample.pdf = sample.dist * sample.dist / (cos_light * self.get_area());
+            return Some(sample)
+        }
+
+        // Sample the cone of directions covered by the sphere
+        let sin2_max = self.get_sin2_max(P)?;
+        let cos_max = (1.0 - sin2_max).max(0.0).sqrt();
+        let wi = sample_uniform_cone(&axis, cos_max, u1, u2);
+        // Distance to the sphere along wi
+        let cos_theta = RtVec3::dot(wi, axis);
+        let sin2_theta = (1.0 - cos_theta * cos_theta).max(0.0);
+        let radius2 = self.radius * self.radius;
+        let dist = dist_center * cos_theta - (radius2 - dist_center * dist_center * sin2_theta).max(0.0).sqrt();
+        let mut sample = RtLightSample::from_points(P, *P + dist * wi, cone_pdf(sin2_max, cos_max))?;
+        // Keep the sampled direction and distance rather than the rounded ones
+        (sample.wi, sample.dist) = (wi, dist);
+        Some(sample)
+    }
+
+    fn pdf(&self, P: &RtPoint3, wi: &RtVec3, hit: &RtRayHit) -> f32 {
+        if self.is_delta() {
+            return 0.0
+        }
+        if self.sampling == RtLightSampling::Uniform {
+            let Some(light_P) = hit.P else { return 0.0 };
+            let cos_light = RtVec3::dot(self.get_normal(&light_P), -*wi);
+            if cos_light <= 0.0 {
+                return 0.0
+            }
+            return (light_P - *P).length_squared() / (cos_light * self.get_area())
+        }
+        match self.get_sin2_max(P) {
+            Some(sin2_max) => cone_pdf(sin2_max, (1.0 - sin2_max).max(0.0).sqrt()),
+            None => 0.0
+        }
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        let emission = self.get_shader().evaluate(sg).emission;
+        if self.is_delta() {
+            // Isotropic intensity
+            return match self.emission.units {
+                RtLightUnits::Radiance => emission,
+                RtLightUnits::Power => emission * (0.25 * RT_ONEOVERPI),
+            }
+        }
+        emission * self.emission.eval(RtVec3::dot(sg.Ng, *wo), self.get_area())
+    }
+
+    fn is_delta(&self) -> bool {
+        self.radius <= 0.0
+    }
+}
+
+impl RtObject for RtSpotLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, _ray: &RtRay) -> Option<RtRayHit> {
+        None
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        self.direction
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::new(self.position, self.position)
+    }
+}
+
+impl RtLight for RtSpotLight {
+    fn sample(&self, P: &RtPoint3, _u1: f32, _u2: f32) -> Option<RtLightSample> {
+        let mut sample = RtLightSample::from_points(P, self.position, 1.0)?;
+        sample.is_delta = true;
+        sample.scale = 1.0 / (sample.dist * sample.dist);
+        Some(sample)
+    }
+
+    fn pdf(&self, _P: &RtPoint3, _wi: &RtVec3, _hit: &RtRayHit) -> f32 {
+        0.0
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        let emission = self.get_shader().evaluate(sg).emission * self.get_falloff(RtVec3::dot(*wo, self.direction));
+        match self.emission.units {
+            RtLightUnits::Radiance => emission,
+            RtLightUnits::Power => {
+                let solid_angle = self.get_solid_angle();
+                if solid_angle > 0.0 { emission / solid_angle } else { RtRGBA::BLACK }
+            }
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+impl RtObject for RtIesLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, _ray: &RtRay) -> Option<RtRayHit> {
+        None
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        self.direction
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::new(self.position, self.position)
+    }
+}
+
+impl RtLight for RtIesLight {
+    fn sample(&self, P: &RtPoint3, _u1: f32, _u2: f32) -> Option<RtLightSample> {
+        let mut sample = RtLightSample::from_points(P, self.position, 1.0)?;
+        sample.is_delta = true;
+        sample.scale = 1.0 / (sample.dist * sample.dist);
+        Some(sample)
+    }
+
+    fn pdf(&self, _P: &RtPoint3, _wi: &RtVec3, _hit: &RtRayHit) -> f32 {
+        0.0
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        let emission = self.get_shader().evaluate(sg).emission * self.get_profile(wo);
+        match self.emission.units {
+            RtLightUnits::Radiance => emission,
+            // Flux of the normalized profile
+            RtLightUnits::Power if self.flux > 0.0 => emission * (self.max_candela / self.flux),
+            RtLightUnits::Power => RtRGBA::BLACK,
+        }
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+impl RtObject for RtSkydomeLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, _ray: &RtRay) -> Option<RtRayHit> {
+        None
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        RtVec3::default()
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::EMPTY
+    }
+}
+
+impl RtLight for RtSkydomeLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        let (wi, pdf) = self.directions.sample(u1, u2)?;
+        Some(RtLightSample::from_direction(P, wi, pdf))
+    }
+
+    fn pdf(&self, _P: &RtPoint3, wi: &RtVec3, _hit: &RtRayHit) -> f32 {
+        self.directions.pdf(wi)
+    }
+
+    fn get_radiance(&self, dir: &RtVec3) -> RtRGBA {
+        let (u, v) = self.directions.get_uv(dir);
+        infinite_light_emission(self, dir) * self.map.lookup(u, v)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+impl RtObject for RtPhysicalSkyLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, _ray: &RtRay) -> Option<RtRayHit> {
+        None
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        RtVec3::default()
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::EMPTY
+    }
+}
+
+impl RtLight for RtPhysicalSkyLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        let (wi, pdf) = self.directions.sample(u1, u2)?;
+        Some(RtLightSample::from_direction(P, wi, pdf))
+    }
+
+    fn pdf(&self, _P: &RtPoint3, wi: &RtVec3, _hit: &RtRayHit) -> f32 {
+        self.directions.pdf(wi)
+    }
+
+    fn get_radiance(&self, dir: &RtVec3) -> RtRGBA {
+        infinite_light_emission(self, dir) * self.sky.get_radiance(dir)
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+impl RtObject for RtDistantLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, _ray: &RtRay) -> Option<RtRayHit> {
+        None
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        -self.direction
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::EMPTY
+    }
+}
+
+impl RtLight for RtDistantLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        if self.is_delta() {
+            let mut sample = RtLightSample::from_direction(P, self.direction, 1.0);
+            sample.is_delta = true;
+            return Some(sample)
+        }
+        let (sin2_max, cos_max) = self.get_cone();
+        let wi = sample_uniform_cone(&self.direction, cos_max, u1, u2);
+        Some(RtLightSample::from_direction(P, wi, cone_pdf(sin2_max, cos_max)))
+    }
+
+    fn pdf(&self, _P: &RtPoint3, wi: &RtVec3, _hit: &RtRayHit) -> f32 {
+        let (sin2_max, cos_max) = self.get_cone();
+        if self.is_delta() || RtVec3::dot(*wi, self.direction) < cos_max {
+            return 0.0
+        }
+        cone_pdf(sin2_max, cos_max)
+    }
+
+    /// Radiance inside the disk of the light. Delta lights
+    /// return their irradiance, as their samples have a pdf of 1.
+    fn get_radiance(&self, dir: &RtVec3) -> RtRGBA {
+        if self.is_delta() {
+            return self.get_irradiance(dir)
+        }
+        let (sin2_max, cos_max) = self.get_cone();
+        if RtVec3::dot(*dir, self.direction) < cos_max {
+            return RtRGBA::BLACK
+        }
+        // Uniform radiance over the solid angle of the disk
+        self.get_irradiance(dir) * cone_pdf(sin2_max, cos_max)
+    }
+
+    fn is_delta(&self) -> bool {
+        self.angle <= 0.0
+    }
+
+    fn is_infinite(&self) -> bool {
+        true
+    }
+}
+
+impl RtObject for RtQuadLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit> {
+        let n = self.get_normal_vector();
+        let denom = RtVec3::dot(n, ray.dir);
+        if denom == 0.0 {
+            return None
+        }
+        let t = RtVec3::dot(n, self.corner - ray.origin) / denom;
+        if t < 0.0 {
+            return None
+        }
+        // Coordinates of the hit along the edges
+        let P = ray.origin + t * ray.dir;
+        let d = P - self.corner;
+        let n2 = n.length_squared();
+        let a = RtVec3::dot(RtVec3::cross(d, self.edge_v), n) / n2;
+        let b = RtVec3::dot(RtVec3::cross(self.edge_u, d), n) / n2;
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None
+        }
+        Some(RtRayHit::new(true, t, P))
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        self.get_normal_vector().normalize()
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        RtAABB::from_points(&[
+            self.corner,
+            self.corner + self.edge_u,
+            self.corner + self.edge_v,
+            self.corner + self.edge_u + self.edge_v,
+        ])
+    }
+}
+
+impl RtLight for RtQuadLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        let n = self.get_normal(P);
+        if !self.emission.is_emitting(RtVec3::dot(n, *P - self.corner)) {
+            return None
+        }
+        if let Some(rectangle) = self.get_spherical_rectangle(P) {
+            return RtLightSample::from_points(P, rectangle.sample(u1, u2), 1.0 / rectangle.solid_angle)
+        }
+        let mut sample = RtLightSample::from_points(P, self.corner + u1 * self.edge_u + u2 * self.edge_v, 0.0)?;
+        let cos_light = RtVec3::dot(n, -sample.wi).abs();
+        if cos_light <= 0.0 {
+            return None
+        }
+        sample.pdf = sample.dist * sample.dist / (cos_light * self.get_area());
+        Some(sample)
+    }
+
+    fn pdf(&self, P: &RtPoint3, wi: &RtVec3, hit: &RtRayHit) -> f32 {
+        let n = self.get_normal(P);
+        let cos_light = RtVec3::dot(n, -*wi);
+        if !self.emission.is_emitting(cos_light) {
+            return 0.0
+        }
+        if let Some(rectangle) = self.get_spherical_rectangle(P) {
+            return 1.0 / rectangle.solid_angle
+        }
+        let Some(light_P) = hit.P else { return 0.0 };
+        (light_P - *P).length_squared() / (cos_light.abs() * self.get_area())
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        self.get_shader().evaluate(sg).emission * self.emission.eval(RtVec3::dot(sg.Ng, *wo), self.get_area())
+    }
+}
+
+impl RtObject for RtDiskLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        &self.object_params
+    }
+
+    fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit> {
+        let denom = RtVec3::dot(self.normal, ray.dir);
+        if denom == 0.0 {
+            return None
+        }
+        let t = RtVec3::dot(self.normal, self.center - ray.origin) / denom;
+        if t < 0.0 {
+            return None
+        }
+        let P = ray.origin + t * ray.dir;
+        if (P - self.center).length_squared() > self.radius * self.radius {
+            return None
+        }
+        Some(RtRayHit::new(true, t, P))
+    }
+
+    fn get_normal(&self, _point: &RtPoint3) -> RtVec3 {
+        self.normal
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        let r = RtVec3::new(self.radius, self.radius, self.radius);
+        RtAABB::new(self.center + -r, self.center + r)
+    }
+}
+
+impl RtLight for RtDiskLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        if !self.emission.is_emitting(RtVec3::dot(self.normal, *P - self.center)) {
+            return None
+        }
+        let (x, y) = sample_concentric_disk(u1, u2);
+        let (t, b) = self.normal.coordinate_system();
+        let light_P = self.center + (self.radius * x) * t + (self.radius * y) * b;
+        let mut sample = RtLightSample::from_points(P, light_P, 0.0)?;
+        let cos_light = RtVec3::dot(self.normal, -sample.wi).abs();
+        if cos_light <= 0.0 {
+            return None
+        }
+        sample.pdf = sample.dist * sample.dist / (cos_light * self.get_area());
+        Some(sample)
+    }
+
+    fn pdf(&self, P: &RtPoint3, wi: &RtVec3, hit: &RtRayHit) -> f32 {
+        let cos_light = RtVec3::dot(self.normal, -*wi);
+        if !self.emission.is_emitting(cos_light) {
+            return 0.0
+        }
+        let Some(light_P) = hit.P else { return 0.0 };
+        (light_P - *P).length_squared() / (cos_light.abs() * self.get_area())
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        self.get_shader().evaluate(sg).emission * self.emission.eval(RtVec3::dot(sg.Ng, *wo), self.get_area())
+    }
+}
+
+impl RtObject for RtMeshLight {
+    fn getObjectParams(&self) -> &ObjectParams {
+        self.mesh.getObjectParams()
+    }
+
+    fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit> {
+        self.mesh.get_intersection(ray)
+    }
+
+    fn get_intersection_in_range(&self, ray: &RtRay, max_dist: f32) -> Option<RtRayHit> {
+        self.mesh.get_intersection_in_range(ray, max_dist)
+    }
+
+    fn is_occluding(&self, ray: &RtRay, max_dist: f32) -> bool {
+        self.mesh.is_occluding(ray, max_dist)
+    }
+
+    fn get_bounding_box(&self) -> RtAABB {
+        self.mesh.get_bounding_box()
+    }
+
+    fn get_normal(&self, point: &RtPoint3) -> RtVec3 {
+        self.mesh.get_normal(point)
+    }
+
+    fn get_sg(&self, ray: &RtRay, hit: &RtRayHit) -> RtShaderGlobals {
+        self.mesh.get_sg(ray, hit)
+    }
+}
+
+impl RtLight for RtMeshLight {
+    fn sample(&self, P: &RtPoint3, u1: f32, u2: f32) -> Option<RtLightSample> {
+        // Uniform point on a triangle picked according to its area
+        let (prim_id, u1) = self.pick_triangle(u1)?;
+        let su1 = u1.sqrt();
+        let (b1, b2) = (su1 * (1.0 - u2), su1 * u2);
+        let [p0, p1, p2] = self.mesh.get_vertices(prim_id);
+        let light_P = p0 + b1 * (p1 - p0) + b2 * (p2 - p0);
+        let mut sample = RtLightSample::from_points(P, light_P, 0.0)?;
+        let cos_light = RtVec3::dot(self.mesh.get_facing_normal(prim_id, b1, b2), -sample.wi);
+        if !self.emission.is_emitting(cos_light) {
+            return None
+        }
+        sample.pdf = sample.dist * sample.dist / (cos_light.abs() * self.get_area());
+        (sample.prim_id, sample.b1, sample.b2) = (prim_id, b1, b2);
+        Some(sample)
+    }
+
+    fn pdf(&self, P: &RtPoint3, wi: &RtVec3, hit: &RtRayHit) -> f32 {
+        let Some(light_P) = hit.P else { return 0.0 };
+        let cos_light = RtVec3::dot(self.mesh.get_facing_normal(hit.prim_id, hit.b1, hit.b2), -*wi);
+        if !self.emission.is_emitting(cos_light) {
+            return 0.0
+        }
+        (light_P - *P).length_squared() / (cos_light.abs() * self.get_area())
+    }
+
+    fn get_emission(&self, sg: &RtShaderGlobals, wo: &RtVec3) -> RtRGBA {
+        self.get_shader().evaluate(sg).emission * self.emission.eval(RtVec3::dot(sg.Ng, *wo), self.get_area())
+    }
+}
+
+
+// ========================================
+//  Sampling utilities
+// ========================================
+
+/// Emission of the shader of an infinite light in the direction `dir`
+fn infinite_light_emission(light: &dyn RtLight, dir: &RtVec3) -> RtRGBA {
+    let mut sg = RtShaderGlobals::default(0, 0);
+    sg.ray_dir = *dir;
+    light.get_shader().evaluate(&sg).emission
+}
+
+/// Distribution of the directions in lat-long coordinates (u, v), 
+/// u turning around the y axis (-z at 0.5) and v going from +y to -y
+struct RtLatLongDistribution {
+    /// Rotation around the y axis, in degrees
+    rotation: f32,
+    distribution: RtDistribution2D,
+}
+
+impl RtLatLongDistribution {
+    /// Directions weighted by the `nu` x `nv` values of `weights`,
+    /// stored row by row from the top
+    fn new(weights: &[f32], nu: usize, nv: usize, rotation: f32) -> Self {
+        // Rows are smaller close to the poles
+        let weights: Vec<f32> = weights.iter().enumerate()
+            .map(|(index, weight)| weight * (RT_PI * ((index / nu) as f32 + 0.5) / nv as f32).sin())
+            .collect();
+        Self { rotation, distribution: RtDistribution2D::new(&weights, nu, nv) }
+    }
+
+    /// Lat-long coordinates of a direction
+    fn get_uv(&self, dir: &RtVec3) -> (f32, f32) {
+        let phi = dir.x.atan2(-dir.z) - self.rotation.to_radians();
+        // atan2 rather than acos(y), that loses the precision close to the poles
+        let theta = (dir.x * dir.x + dir.z * dir.z).sqrt().atan2(dir.y);
+        ((phi / (2.0 * RT_PI) + 0.5).rem_euclid(1.0), theta / RT_PI)
+    }
+
+    /// Direction of lat-long coordinates, with the sine of its angle to the y axis
+    fn get_direction(u: f32, v: f32, rotation: f32) -> (RtVec3, f32) {
+        let phi = 2.0 * RT_PI * (u - 0.5) + rotation.to_radians();
+        let theta = RT_PI * v;
+        let sin_theta = theta.sin();
+        (RtVec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos()), sin_theta)
+    }
+
+    /// Sampled direction and its density in solid angle
+    fn sample(&self, u1: f32, u2: f32) -> Option<(RtVec3, f32)> {
+        let ((u, v), pdf_uv) = self.distribution.sample(u1, u2);
+        let (wi, sin_theta) = Self::get_direction(u, v, self.rotation);
+        if pdf_uv <= 0.0 || sin_theta <= 0.0 {
+            return None
+        }
+        // Density of the lat-long coordinates to density in solid angle
+        Some((wi, pdf_uv / (2.0 * RT_PI * RT_PI * sin_theta)))
+    }
+
+    fn pdf(&self, dir: &RtVec3) -> f32 {
+        let (u, v) = self.get_uv(dir);
+        let sin_theta = (RT_PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0
+        }
+        self.distribution.pdf(u, v) / (2.0 * RT_PI * RT_PI * sin_theta)
+    }
+}
+
+/// Density of uniform cone sampling, 1 - cos_max is computed 
+/// from sin² to stay accurate for small cones
+fn cone_pdf(sin2_max: f32, cos_max: f32) -> f32 {
+    let one_minus_cos_max = sin2_max / (1.0 + cos_max);
+    1.0 / (2.0 * RT_PI * one_minus_cos_max)
+}
+
+/// Rectangle projected on the unit sphere around a point
+/// 
+/// From "An Area-Preserving Parametrization for Spherical Rectangles",
+/// Ureña, Fajardo & King (2013).
+struct RtSphericalRectangle {
+    origin: RtPoint3,
+    /// Local frame, z points away from the rectangle
+    x: RtVec3,
+    y: RtVec3,
+    z: RtVec3,
+    /// Rectangle bounds in the local frame
+    x0: f32, x1: f32,
+    y0: f32, y1: f32,
+    z0: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+    solid_angle: f32,
+}
+
+impl RtSphericalRectangle {
+    /// Under this solid angle, single precision is not enough
+    /// and the rectangle should be sampled by area
+    const MIN_SOLID_ANGLE: f32 = 1e-3;
+
+    /// Rectangle from `corner` along the perpendicular edges
+    /// `edge_u` and `edge_v`, seen from `origin`
+    fn new(origin: &RtPoint3, corner: &RtPoint3, edge_u: &RtVec3, edge_v: &RtVec3) -> Option<Self> {
+        use std::f32::consts::PI;
+        let (len_u, len_v) = (edge_u.length(), edge_v.length());
+        let x = *edge_u / len_u;
+        let y = *edge_v / len_v;
+        let mut z = RtVec3::cross(x, y);
+        let d = *corner - *origin;
+        let mut z0 = RtVec3::dot(d, z);
+        if z0 > 0.0 {
+            z = -z;
+            z0 = -z0;
+        }
+        let x0 = RtVec3::dot(d, x);
+        let y0 = RtVec3::dot(d, y);
+        let (x1, y1) = (x0 + len_u, y0 + len_v);
+
+        // Normals of the planes through the origin and the edges
+        let n0 = RtVec3::new(0.0, z0, -y0).normalize();
+        let n1 = RtVec3::new(-z0, 0.0, x1).normalize();
+        let n2 = RtVec3::new(0.0, -z0, y1).normalize();
+        let n3 = RtVec3::new(z0, 0.0, -x0).normalize();
+        // Internal angles of the spherical rectangle
+        let angle = |a: RtVec3, b: RtVec3| (-RtVec3::dot(a, b)).clamp(-1.0, 1.0).acos();
+        let (g0, g1, g2, g3) = (angle(n0, n1), angle(n1, n2), angle(n2, n3), angle(n3, n0));
+        let k = 2.0 * PI - g2 - g3;
+        let solid_angle = g0 + g1 - k;
+        if solid_angle.is_nan() || solid_angle < Self::MIN_SOLID_ANGLE {
+            return None
+        }
+        Some(Self { origin: *origin, x, y, z, x0, x1, y0, y1, z0, b0: n0.z, b1: n2.z, k, solid_angle })
+    }
+
+    /// Point of the rectangle, uniform in solid angle
+    fn sample(&self, u1: f32, u2: f32) -> RtPoint3 {
+        // Compute cu, the cosine of the sampled direction to x
+        let au = u1 * self.solid_angle + self.k;
+        let fu = (au.cos() * self.b0 - self.b1) / au.sin();
+        let cu = (1.0 / (fu * fu + self.b0 * self.b0).sqrt()).copysign(fu).clamp(-1.0, 1.0);
+        let xu = (-(cu * self.z0) / (1.0 - cu * cu).max(0.0).sqrt()).clamp(self.x0, self.x1);
+        // Sample yv uniformly in the projected heights
+        let d = (xu * xu + self.z0 * self.z0).sqrt();
+        let h0 = self.y0 / (d * d + self.y0 * self.y0).sqrt();
+        let h1 = self.y1 / (d * d + self.y1 * self.y1).sqrt();
+        let hv = h0 + u2 * (h1 - h0);
+        let hv2 = hv * hv;
+        let yv = if hv2 < 1.0 - RT_EPSILON { (hv * d) / (1.0 - hv2).sqrt() } else { self.y1 };
+        self.origin + xu * self.x + yv * self.y + self.z0 * self.z
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use crate::rt_shaders::DEFAULT_SHADER;
+
+    /// Small xorshift generator, to get the same samples on every run
+    struct TestRng(u32);
+
+    impl TestRng {
+        fn next(&mut self) -> f32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    fn make_params(object_type: &str) -> ObjectParams {
+        ObjectParams::new(String::from("light"), String::from(object_type), Box::new(DEFAULT_SHADER))
+    }
+
+    fn make_ray(origin: RtPoint3, dir: RtVec3) -> RtRay {
+        RtRay { origin, dir, bounces: 0, x: 0, y: 0, si: 0 }
+    }
+
+    fn assert_close(value: f32, expected: f32, tolerance: f32, what: &str) {
+        assert!((value - expected).abs() <= tolerance * expected.abs(),
+            "{what} : got {value}, expected {expected}");
+    }
+
+    /// Solid angle of the triangle seen from P (Van Oosterom & Strackee)
+    fn triangle_solid_angle(P: &RtPoint3, p0: RtPoint3, p1: RtPoint3, p2: RtPoint3) -> f32 {
+        let (a, b, c) = (p0 - *P, p1 - *P, p2 - *P);
+        let (la, lb, lc) = (a.length(), b.length(), c.length());
+        let numerator = RtVec3::dot(a, RtVec3::cross(b, c)).abs();
+        let denominator = la * lb * lc + RtVec3::dot(a, b) * lc + RtVec3::dot(a, c) * lb + RtVec3::dot(b, c) * la;
+        2.0 * numerator.atan2(denominator)
+    }
+
+    fn quad_solid_angle(P: &RtPoint3, light: &RtQuadLight) -> f32 {
+        let (p0, p1) = (light.corner, light.corner + light.edge_u);
+        let (p2, p3) = (p1 + light.edge_v, light.corner + light.edge_v);
+        triangle_solid_angle(P, p0, p1, p2) + triangle_solid_angle(P, p0, p2, p3)
+    }
+
+    /// Samples the light from P on a jittered grid of n x n samples,
+    /// checks that the density of every sample is the one given by pdf()
+    /// for the ray towards it, and returns the estimated solid angle of 
+    /// the light, the mean of 1 / pdf.
+    fn check_light(light: &dyn RtLight, P: &RtPoint3, n: usize) -> f32 {
+        let mut rng = TestRng(0x1234_5678);
+        let mut solid_angle = 0.0_f64;
+        let mut misses = 0;
+        for i in 0..n * n {
+            let u1 = ((i % n) as f32 + rng.next()) / n as f32;
+            let u2 = ((i / n) as f32 + rng.next()) / n as f32;
+            let Some(sample) = light.sample(P, u1, u2) else { continue };
+            assert!(sample.pdf > 0.0 && sample.pdf.is_finite(), "Invalid pdf {} for ({u1}, {u2})", sample.pdf);
+            solid_angle += 1.0 / sample.pdf as f64;
+            // Rays grazing the silhouette may miss the light, and the density
+            // of grazing samples is too sensitive to rounding to be compared
+            let Some(hit) = light.get_intersection(&make_ray(*P, sample.wi)) else {
+                misses += 1;
+                continue
+            };
+            if RtVec3::dot(light.get_normal(&sample.P), sample.wi).abs() < 0.05 {
+                continue
+            }
+            assert_close(hit.dist, sample.dist, 1e-3, "Distance to the sample");
+            assert_close(light.pdf(P, &sample.wi, &hit), sample.pdf, 1e-2, "pdf of the sample");
+        }
+        assert!(misses * 1000 < n * n, "{misses} samples out of {} missed the light", n * n);
+        (solid_angle / (n * n) as f64) as f32
+    }
+
+    #[test]
+    fn quad_light_sampling() {
+        let points = [
+            RtPoint3::new(0.2, 0.3, 2.0),
+            RtPoint3::new(3.0, -1.0, 0.5),
+            RtPoint3::new(-0.5, 0.5, 0.2),
+        ];
+        for sampling in RtLightSampling::ALL {
+            // The spherical rectangle is only used for rectangles
+            for edge_v in [RtVec3::new(0.0, 1.5, 0.0), RtVec3::new(0.5, 1.5, 0.0)] {
+                let light = RtQuadLight {
+                    object_params: make_params("quad"),
+                    corner: RtPoint3::new(-1.0, -0.5, 0.0),
+                    edge_u: RtVec3::new(2.0, 0.0, 0.0),
+                    edge_v,
+                    emission: RtEmission::default(),
+                    sampling,
+                };
+                for P in &points {
+                    let expected = quad_solid_angle(P, &light);
+                    assert_close(check_light(&light, P, 100), expected, 1e-2, "Solid angle of the quad");
+                }
+                // Nothing is emitted behind the light
+                let behind = RtPoint3::new(0.0, 0.0, -1.0);
+                assert!(light.sample(&behind, 0.5, 0.5).is_none());
+                let hit = light.get_intersection(&make_ray(behind, RtVec3::new(0.0, 0.0, 1.0))).unwrap();
+                assert_eq!(light.pdf(&behind, &RtVec3::new(0.0, 0.0, 1.0), &hit), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn disk_light_sampling() {
+        let light = RtDiskLight {
+            object_params: make_params("disk"),
+            center: RtPoint3::new(0.0, 2.0, 0.0),
+            normal: RtVec3::new(0.0, -1.0, 0.0),
+            radius: 0.75,
+            emission: RtEmission::default(),
+        };
+        // On the axis of the disk
+        let height: f32 = 1.5;
+        let expected = 2.0 * RT_PI * (1.0 - height / (height * height + light.radius * light.radius).sqrt());
+        let P = RtPoint3::new(0.0, 2.0 - height, 0.0);
+        assert_close(check_light(&light, &P, 100), expected, 1e-2, "Solid angle of the disk");
+        // Off the axis, compared to a polygon close to the disk
+        let (t, b) = light.normal.coordinate_system();
+        let sides = 512;
+        let rim: Vec<RtPoint3> = (0..sides)
+            .map(|i| {
+                let phi = 2.0 * RT_PI * i as f32 / sides as f32;
+                light.center + (light.radius * phi.cos()) * t + (light.radius * phi.sin()) * b
+            })
+            .collect();
+        for P in [RtPoint3::new(1.5, 1.0, 0.5), RtPoint3::new(-0.5, 1.9, -0.8)] {
+            let expected: f32 = (0..sides)
+                .map(|i| triangle_solid_angle(&P, light.center, rim[i], rim[(i + 1) % sides]))
+                .sum();
+            assert_close(check_light(&light, &P, 100), expected, 1e-2, "Solid angle of the disk");
+        }
+        assert!(light.sample(&RtPoint3::new(0.0, 3.0, 0.0), 0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn mesh_light_sampling() {
+        // Tent of two rectangles, seen from above its ridge
+        let vlist = vec![
+            RtPoint3::new(-1.0, 0.0, -1.0), RtPoint3::new(0.0, 0.5, -1.0), RtPoint3::new(1.0, 0.0, -1.0),
+            RtPoint3::new(-1.0, 0.0, 1.0), RtPoint3::new(0.0, 0.5, 1.0), RtPoint3::new(1.0, 0.0, 1.0),
+        ];
+        let mesh = RtPolymesh::new(make_params("polymesh"), vlist.clone(), &[4, 4], &[0, 1, 4, 3, 1, 2, 5, 4]).unwrap();
+        let emission = RtEmission { two_sided: true, ..RtEmission::default() };
+        let light = RtMeshLight::new(mesh, emission);
+        for P in [RtPoint3::new(0.0, 2.0, 0.0), RtPoint3::new(0.3, 1.2, 0.4), RtPoint3::new(-2.0, 3.0, 1.0)] {
+            let expected = [[0, 1, 4], [0, 4, 3], [1, 2, 5], [1, 5, 4]].iter()
+                .map(|&[i0, i1, i2]| triangle_solid_angle(&P, vlist[i0], vlist[i1], vlist[i2]))
+                .sum();
+            assert_close(check_light(&light, &P, 100), expected, 1e-2, "Solid angle of the mesh");
+        }
+    }
+
+    #[test]
+    fn sphere_light_sampling() {
+        for sampling in RtLightSampling::ALL {
+            let light = RtPointLight {
+                object_params: make_params("point"),
+                center: RtPoint3::new(1.0, 2.0, -1.0),
+                radius: 0.5,
+                emission: RtEmission::default(),
+                sampling,
+            };
+            for P in [RtPoint3::new(1.0, 0.0, -1.0), RtPoint3::new(-2.0, 1.0, 3.0), RtPoint3::new(1.2, 2.6, -1.1)] {
+                let sin2_max = light.radius * light.radius / (light.center - P).length_squared();
+                let expected = 2.0 * RT_PI * (1.0 - (1.0 - sin2_max).sqrt());
+                assert_close(check_light(&light, &P, 100), expected, 1e-2, "Solid angle of the sphere");
+            }
+        }
+    }
+
+    /// Checks that the density of the samples of an infinite light is the one
+    /// given by pdf(), and that the density integrates to 1 over the sphere
+    fn check_infinite_light(light: &dyn RtLight, n: usize) {
+        let P = RtPoint3::new(0.0, 0.0, 0.0);
+        let hit = RtRayHit::default();
+        let mut rng = TestRng(0x8765_4321);
+        let mut mismatches = 0;
+        for i in 0..n * n {
+            let u1 = ((i % n) as f32 + rng.next()) / n as f32;
+            let u2 = ((i / n) as f32 + rng.next()) / n as f32;
+            let Some(sample) = light.sample(&P, u1, u2) else { continue };
+            assert!(sample.pdf > 0.0 && sample.pdf.is_finite(), "Invalid pdf {} for ({u1}, {u2})", sample.pdf);
+            assert!(sample.dist.is_infinite());
+            // Samples on the border of two pixels may be looked up in the other one
+            if (light.pdf(&P, &sample.wi, &hit) - sample.pdf).abs() > 1e-3 * sample.pdf {
+                mismatches += 1;
+            }
+        }
+        assert!(mismatches * 1000 < n * n, "{mismatches} samples out of {} don't match pdf()", n * n);
+        // The density is constant over the pixels, a fine lat-long grid integrates it
+        let (nu, nv) = (1024, 512);
+        let mut integral = 0.0_f64;
+        for j in 0..nv {
+            for i in 0..nu {
+                let (u, v) = ((i as f32 + 0.5) / nu as f32, (j as f32 + 0.5) / nv as f32);
+                let (dir, sin_theta) = RtLatLongDistribution::get_direction(u, v, 0.0);
+                integral += (light.pdf(&P, &dir, &hit) * 2.0 * RT_PI * RT_PI * sin_theta) as f64;
+            }
+        }
+        assert_close((integral / (nu * nv) as f64) as f32, 1.0, 1e-2, "Integral of the pdf");
+    }
+
+    #[test]
+    fn lat_long_round_trip() {
+        for rotation in [0.0, 37.5, -120.0, 400.0] {
+            let directions = RtLatLongDistribution::new(&[1.0], 1, 1, rotation);
+            for i in 0..50 {
+                for j in 1..50 {
+                    let (u, v) = ((i as f32 + 0.5) / 50.0, j as f32 / 50.0);
+                    let (dir, sin_theta) = RtLatLongDistribution::get_direction(u, v, rotation);
+                    assert_close(dir.length(), 1.0, 1e-5, "Length of the direction");
+                    assert_close(sin_theta, (RT_PI * v).sin(), 1e-5, "sin(theta)");
+                    let (u2, v2) = directions.get_uv(&dir);
+                    let du = (u2 - u).abs();
+                    assert!(du.min(1.0 - du) < 1e-4 && (v2 - v).abs() < 1e-4,
+                        "({u}, {v}) rotated by {rotation} gives ({u2}, {v2})");
+                }
+            }
+        }
+        // The center of the map faces -z and its top row +y
+        let (dir, _) = RtLatLongDistribution::get_direction(0.5, 0.5, 0.0);
+        assert!((dir - RtVec3::new(0.0, 0.0, -1.0)).length() < 1e-5);
+        let (dir, _) = RtLatLongDistribution::get_direction(0.5, 0.0, 0.0);
+        assert!((dir - RtVec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn skydome_sampling() {
+        // Dark map with a bright spot
+        let mut map = RtImage::new(32, 16, RtRGBA::from_rgb(0.05, 0.1, 0.2));
+        map.pixels[5 * 32 + 20] = RtRGBA::from_rgb(200.0, 180.0, 150.0);
+        let sky = RtImage::open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes/assets/sky.hdr")).unwrap();
+        for map in [None, Some(map), Some(sky)] {
+            for rotation in [0.0, 60.0] {
+                let light = RtSkydomeLight::new(make_params("skydome"), map.clone(), rotation);
+                check_infinite_light(&light, 200);
+            }
+        }
+    }
+
+    #[test]
+    fn physical_sky_sampling() {
+        for (elevation, azimuth) in [(45.0, 0.0), (5.0, 130.0), (80.0, -60.0)] {
+            let light = RtPhysicalSkyLight::new(make_params("physical_sky"), elevation, azimuth, 3.0);
+            check_infinite_light(&light, 200);
+        }
+    }
+
+    #[test]
+    fn point_light_is_delta() {
+        let light = RtPointLight {
+            object_params: make_params("point"),
+            center: RtPoint3::new(0.0, 2.0, 0.0),
+            radius: 0.0,
+            emission: RtEmission::default(),
+            sampling: RtLightSampling::default(),
+        };
+        let sample = light.sample(&RtPoint3::new(0.0, 0.0, 0.0), 0.3, 0.7).unwrap();
+        assert!(sample.is_delta);
+        assert_eq!(sample.pdf, 1.0);
+        assert_close(sample.scale, 0.25, 1e-6, "1/d² scale");
+        assert_eq!(light.pdf(&RtPoint3::new(0.0, 0.0, 0.0), &sample.wi, &sample.get_hit()), 0.0);
+    }
 }
@@ -37,7 +37,8 @@ impl ObjectParams {
 //  Object
 // ========================================
 
-pub trait RtObject {
+/// Objects are shared between render threads
+pub trait RtObject: Send + Sync {
     /// Get object parameters
     fn getObjectParams(&self) -> &ObjectParams;
     
@@ -59,6 +60,19 @@ pub trait RtObject {
     /// Get intersection point
     fn get_intersection(&self, ray: &RtRay) -> Option<RtRayHit>;
 
+    /// Get intersection point closer than max_dist
+    fn get_intersection_in_range(&self, ray: &RtRay, max_dist: f32) -> Option<RtRayHit> {
+        self.get_intersection(ray).filter(|hit| hit.dist < max_dist)
+    }
+
+    /// Is there any intersection closer than max_dist
+    fn is_occluding(&self, ray: &RtRay, max_dist: f32) -> bool {
+        self.get_intersection_in_range(ray, max_dist).is_some()
+    }
+
+    /// Get world-space bounding box
+    fn get_bounding_box(&self) -> RtAABB;
+
     /// Get object normal
     fn get_normal(&self, point: &RtPoint3) -> RtVec3;
 
@@ -70,6 +84,7 @@ pub trait RtObject {
             hit.P.unwrap()
         );
         sg.N = self.get_normal(&sg.P);
+        sg.Ng = sg.N;
         sg
     }
 
@@ -13,10 +13,13 @@ use crate::rt_types::*;
 use crate::rt_shader_globals::*;
 
 pub enum RtRayType {
-    RT_RAY_UNKNOWN
+    RT_RAY_UNKNOWN,
+    /// Visibility ray towards a light
+    RT_RAY_SHADOW
 }
 
 /// Describes a ray
+#[derive(Clone, Copy)]
 pub struct RtRay {
     // Basic ray infos
     /// Ray origin
@@ -26,13 +29,15 @@ pub struct RtRay {
 
     // Bounces
     /// Number of bounces so far (0 for camera rays)
-    pub bounces: u8,
+    pub bounces: u16,
 
     // Raster space
     /// Raster-space X coordinate 
     pub x: u16,
     /// Raster-space Y coordinate 
-    pub y: u16
+    pub y: u16,
+    /// Index of the pixel sample
+    pub si: u32
 }
 
 impl RtRay {
@@ -43,7 +48,8 @@ impl RtRay {
             dir,
             bounces: sg.bounces + 1,
             x: sg.x,
-            y: sg.y
+            y: sg.y,
+            si: sg.si
         }
     }
 
@@ -69,18 +75,29 @@ impl std::fmt::Display for RtRay {
 pub struct RtRayHit {
     pub hit: bool,
     pub dist: f32,
-    pub P: Option<RtPoint3>
+    pub P: Option<RtPoint3>,
+    /// Index of the primitive that was hit (e.g. triangle of a polymesh)
+    pub prim_id: usize,
+    /// Barycentric coordinates of the hit on the primitive
+    pub b1: f32,
+    pub b2: f32
 }
 
 impl Default for RtRayHit {
     fn default() -> Self {
-        Self { hit: false, dist: NAN, P: None }
+        Self { hit: false, dist: NAN, P: None, prim_id: 0, b1: 0.0, b2: 0.0 }
     }
 }
 
 impl RtRayHit {
     pub fn new(hit: bool, dist: f32, P: RtPoint3) -> Self {
-        Self { hit, dist, P: Some(P) }
+        Self { hit, dist, P: Some(P), prim_id: 0, b1: 0.0, b2: 0.0 }
+    }
+
+    /// Hit on a primitive of the object, with the barycentric 
+    /// coordinates (b1, b2) of the hit point
+    pub fn on_primitive(dist: f32, P: RtPoint3, prim_id: usize, b1: f32, b2: f32) -> Self {
+        Self { hit: true, dist, P: Some(P), prim_id, b1, b2 }
     }
 }
 
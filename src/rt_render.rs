@@ -2,10 +2,16 @@
 ///                    Raito Render
 /// 
 /// Module description :
-///   Implements the ray tracing function.
+///   Implements the path tracer : follows the paths
+///   of the camera rays, sampling the lights and the
+///   BSDFs returned by the shaders.
 /// =====================================================
 
-use std::f32::NAN;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::{debug, error};
 
 use crate::rt_types::*;
 use crate::rt_camera::*;
@@ -14,7 +20,11 @@ use crate::rt_shader_globals::*;
 use crate::rt_scene::*;
 use crate::RtVec3;
 use crate::rt_objects::rt_object_base::*;
-use crate::rt_render_output::RtRenderResult;
+use crate::rt_objects::rt_lights::{RtLight, RtLightSample};
+use crate::rt_sampler::*;
+use crate::rt_render_output::{RtRenderResult, RtPixelSamples, RtFilmTile};
+use crate::rt_render_job::RtRenderControl;
+use crate::rt_shaders::rt_bsdf::*;
 
 
 // ========================================
@@ -28,7 +38,8 @@ pub fn RtMakeRay(sg: &RtShaderGlobals, _raytype: RtRayType, dir: RtVec3, _maxdis
         dir,
         bounces: sg.bounces + 1, 
         x: sg.x, 
-        y: sg.y
+        y: sg.y,
+        si: sg.si
     }
 }
 
@@ -45,154 +56,392 @@ pub fn RtRefractRay(ray: &mut RtRay, wo: &RtVec3, normal: &RtVec3, eta: f32, _sg
     ray.dir = (r_out_perp + r_out_parallel).normalize();
 }
 
+// ========================================
+//  Sampling
+// ========================================
+
+/// First dimension of the bounce of the shading point
+fn RtBounceDimension(sg: &RtShaderGlobals, dimension: u32) -> u32 {
+    RT_DIM_BOUNCE + u32::from(sg.bounces) * RT_DIMS_PER_BOUNCE + dimension
+}
+
+/// Sample value in [0, 1) for the shading point
+/// 
+/// `dimension` is one of the dimensions used at each bounce (RT_DIM_BSDF, ...).
+pub fn RtGetSample1D(scene: &RtScene, sg: &RtShaderGlobals, dimension: u32) -> f32 {
+    scene.settings.get_sampler().get_1d(sg.x, sg.y, sg.si, RtBounceDimension(sg, dimension))
+}
+
+/// Sample values in [0, 1)² for the shading point, see RtGetSample1D
+pub fn RtGetSample2D(scene: &RtScene, sg: &RtShaderGlobals, dimension: u32) -> (f32, f32) {
+    scene.settings.get_sampler().get_2d(sg.x, sg.y, sg.si, RtBounceDimension(sg, dimension))
+}
+
+
 // ========================================
 //  Launching rays
 // ========================================
 
+thread_local! {
+    /// Rays traced by the render thread, reported to the render control
+    static RT_RAYS_TRACED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Count a ray traced in the scene
+fn RtCountRay() {
+    RT_RAYS_TRACED.with(|rays| rays.set(rays.get() + 1));
+}
+
+/// Number of rays traced by the current thread
+fn RtRaysTraced() -> u64 {
+    RT_RAYS_TRACED.with(|rays| rays.get())
+}
+
 /// Launch a ray on a scene
+/// 
+/// Follows the path started by the ray until it leaves the scene, 
+/// reaches a depth limit or is terminated by Russian roulette. 
+/// `colorOutput` is the radiance carried back along the ray.
 pub fn RtTraceRay(scene: &RtScene, ray: &RtRay) -> Option<RtHit> {
-    if ray.bounces >= scene.settings.max_bounces {
-        return None
-    }
+    let settings = &scene.settings;
+    let nb_lights = scene.list_lights().len() as f32;
+
+    let mut ray = *ray;
+    let mut radiance = RtRGBA::BLACK;
+    let mut throughput = RtRGBA::WHITE;
+    let mut depth = RtPathDepth::default();
+    // Density of the BSDF sample that created the ray,
+    // None for camera rays and delta lobes
+    let mut bsdf_pdf: Option<f32> = None;
+    // Past the depth limits, only gather the lights hit by the ray,
+    // as light sampling does on the last shading point
+    let mut lights_only = false;
+    // First hit of the path
+    let mut first_hit: Option<RtPoint3> = None;
+
+    loop {
+        // Shapes : Find closest hit point & object
+        RtCountRay();
+        let shape_hit = scene.closest_hit(&ray, f32::INFINITY);
 
-    let mut min_dist: f32 = NAN;
-    let mut first_hit: Option<RtRayHit> = None;
-    let mut first_hit_object: Option<&Box<dyn RtObject>> = None;
-    
-    // Shapes : Find closest hit point & object
-    let geometry = scene.list_shapes();
-    for shape in geometry {
-        // Compute intersections
-        let hit = shape.get_intersection(ray);
-        // Execute shader
-        if hit.is_some() {
-            let hitSg = hit.unwrap();
-            if hitSg.dist < min_dist || min_dist.is_nan() {
-                min_dist = hitSg.dist;
-                first_hit = Some(hitSg);
-                first_hit_object = Some(shape);
+        // Lights in front of the closest shape
+        let max_dist = shape_hit.as_ref().map_or(f32::INFINITY, |(hit, _)| hit.dist);
+        if let Some((hit, light)) = scene.closest_light_hit(&ray, max_dist) {
+            let hit_sg = light.get_sg(&ray, &hit);
+            let emission = light.get_emission(&hit_sg, &-ray.dir.normalize());
+            let weight = match bsdf_pdf {
+                Some(pdf) => RtBsdfSampleWeight(scene, light.pdf(&ray.origin, &ray.dir, &hit) / nb_lights, pdf),
+                None => 1.0,
+            };
+            radiance += throughput * emission * weight;
+            first_hit.get_or_insert(hit_sg.P);
+            break;
+        }
+
+        let Some((hit, hit_object)) = shape_hit else {
+            // Infinite lights (skies, distant lights)
+            let dir = ray.dir.normalize();
+            for light in scene.list_lights().iter().filter(|light| light.is_infinite() && !light.is_delta()) {
+                let weight = match bsdf_pdf {
+                    Some(pdf) => RtBsdfSampleWeight(scene, light.pdf(&ray.origin, &dir, &RtRayHit::default()) / nb_lights, pdf),
+                    None => 1.0,
+                };
+                radiance += throughput * light.get_radiance(&dir) * weight;
+            }
+            break;
+        };
+
+        // Execute shader on closest hit
+        let hit_sg = hit_object.get_sg(&ray, &hit);
+        first_hit.get_or_insert(hit_sg.P);
+        let closure = hit_object.get_shader().evaluate(&hit_sg);
+        // Emissive shapes that are not lights are only found by the path
+        radiance += throughput * closure.emission;
+        if lights_only {
+            break;
+        }
+        let Some(bsdf) = &closure.bsdf else {
+            break;
+        };
+        let wo = -hit_sg.ray_dir;
+
+        // Direct contribution : sample the lights
+        if !bsdf.is_delta() {
+            if let Some(light_sample) = RtLightsGetSample(scene, &hit_sg) {
+                let f = bsdf.eval(&wo, &light_sample.wi);
+                let weight = RtLightSampleWeight(scene, &light_sample, bsdf.pdf(&wo, &light_sample.wi));
+                radiance += throughput * f * light_sample.Li * (weight / light_sample.pdf);
             }
         }
+
+        // Indirect contribution : continue the path in a direction sampled by the BSDF
+        let (u1, u2) = RtGetSample2D(scene, &hit_sg, RT_DIM_BSDF);
+        let Some(bsdf_sample) = bsdf.sample(&wo, u1, u2) else {
+            break;
+        };
+        throughput = throughput * bsdf_sample.weight;
+        bsdf_pdf = if bsdf_sample.is_delta { None } else { Some(bsdf_sample.pdf) };
+        lights_only = !depth.add_bounce(bsdf_sample.lobe, settings);
+
+        // Russian roulette, survivors carry the energy of the terminated paths
+        if !lights_only && depth.total > settings.rr_depth {
+            let survival = throughput.r.max(throughput.g).max(throughput.b).min(0.95);
+            if RtGetSample1D(scene, &hit_sg, RT_DIM_RUSSIAN_ROULETTE) >= survival {
+                break;
+            }
+            throughput = throughput / survival;
+        }
+        if throughput.r <= 0.0 && throughput.g <= 0.0 && throughput.b <= 0.0 {
+            break;
+        }
+
+        // Start from the side of the surface the ray goes to
+        let offset = if RtVec3::dot(bsdf_sample.wi, hit_sg.Ng) >= 0.0 { hit_sg.Ng } else { -hit_sg.Ng };
+        ray = RtMakeRay(&hit_sg, RtRayType::RT_RAY_UNKNOWN, bsdf_sample.wi, f32::INFINITY);
+        ray.origin = ray.origin + RT_EPSILON * offset;
     }
 
-    // Lights : Find closest hit point & object
-    // let lights = scene.list_lights();
-    // for light in lights {
-    //     // Compute intersections
-    //     let hit = light.get_intersection(ray);
-    //     // Execute shader
-    //     if hit.is_some() {
-    //         let hitSg = hit.unwrap();
-    //         if hitSg.dist < min_dist || min_dist.is_nan() {
-    //             min_dist = hitSg.dist;
-    //             first_hit = Some(hitSg);
-    //             first_hit_object = Some(light);
-    //         }
-    //     }
-    // }
-    
-    // Execute shader on closest hit and return hit result
-    if first_hit_object.is_some() {
-        let hit = first_hit.unwrap();
-        let hit_sg = first_hit_object.unwrap().get_sg(ray, &hit);
-        let hit_point = hit.P.unwrap();
-        let color = first_hit_object.unwrap().get_shader().evaluate(scene, &hit_sg);
-
-        Some( RtHit::new(true, color, hit_point) )
-    } else {
-        // None
-        let a = 0.5 * ray.dir.y + 1.0;
-        let skyColor = (1.0 - a) * RtRGBA::WHITE + a * RtRGBA::from_rgb(0.5, 0.7, 1.0);
-        Some(RtHit::new(false, skyColor, RtPoint3::default()))
+    Some(RtHit::new(first_hit.is_some(), radiance, first_hit.unwrap_or_default()))
+}
+
+
+/// Number of bounces of a path, per lobe type
+#[derive(Debug, Default, Clone, Copy)]
+struct RtPathDepth {
+    total: u16,
+    diffuse: u16,
+    specular: u16,
+    transmission: u16,
+}
+
+impl RtPathDepth {
+    /// Count a bounce, returns false if it exceeds the depth limits
+    fn add_bounce(&mut self, lobe: RtLobe, settings: &RtRenderSettings) -> bool {
+        self.total = self.total.saturating_add(1);
+        let (count, limit) = match lobe {
+            RtLobe::Diffuse => (&mut self.diffuse, settings.diffuse_depth),
+            RtLobe::Specular => (&mut self.specular, settings.specular_depth),
+            RtLobe::Transmission => (&mut self.transmission, settings.transmission_depth),
+        };
+        *count = count.saturating_add(1);
+        *count <= limit && self.total <= settings.max_bounces
     }
 }
 
 
-/// Launch to lights
-pub fn RtTraceToLights(scene: &RtScene, ray: &RtRay) -> Option<RtHit> {
-    
-    let mut min_dist: f32 = NAN;
-    let mut first_hit: Option<RtRayHit> = None;
-    let mut first_hit_object: Option<&Box<dyn RtObject>> = None;
-    
-    // Find closest hit point & object
+// ========================================
+//  Direct lighting
+// ========================================
+
+/// Sample the direct lighting of one light, picked at random
+/// 
+/// Returns None if there is no light to sample or if the sampled 
+/// point is masked by a shape. Otherwise `Li` holds the incoming 
+/// radiance and `pdf` includes the probability of picking the light,
+/// so the light contribution is `bsdf * Li * cos / pdf`.
+pub fn RtLightsGetSample(scene: &RtScene, sg: &RtShaderGlobals) -> Option<RtLightSample> {
     let lights = scene.list_lights();
-    for light in lights {
-        // Compute intersections
-        let hit = light.get_intersection(ray);
-        // Execute shader
-        if hit.is_some() {
-            let hitSg = hit.unwrap();
-            if hitSg.dist < min_dist || min_dist.is_nan() {
-                min_dist = hitSg.dist;
-                first_hit = Some(hitSg);
-                first_hit_object = Some(light);
-            }
-        }
+    if lights.is_empty() {
+        return None
     }
-    
-    // Execute shader on closest hit and return hit result
-    if first_hit_object.is_some() {
-        if first_hit_object.unwrap().get_type().as_str() == "light" {
-            let hit = first_hit.unwrap();
-            let hit_sg = first_hit_object.unwrap().get_sg(ray, &hit);
-            let hit_point = hit.P.unwrap();
-            let color = first_hit_object.unwrap().get_shader().evaluate(scene, &hit_sg);
-            // TODO : attenuation
-            Some( RtHit::new(true, color, hit_point) )
-        } else {
-            // Not a light : masked
-            None
-        }
-    } else {
-        None
+    let u_light = RtGetSample1D(scene, sg, RT_DIM_LIGHT_PICK);
+    let light_index = ((u_light * lights.len() as f32) as usize).min(lights.len() - 1);
+    let light = &lights[light_index];
+    if scene.settings.direct_lighting == RtDirectLighting::BsdfSampling && !light.is_delta() {
+        // Found by the rays sampled by the BSDFs
+        return None
+    }
+    let (u1, u2) = RtGetSample2D(scene, sg, RT_DIM_LIGHT);
+    let mut sample = light.sample(&sg.P, u1, u2)?;
+    if sample.pdf <= 0.0 || sample.dist <= 0.0 {
+        return None
     }
-}
 
+    // Shadow ray, from the side of the surface facing the light
+    let offset = if RtVec3::dot(sample.wi, sg.Ng) >= 0.0 { sg.Ng } else { -sg.Ng };
+    let mut ray = RtMakeRay(sg, RtRayType::RT_RAY_SHADOW, sample.wi, sample.dist);
+    ray.origin = ray.origin + RT_EPSILON * offset;
+    RtCountRay();
+    if scene.any_hit(&ray, sample.dist * (1.0 - RT_EPSILON)) {
+        return None
+    }
+    // Lights hide the lights behind them, as for the rays sampled by the BSDFs.
+    // Aim at the sampled point from the offset origin, so that the sampled
+    // light does not hide itself.
+    let mut light_ray = ray;
+    let mut light_dist = f32::INFINITY;
+    if !light.is_infinite() {
+        let to_light = sample.P - ray.origin;
+        light_dist = to_light.length();
+        light_ray.dir = to_light / light_dist;
+    }
+    if scene.closest_light_hit(&light_ray, light_dist * (1.0 - RT_EPSILON)).is_some() {
+        return None
+    }
 
-fn linear_to_gamma(linear_component: f32) -> f32 {
-    if linear_component > 0.0 {
-        linear_component.sqrt()
+    // Emitted radiance
+    sample.Li = if light.is_infinite() {
+        light.get_radiance(&sample.wi) * sample.scale
     } else {
-        0.0
+        let light_sg = light.get_sg(&ray, &sample.get_hit());
+        light.get_emission(&light_sg, &-sample.wi) * sample.scale
+    };
+    sample.pdf /= lights.len() as f32;
+    Some(sample)
+}
+
+
+/// Power heuristic (beta = 2) for multiple importance sampling
+pub fn RtPowerHeuristic(pdf_f: f32, pdf_g: f32) -> f32 {
+    let (f2, g2) = (pdf_f * pdf_f, pdf_g * pdf_g);
+    if f2 + g2 > 0.0 { f2 / (f2 + g2) } else { 0.0 }
+}
+
+/// Weight of a sample returned by RtLightsGetSample
+/// 
+/// `bsdf_pdf` is the density of sampling the same direction with the BSDF.
+pub fn RtLightSampleWeight(scene: &RtScene, sample: &RtLightSample, bsdf_pdf: f32) -> f32 {
+    if sample.is_delta {
+        return 1.0
+    }
+    match scene.settings.direct_lighting {
+        RtDirectLighting::Mis => RtPowerHeuristic(sample.pdf, bsdf_pdf),
+        RtDirectLighting::LightSampling => 1.0,
+        RtDirectLighting::BsdfSampling => 0.0,
     }
 }
 
+/// Weight of a light hit by a ray sampled by a BSDF
+/// 
+/// `light_pdf` is the density of sampling the same direction with 
+/// RtLightsGetSample, `bsdf_pdf` the density of the sampled direction.
+pub fn RtBsdfSampleWeight(scene: &RtScene, light_pdf: f32, bsdf_pdf: f32) -> f32 {
+    match scene.settings.direct_lighting {
+        RtDirectLighting::Mis => RtPowerHeuristic(bsdf_pdf, light_pdf),
+        RtDirectLighting::LightSampling => 0.0,
+        RtDirectLighting::BsdfSampling => 1.0,
+    }
+}
+
+
+// ========================================
+//  Render
+// ========================================
+
+/// Render the samples [first_sample, end_sample) of the pixels of a bucket
+/// 
+/// Each pixel gets at least render_spp samples. With adaptive 
+/// sampling, it keeps being sampled until its noise goes under 
+/// the threshold or it reaches the maximum number of samples.
+/// The samples are splatted by the filter to the pixels around 
+/// them, including the margin of the tile out of the bucket.
+/// 
+/// Returns None when the render is cancelled.
+fn RtRenderBucket(scene: &RtScene, result: &RtRenderResult, bucket: &RtBucket, 
+                  first_sample: u32, end_sample: u32, control: &RtRenderControl) -> Option<RtFilmTile> {
+    let min_spp = u32::from(scene.settings.render_spp);
+    let end_sample = end_sample.min(scene.settings.get_max_spp());
+    let adaptive = scene.settings.is_adaptive();
+
+    let sampler = scene.settings.get_sampler();
+    let filter = scene.settings.get_filter();
+    let camera = scene.get_camera();
 
-/// Launch render on scene
-pub fn RtRenderScene(scene: &RtScene, result: &mut RtRenderResult) {
-    // TODO : for now the camera
-    // - center is at 0
-    // - direction is towards the -y direction
-    // 
-    // We want to be able to change that, move and rotate the camera
-    // We need to implement world and camera space
-
-    let inv_nb_spp: f32 = 1.0 / (scene.settings.render_spp as f32);
-
-    let cam_rays = RtCameraRayIterator::new(scene.get_camera());
-    for camera_ray in cam_rays {
-        let mut pixelColor = RtRGBA::BLACK;
-        for _ in 0..scene.settings.render_spp {
-            let ray = camera_ray.get_ray(scene.get_camera());
-            let hit = RtTraceRay(scene, &ray);
-            if hit.is_some() {
-                let hitResult = hit.unwrap();
-                pixelColor += hitResult.colorOutput * inv_nb_spp;
-            } else {
-                // let a = 0.5 * ray.dir.y + 1.0;
-                // let skyColor = (1.0 - a) * RtRGBA::WHITE + a * RtRGBA::from_rgb(0.5, 0.7, 1.0);
-                // pixelColor += skyColor * inv_nb_spp;
-                pixelColor += RtRGBA::ERRCOLOR  * inv_nb_spp;
+    let mut tile = RtFilmTile::new(bucket, filter.margin(), 
+        usize::from(camera.image_width), usize::from(camera.image_height));
+    // Samples of the previous passes, for the noise estimation. They are 
+    // only needed by adaptive sampling, and read with a single lock.
+    let previous_samples = (adaptive && first_sample > 0).then(|| result.get_bucket_samples(bucket));
+    for (pixel_index, camera_ray) in bucket.pixels().enumerate() {
+        if control.is_cancelled() {
+            return None;
+        }
+        let rays_before = RtRaysTraced();
+        // Samples taken in the pixel, including the previous passes
+        let mut pixelSamples = previous_samples.as_ref()
+            .map_or_else(RtPixelSamples::default, |samples| samples[pixel_index]);
+        for si in first_sample..end_sample {
+            if adaptive && si >= min_spp && pixelSamples.get_error() < scene.settings.adaptive_threshold {
+                break;
             }
+            let color = match camera_ray.get_ray(camera, &sampler, si) {
+                Some(ray) => match RtTraceRay(scene, &ray) {
+                    Some(hitResult) => hitResult.colorOutput,
+                    None => RtRGBA::ERRCOLOR,
+                },
+                // Outside of the camera projection
+                None => RtRGBA::BLACK,
+            };
+            pixelSamples.add_statistics(color);
+            tile.get_pixel_mut(camera_ray.x(), camera_ray.y()).add_statistics(color);
+            // Pixels are kept linear, they are encoded for display or export
+            let (px, py) = camera_ray.get_film_sample(&sampler, si);
+            tile.splat(&filter, camera_ray.x(), camera_ray.y(), px, py, color);
         }
-        // panic!("Pixel : {} {}", camera_ray.x(), camera_ray.y());
-        let outColor = RtRGBA::from_rgb(
-            linear_to_gamma(pixelColor.r), 
-            linear_to_gamma(pixelColor.g), 
-            linear_to_gamma(pixelColor.b) 
-        );
-
-        result.set_pixel_color(camera_ray.x(), camera_ray.y(), outColor);
+        control.add_pixel(RtRaysTraced() - rays_before);
+    }
+    Some(tile)
+}
+
+/// Render the samples [first_sample, first_sample + nb_samples) of 
+/// every pixel and add them to the result. Progressive renders call
+/// it once per pass, on the same result.
+/// 
+/// The image is split in buckets that are picked by a pool of 
+/// render threads. Each bucket is added to the result as soon
+/// as it and the buckets before it are finished.
+/// 
+/// The render control cancels the pass and counts its pixels and rays.
+/// Returns false if the render was cancelled before the end of the pass,
+/// the result then misses the samples of some of the buckets.
+pub fn RtRenderPass(scene: &RtScene, result: &RtRenderResult, 
+                    first_sample: u32, nb_samples: u32, control: &RtRenderControl) -> bool {
+    let camera = scene.get_camera();
+    if result.width != usize::from(camera.image_width) || result.height != usize::from(camera.image_height) {
+        error!("Render result is {}x{} but the camera renders {}x{} images !",
+            result.width, result.height, camera.image_width, camera.image_height);
+        return false;
     }
+    let buckets: Vec<RtBucket> = RtBucketIterator::new(
+        scene.get_camera(), scene.settings.bucket_size).collect();
+    let nb_threads = scene.settings.get_nb_threads().min(buckets.len()).max(1);
+    let end_sample = first_sample.saturating_add(nb_samples);
+    debug!("Rendering samples {} to {} of {} buckets on {} threads", 
+        first_sample, end_sample, buckets.len(), nb_threads);
+
+    // Index of the next bucket to render
+    let next_bucket = AtomicUsize::new(0);
+    // Tiles overlap with the filter margins. Finished tiles wait for the 
+    // tiles of the previous buckets, so the pixels are always summed in 
+    // the same order whatever the number of threads.
+    let pending_tiles: Mutex<(usize, BTreeMap<usize, RtFilmTile>)> = Mutex::new((0, BTreeMap::new()));
+    std::thread::scope(|s| {
+        for _ in 0..nb_threads {
+            s.spawn(|| {
+                loop {
+                    let index = next_bucket.fetch_add(1, Ordering::Relaxed);
+                    let Some(bucket) = buckets.get(index) else {
+                        break;
+                    };
+                    let Some(tile) = RtRenderBucket(scene, result, bucket, first_sample, end_sample, control) else {
+                        break;
+                    };
+                    let mut pending = pending_tiles.lock().unwrap_or_else(|e| e.into_inner());
+                    let (next_tile, tiles) = &mut *pending;
+                    tiles.insert(index, tile);
+                    while let Some(tile) = tiles.remove(next_tile) {
+                        result.add_tile(&tile);
+                        *next_tile += 1;
+                    }
+                }
+            });
+        }
+    });
+    !control.is_cancelled()
+}
+
+/// Launch render on scene, with all the samples of the pixels
+pub fn RtRenderScene(scene: &RtScene, result: &RtRenderResult) {
+    result.clear();
+    RtRenderPass(scene, result, 0, scene.settings.get_max_spp(), &RtRenderControl::default());
 }
@@ -0,0 +1,246 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Render jobs running in the background : the render
+///   can be cancelled, and reports its progress while
+///   the result is filled.
+/// =====================================================
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use log::error;
+
+use crate::rt_scene::RtScene;
+use crate::rt_render::RtRenderPass;
+use crate::rt_render_output::RtRenderResult;
+
+
+// ========================================
+//  Render control
+// ========================================
+
+/// Shared by a render and its callers, to cancel the render
+/// and count the work done
+#[derive(Debug, Default)]
+pub struct RtRenderControl {
+    cancel: AtomicBool,
+    /// Pixels rendered, counted once per pass
+    pixels_done: AtomicU64,
+    rays_traced: AtomicU64,
+    passes_done: AtomicU32,
+    /// Time at which the render stopped, set by the render thread
+    finish_time: Mutex<Option<Instant>>,
+    finished: Condvar,
+}
+
+impl RtRenderControl {
+    /// Ask the render threads to stop after the pixel they are rendering
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Count a rendered pixel and the rays traced for it
+    pub fn add_pixel(&self, rays: u64) {
+        self.pixels_done.fetch_add(1, Ordering::Relaxed);
+        self.rays_traced.fetch_add(rays, Ordering::Relaxed);
+    }
+
+    pub fn add_pass(&self) {
+        self.passes_done.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn pixels_done(&self) -> u64 {
+        self.pixels_done.load(Ordering::Relaxed)
+    }
+
+    pub fn rays_traced(&self) -> u64 {
+        self.rays_traced.load(Ordering::Relaxed)
+    }
+
+    pub fn passes_done(&self) -> u32 {
+        self.passes_done.load(Ordering::Relaxed)
+    }
+
+    /// Called by the render thread when it stops, completed or not
+    pub fn finish(&self) {
+        let mut finish_time = self.finish_time.lock().unwrap_or_else(|e| e.into_inner());
+        finish_time.get_or_insert_with(Instant::now);
+        self.finished.notify_all();
+    }
+
+    pub fn finish_time(&self) -> Option<Instant> {
+        *self.finish_time.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Wait for the end of the render for at most `timeout`,
+    /// returns true if the render is finished
+    pub fn wait_finish(&self, timeout: Duration) -> bool {
+        let finish_time = self.finish_time.lock().unwrap_or_else(|e| e.into_inner());
+        let (finish_time, _) = self.finished
+            .wait_timeout_while(finish_time, timeout, |finish_time| finish_time.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        finish_time.is_some()
+    }
+}
+
+
+// ========================================
+//  Render progress
+// ========================================
+
+/// Snapshot of the progress of a render job
+#[derive(Debug, Clone, Copy)]
+pub struct RtRenderProgress {
+    /// Part of the render done, in [0, 1]
+    pub fraction: f32,
+    pub elapsed: Duration,
+    /// Estimated remaining time, None before the first pixel
+    pub eta: Option<Duration>,
+    pub rays_traced: u64,
+    pub rays_per_second: f64,
+    /// Sample passes finished, out of nb_passes
+    pub passes_done: u32,
+    pub nb_passes: u32,
+}
+
+impl RtRenderProgress {
+    pub fn percent(&self) -> f32 {
+        100.0 * self.fraction
+    }
+}
+
+impl std::fmt::Display for RtRenderProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:5.1} % | {:.1?} elapsed", self.percent(), self.elapsed)?;
+        if let Some(eta) = self.eta {
+            write!(f, ", ETA {:.1?}", eta)?;
+        }
+        write!(f, " | {:.2} Mrays/s", self.rays_per_second * 1e-6)
+    }
+}
+
+
+// ========================================
+//  Render job
+// ========================================
+
+/// Render launched on a background thread
+///
+/// The samples are added to the result while the job runs, so it
+/// can be displayed at any time. A full render traces all the samples
+/// of a bucket before moving to the next one, a progressive render
+/// adds one sample to every pixel at each pass.
+pub struct RtRenderJob {
+    control: Arc<RtRenderControl>,
+    /// Total number of pixels to render, once per pass
+    total_pixels: u64,
+    nb_passes: u32,
+    start_time: Instant,
+    thread: JoinHandle<bool>,
+}
+
+impl RtRenderJob {
+    /// Start a full render of the scene, the result is cleared first
+    pub fn start(scene: Arc<RtScene>, result: Arc<RtRenderResult>) -> Self {
+        Self::launch(scene, result, false)
+    }
+
+    /// Start a progressive render of the scene, one sample per pass
+    pub fn start_progressive(scene: Arc<RtScene>, result: Arc<RtRenderResult>) -> Self {
+        Self::launch(scene, result, true)
+    }
+
+    fn launch(scene: Arc<RtScene>, result: Arc<RtRenderResult>, progressive: bool) -> Self {
+        let control = Arc::new(RtRenderControl::default());
+        let max_spp = scene.settings.get_max_spp();
+        let nb_passes = if progressive { max_spp } else { 1 };
+        let total_pixels = (result.width * result.height) as u64 * u64::from(nb_passes);
+        result.clear();
+        let start_time = Instant::now();
+        let thread = {
+            let control = control.clone();
+            std::thread::spawn(move || {
+                let samples_per_pass = if progressive { 1 } else { max_spp };
+                let mut completed = true;
+                for pass in 0..nb_passes {
+                    if !RtRenderPass(&scene, &result, pass * samples_per_pass, samples_per_pass, &control) {
+                        completed = false;
+                        break;
+                    }
+                    control.add_pass();
+                }
+                control.finish();
+                completed
+            })
+        };
+        Self { control, total_pixels, nb_passes, start_time, thread }
+    }
+
+    /// Ask the render to stop, without waiting for it
+    pub fn cancel(&self) {
+        self.control.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the end of the render for at most `timeout`,
+    /// returns true if the render is finished
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        // A render thread that panicked never calls finish
+        self.control.wait_finish(timeout) || self.is_finished()
+    }
+
+    /// Progress of the render, the time stops at the end of the render
+    pub fn progress(&self) -> RtRenderProgress {
+        let elapsed = match self.control.finish_time() {
+            Some(finish_time) => finish_time.saturating_duration_since(self.start_time),
+            None => self.start_time.elapsed(),
+        };
+        let pixels_done = self.control.pixels_done();
+        let fraction = if self.total_pixels > 0 {
+            (pixels_done as f64 / self.total_pixels as f64).min(1.0)
+        } else {
+            1.0
+        };
+        let eta = (fraction > 0.0)
+            .then(|| elapsed.mul_f64((1.0 - fraction) / fraction));
+        let rays_traced = self.control.rays_traced();
+        let seconds = elapsed.as_secs_f64();
+        RtRenderProgress {
+            fraction: fraction as f32,
+            elapsed,
+            eta,
+            rays_traced,
+            rays_per_second: if seconds > 0.0 { rays_traced as f64 / seconds } else { 0.0 },
+            passes_done: self.control.passes_done(),
+            nb_passes: self.nb_passes,
+        }
+    }
+
+    /// Wait for the end of the render, returns false if it was
+    /// cancelled or failed
+    pub fn wait(self) -> bool {
+        match self.thread.join() {
+            Ok(completed) => completed,
+            Err(_) => {
+                error!("Render thread panicked !");
+                false
+            }
+        }
+    }
+
+    /// Cancel the render and wait for the render threads
+    pub fn stop(self) {
+        self.cancel();
+        self.wait();
+    }
+}
@@ -3,60 +3,398 @@
 /// 
 /// Module description :
 ///   Handle render result & output
-///   We can write tools to export image here 
+///   The pixels are stored as linear values. They are
+///   sRGB encoded for display and 8-bit images (PPM, PNG),
+///   OpenEXR images keep the linear HDR values.
 /// =====================================================
 
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
 use egui::Color32;
-use log::error;
+use log::{error, info};
 
 use crate::rt_types::*;
+use crate::rt_camera::RtBucket;
+use crate::rt_filter::RtFilter;
+
+// ========================================
+//  Pixel samples
+// ========================================
+
+/// Samples accumulated in a pixel : the colors splatted by the 
+/// reconstruction filter, and the moments of the luminance of the
+/// samples taken in the pixel to estimate its noise
+#[derive(Debug, Clone, Copy)]
+pub struct RtPixelSamples {
+    /// Sum of the sample colors weighted by the filter, and of the weights
+    pub sum: RtRGBA,
+    pub weight_sum: f32,
+    /// Sum of the luminance of the samples and of its square
+    pub lum_sum: f64,
+    pub lum_sq_sum: f64,
+    /// Number of samples
+    pub count: u32,
+}
+
+impl Default for RtPixelSamples {
+    fn default() -> Self {
+        Self {
+            // The alpha is summed too, it starts at 0
+            sum: RtRGBA { r: 0.0, g: 0.0, b: 0.0, a: 0.0 },
+            weight_sum: 0.0,
+            lum_sum: 0.0,
+            lum_sq_sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl RtPixelSamples {
+    /// Add an unfiltered sample of the pixel
+    pub fn add_sample(&mut self, color: RtRGBA) {
+        self.add_statistics(color);
+        self.splat(color, 1.0);
+    }
+
+    /// Count a sample taken in the pixel for the noise estimation
+    pub fn add_statistics(&mut self, color: RtRGBA) {
+        let luminance = f64::from(color.luminance());
+        self.lum_sum += luminance;
+        self.lum_sq_sum += luminance * luminance;
+        self.count += 1;
+    }
+
+    /// Add a sample color with the weight of the filter
+    pub fn splat(&mut self, color: RtRGBA, weight: f32) {
+        self.sum.r += weight * color.r;
+        self.sum.g += weight * color.g;
+        self.sum.b += weight * color.b;
+        self.sum.a += weight * color.a;
+        self.weight_sum += weight;
+    }
+
+    /// Add the samples of another accumulation of the pixel
+    pub fn merge(&mut self, other: &RtPixelSamples) {
+        self.sum.r += other.sum.r;
+        self.sum.g += other.sum.g;
+        self.sum.b += other.sum.b;
+        self.sum.a += other.sum.a;
+        self.weight_sum += other.weight_sum;
+        self.lum_sum += other.lum_sum;
+        self.lum_sq_sum += other.lum_sq_sum;
+        self.count += other.count;
+    }
+
+    /// Weighted mean of the samples, black without samples
+    pub fn get_color(&self) -> RtRGBA {
+        // Negative lobes of the filter can cancel the weights
+        if self.weight_sum <= 0.0 {
+            return RtRGBA::default()
+        }
+        let inv_weight = 1.0 / self.weight_sum;
+        RtRGBA {
+            r: self.sum.r * inv_weight,
+            g: self.sum.g * inv_weight,
+            b: self.sum.b * inv_weight,
+            a: self.sum.a * inv_weight,
+        }
+    }
+
+    /// Variance of the mean luminance, infinite below two samples
+    pub fn get_variance(&self) -> f32 {
+        if self.count < 2 {
+            return f32::INFINITY
+        }
+        let n = f64::from(self.count);
+        let mean = self.lum_sum / n;
+        let sample_variance = ((self.lum_sq_sum - n * mean * mean) / (n - 1.0)).max(0.0);
+        (sample_variance / n) as f32
+    }
+
+    /// Noise of the pixel : standard error of the luminance, relative 
+    /// to the square root of the luminance to follow the perceived noise
+    pub fn get_error(&self) -> f32 {
+        let variance = self.get_variance();
+        if !variance.is_finite() {
+            return f32::INFINITY
+        }
+        let mean = (self.lum_sum / f64::from(self.count)) as f32;
+        variance.sqrt() / mean.max(1e-4).sqrt()
+    }
+}
+
+
+// ========================================
+//  Film tiles
+// ========================================
+
+/// Samples of a bucket, with a margin for the samples splatted 
+/// by the filter to the pixels of the neighbour buckets
+pub struct RtFilmTile {
+    /// Top left pixel and size, clipped to the image
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// Pixels row by row
+    pub pixels: Vec<RtPixelSamples>,
+}
+
+impl RtFilmTile {
+    pub fn new(bucket: &RtBucket, margin: usize, image_width: usize, image_height: usize) -> Self {
+        let x = usize::from(bucket.x).saturating_sub(margin);
+        let y = usize::from(bucket.y).saturating_sub(margin);
+        let width = (usize::from(bucket.x) + usize::from(bucket.width) + margin).min(image_width) - x;
+        let height = (usize::from(bucket.y) + usize::from(bucket.height) + margin).min(image_height) - y;
+        Self { 
+            x, y, width, height, 
+            pixels: vec![RtPixelSamples::default(); width * height] 
+        }
+    }
+
+    /// Samples of the pixel (x, y) of the image
+    pub fn get_pixel_mut(&mut self, x: usize, y: usize) -> &mut RtPixelSamples {
+        &mut self.pixels[(y - self.y) * self.width + (x - self.x)]
+    }
+
+    /// Splat a sample of the pixel (x, y), at the position (px, py) in 
+    /// the pixel, to the pixels of the tile in the support of the filter
+    pub fn splat(&mut self, filter: &RtFilter, x: usize, y: usize, px: f32, py: f32, color: RtRGBA) {
+        let margin = filter.margin();
+        let (x0, x1) = (x.saturating_sub(margin).max(self.x), (x + margin + 1).min(self.x + self.width));
+        let (y0, y1) = (y.saturating_sub(margin).max(self.y), (y + margin + 1).min(self.y + self.height));
+        for ty in y0..y1 {
+            // Offsets from the pixel centers are computed from the pixel of 
+            // the sample, x + px would lose the precision of px
+            let dy = (y as f32 - ty as f32) + (py - 0.5);
+            for tx in x0..x1 {
+                let dx = (x as f32 - tx as f32) + (px - 0.5);
+                let weight = filter.eval(dx, dy);
+                if weight != 0.0 {
+                    self.get_pixel_mut(tx, ty).splat(color, weight);
+                }
+            }
+        }
+    }
+}
+
+
+// ========================================
+//  AOVs
+// ========================================
+
+/// Image stored in the render result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtAov {
+    /// Rendered colors
+    #[default]
+    Beauty,
+    /// Number of samples of each pixel, divided by the highest count
+    SampleCount,
+}
+
+impl RtAov {
+    pub const ALL: [RtAov; 2] = [Self::Beauty, Self::SampleCount];
+
+    /// Name used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Beauty => "beauty",
+            Self::SampleCount => "spp",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+}
+
+
+// ========================================
+//  Render result
+// ========================================
 
 /// Holds the result from the render
+/// 
+/// The pixels are behind a lock so that render threads can 
+/// write their buckets while the image is being displayed.
 pub struct RtRenderResult {
     pub width: usize,
     pub height: usize,
-    /// Array of array of color
-    /// To access : render_grid[col][row] -> index from top left to bottom right
-    render_grid: Vec<Vec<RtRGBA>>,
+    /// Array of array of pixel samples
+    /// To access : render_grid[row][col] -> index from top left to bottom right
+    render_grid: Mutex<Vec<Vec<RtPixelSamples>>>,
 }
 
 impl RtRenderResult {
     /// Creates a new render result with black pixels
     pub fn new(width: usize, height: usize) -> Self {
-        let mut render = Self {
+        let mut render_grid = Vec::with_capacity(height);
+        for _ in 0..height {
+            // Add a row of black pixels
+            render_grid.push(vec![RtPixelSamples::default(); width]);
+        }
+        Self {
             width,
             height,
-            render_grid: Vec::with_capacity(usize::from(width))
-        };
-        for y in 0..height {
-            // Add a row with known size
-            render.render_grid.push(Vec::with_capacity(height));
-            // For each cell add color
-            for _ in 0..width {
-                render.render_grid[y].push(RtRGBA::default())
-            }
+            render_grid: Mutex::new(render_grid)
+        }
+    }
+
+    fn lock_grid(&self) -> MutexGuard<'_, Vec<Vec<RtPixelSamples>>> {
+        // A render thread that panicked can't leave a pixel half written
+        self.render_grid.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Remove the samples of all the pixels
+    pub fn clear(&self) {
+        for row in self.lock_grid().iter_mut() {
+            row.fill(RtPixelSamples::default());
         }
-        render
     }
 
     /// Utility function to set the color of a pixel
-    pub fn set_pixel_color(&mut self, x: usize, y: usize, color: RtRGBA) {
-        self.render_grid[y][x] = color;
+    pub fn set_pixel_color(&self, x: usize, y: usize, color: RtRGBA) {
+        let mut samples = RtPixelSamples::default();
+        samples.add_sample(color);
+        self.lock_grid()[y][x] = samples;
+    }
+
+    /// Add the samples of a finished tile
+    pub fn add_tile(&self, tile: &RtFilmTile) {
+        let mut grid = self.lock_grid();
+        for (row, row_samples) in tile.pixels.chunks(tile.width).enumerate() {
+            let grid_row = &mut grid[tile.y + row][tile.x..tile.x + tile.width];
+            for (pixel, samples) in grid_row.iter_mut().zip(row_samples) {
+                pixel.merge(samples);
+            }
+        }
+    }
+
+    /// Samples accumulated in a pixel
+    pub fn get_pixel_samples(&self, x: usize, y: usize) -> RtPixelSamples {
+        self.lock_grid()[y][x]
+    }
+
+    /// Samples accumulated in the pixels of a bucket, row by row
+    pub fn get_bucket_samples(&self, bucket: &RtBucket) -> Vec<RtPixelSamples> {
+        let grid = self.lock_grid();
+        bucket.pixels().map(|pixel| grid[pixel.y()][pixel.x()]).collect()
     }
 
     /// Utility function to query the color of a pixel
-    pub fn rt_get_pixel_color(&mut self, x: usize, y: usize) -> RtRGBA {
-        self.render_grid[y][x]
+    pub fn rt_get_pixel_color(&self, x: usize, y: usize) -> RtRGBA {
+        self.lock_grid()[y][x].get_color()
     }
     
     /// Utility function to query the color of a pixel
-    pub fn get_pixel_color(&mut self, x: usize, y: usize) -> Color32 {
-        let color = self.render_grid[y][x];
-        color.to_color32()
+    pub fn get_pixel_color(&self, x: usize, y: usize) -> Color32 {
+        let color = self.lock_grid()[y][x].get_color();
+        color.linear_to_srgb().to_color32()
     }
 
-    pub fn export_as_ppm(&self) {
-        // TODO
-        error!("Function export_as_ppm not implemented yet");
+    /// Copy of all the pixels, row by row
+    pub fn get_pixels(&self) -> Vec<Vec<RtRGBA>> {
+        self.get_aov(RtAov::Beauty)
+    }
+
+    /// Number of samples of each pixel, row by row
+    pub fn get_sample_counts(&self) -> Vec<Vec<u32>> {
+        self.lock_grid().iter()
+            .map(|row| row.iter().map(|samples| samples.count).collect())
+            .collect()
+    }
+
+    /// Pixels of an AOV, row by row
+    pub fn get_aov(&self, aov: RtAov) -> Vec<Vec<RtRGBA>> {
+        let grid = self.lock_grid();
+        match aov {
+            RtAov::Beauty => grid.iter()
+                .map(|row| row.iter().map(|samples| samples.get_color()).collect())
+                .collect(),
+            RtAov::SampleCount => {
+                let max_count = grid.iter().flatten().map(|samples| samples.count).max().unwrap_or(0).max(1);
+                grid.iter()
+                    .map(|row| row.iter().map(|samples| {
+                        let v = samples.count as f32 / max_count as f32;
+                        RtRGBA::from_rgb(v, v, v)
+                    }).collect())
+                    .collect()
+            }
+        }
+    }
+
+    /// sRGB encoded 8-bit RGB values of an AOV, row by row
+    fn get_rgb8(&self, aov: RtAov) -> Vec<u8> {
+        let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let pixels = self.get_aov(aov);
+        let mut data = Vec::with_capacity(3 * self.width * self.height);
+        for color in pixels.iter().flatten().map(|c| c.linear_to_srgb()) {
+            data.extend([to_u8(color.r), to_u8(color.g), to_u8(color.b)]);
+        }
+        data
+    }
+
+    /// Write an AOV as a binary PPM file
+    pub fn export_as_ppm(&self, path: &Path, aov: RtAov) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Cannot create {} : {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)
+            .and_then(|_| writer.write_all(&self.get_rgb8(aov)))
+            .map_err(|e| format!("Cannot write {} : {}", path.display(), e))
+    }
+
+    /// Write an AOV as a 8-bit PNG file
+    pub fn export_as_png(&self, path: &Path, aov: RtAov) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("Cannot create {} : {}", path.display(), e))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.get_rgb8(aov)))
+            .map_err(|e| format!("Cannot write {} : {}", path.display(), e))
+    }
+
+    /// Write the linear values of an AOV as a 32-bit float OpenEXR file
+    ///
+    /// The alpha of the samples is not a coverage, and the background
+    /// is always rendered : the image is written as opaque.
+    pub fn export_as_exr(&self, path: &Path, aov: RtAov) -> Result<(), String> {
+        let pixels = self.get_aov(aov);
+        exr::prelude::write_rgba_file(path, self.width, self.height, |x, y| {
+            let color = pixels[y][x];
+            (color.r, color.g, color.b, 1.0)
+        }).map_err(|e| format!("Cannot write {} : {}", path.display(), e))
+    }
+
+    /// Lowercase extension of an image path
+    fn get_extension(path: &Path) -> String {
+        path.extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+
+    /// Returns true if export_image can write this file format
+    pub fn is_supported_format(path: &Path) -> bool {
+        matches!(Self::get_extension(path).as_str(), "ppm" | "png" | "exr")
+    }
+
+    /// Write the image, in a format depending on the file extension
+    pub fn export_image(&self, path: &Path) -> Result<(), String> {
+        self.export_aov(path, RtAov::Beauty)
+    }
+
+    /// Write an AOV, in a format depending on the file extension
+    pub fn export_aov(&self, path: &Path, aov: RtAov) -> Result<(), String> {
+        match Self::get_extension(path).as_str() {
+            "ppm" => self.export_as_ppm(path, aov)?,
+            "png" => self.export_as_png(path, aov)?,
+            "exr" => self.export_as_exr(path, aov)?,
+            _ => return Err(format!("Unsupported image format : {}", path.display()))
+        }
+        info!("Image saved to {}", path.display());
+        Ok(())
     }
 }
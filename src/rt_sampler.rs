@@ -7,153 +7,670 @@
 
 use crate::rt_types::*;
 
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
 
+// ========================================
+//  Random numbers
+// ========================================
 
-pub fn random_float() -> f32 {
-    rand::thread_rng().gen()
+/// Seeded random number generator (PCG32) for the randomness
+/// that doesn't come from the render samples, e.g. scene generation
+#[derive(Debug, Clone)]
+pub struct RtRng {
+    state: u64,
+    inc: u64,
 }
 
-pub fn random_float_range(x: f32, y: f32) -> f32 {
-    rand::thread_rng().gen_range(x..y)
+impl RtRng {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Self { state: 0, inc: (seed << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(0x853c49e6748fea9b ^ seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old_state = self.state;
+        self.state = old_state.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+        let xorshifted = (((old_state >> 18) ^ old_state) >> 27) as u32;
+        let rot = (old_state >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Float in [0, 1)
+    pub fn random_float(&mut self) -> f32 {
+        to_unit_float(self.next_u32())
+    }
+
+    /// Float in [x, y)
+    pub fn random_float_range(&mut self, x: f32, y: f32) -> f32 {
+        (x + (y - x) * self.random_float()).min(y.max(x))
+    }
 }
 
 impl RtVec3 {
-    pub fn random() -> RtVec3 {
-        RtVec3::new(random_float(), random_float(), random_float())
+    pub fn random(rng: &mut RtRng) -> RtVec3 {
+        RtVec3::new(rng.random_float(), rng.random_float(), rng.random_float())
     }
 
-    pub fn random_range(min: f32, max: f32) -> RtVec3 {
+    pub fn random_range(rng: &mut RtRng, min: f32, max: f32) -> RtVec3 {
         RtVec3::new(
-            random_float_range(min,max), 
-            random_float_range(min,max), 
-            random_float_range(min,max)
+            rng.random_float_range(min,max), 
+            rng.random_float_range(min,max), 
+            rng.random_float_range(min,max)
         )
     }
 }
 
 
 // ========================================
-//  Sampler
+//  Warping functions
+//  (uniform samples in [0, 1) to directions)
 // ========================================
 
-pub struct RtSampler {
-    // parameters
-    seed: u64, 
-    nsamples: u8, 
-    ndim: u8,
+/// Cosine weighted direction in the hemisphere around `n`
+pub fn sample_cosine_hemisphere(n: &RtVec3, u1: f32, u2: f32) -> RtVec3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * RT_PI * u2;
+    let (t, b) = n.coordinate_system();
+    let z = (1.0 - u1).max(0.0).sqrt();
+    (r * phi.cos()) * t + (r * phi.sin()) * b + z * *n
 }
 
-impl RtSampler {
-    pub fn new(seed: u64, nsamples: u8, ndim: u8) -> RtSampler {
-        RtSampler { 
-            seed, 
-            nsamples, 
-            ndim,
-        }
+/// Density in solid angle of sample_cosine_hemisphere
+pub fn pdf_cosine_hemisphere(cos_theta: f32) -> f32 {
+    cos_theta.max(0.0) * RT_ONEOVERPI
+}
+
+/// Uniform direction in the cone around `axis`
+pub fn sample_uniform_cone(axis: &RtVec3, cos_max: f32, u1: f32, u2: f32) -> RtVec3 {
+    let cos_theta = 1.0 - u1 * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * RT_PI * u2;
+    let (t, b) = axis.coordinate_system();
+    (sin_theta * phi.cos()) * t + (sin_theta * phi.sin()) * b + cos_theta * *axis
+}
+
+/// Density in solid angle of sample_uniform_cone
+pub fn pdf_uniform_cone(cos_max: f32) -> f32 {
+    1.0 / (2.0 * RT_PI * (1.0 - cos_max))
+}
+
+/// Direction around `axis` following the normalized Phong lobe cos^exponent
+pub fn sample_phong_lobe(axis: &RtVec3, exponent: f32, u1: f32, u2: f32) -> RtVec3 {
+    let cos_alpha = u1.powf(1.0 / (exponent + 1.0));
+    let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+    let phi = 2.0 * RT_PI * u2;
+    let (t, b) = axis.coordinate_system();
+    (sin_alpha * phi.cos()) * t + (sin_alpha * phi.sin()) * b + cos_alpha * *axis
+}
+
+/// Density in solid angle of sample_phong_lobe, 
+/// `cos_alpha` being the cosine to the lobe axis
+pub fn pdf_phong_lobe(cos_alpha: f32, exponent: f32) -> f32 {
+    if cos_alpha <= 0.0 {
+        return 0.0
+    }
+    (exponent + 1.0) * 0.5 * RT_ONEOVERPI * cos_alpha.powf(exponent)
+}
+
+/// Uniform point in the unit disk (Shirley's concentric mapping)
+pub fn sample_concentric_disk(u1: f32, u2: f32) -> (f32, f32) {
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0)
     }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, 0.25 * RT_PI * (b / a))
+    } else {
+        (b, 0.5 * RT_PI - 0.25 * RT_PI * (a / b))
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// Uniform point in the regular polygon with `sides` vertices on the
+/// unit circle, the first one at the angle `rotation` (radians)
+pub fn sample_regular_polygon(sides: u16, rotation: f32, u1: f32, u2: f32) -> (f32, f32) {
+    // Pick a triangle between the center and a side, then reuse u1
+    let n = sides.max(3) as f32;
+    let side = (u1 * n).floor().min(n - 1.0);
+    let u1 = u1 * n - side;
+    let phi0 = rotation + 2.0 * RT_PI * side / n;
+    let phi1 = phi0 + 2.0 * RT_PI / n;
+    // Uniform point in the triangle
+    let s = u1.sqrt();
+    let (a, b) = (s * (1.0 - u2), s * u2);
+    (a * phi0.cos() + b * phi1.cos(), a * phi0.sin() + b * phi1.sin())
 }
 
 
 // ========================================
-//  Sampler Iterator
+//  Tabulated distributions
 // ========================================
 
-pub struct RtSampleIterator<'a> {
-    stop: bool,
-    sampler: &'a RtSampler,
+/// Piecewise constant distribution over [0, 1), 
+/// proportional to the values of a function
+#[derive(Debug, Clone)]
+pub struct RtDistribution1D {
+    func: Vec<f32>,
+    /// cdf[i] is the probability of the values before i, cdf[n] = 1
+    cdf: Vec<f32>,
+    /// Integral of the function over [0, 1)
+    integral: f32,
+}
 
-    // To iterate on
-    rng: ChaCha8Rng,
-    // Current state
-    taken: u8
+impl RtDistribution1D {
+    /// Distribution of the values of `func`, negative values count as 0.
+    /// A function that is 0 everywhere gives a uniform distribution.
+    pub fn new(func: &[f32]) -> Self {
+        let n = func.len().max(1);
+        let mut func: Vec<f32> = func.iter().map(|f| f.max(0.0)).collect();
+        func.resize(n, 0.0);
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i] / n as f32;
+        }
+        let integral = cdf[n];
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            func.iter_mut().for_each(|f| *f = 1.0);
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f32 / n as f32);
+        }
+        cdf[n] = 1.0;
+        Self { func, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Integral of the function, 0 if the distribution fell back to uniform
+    pub fn integral(&self) -> f32 {
+        self.integral
+    }
+
+    /// Integral of the sampled values
+    fn norm(&self) -> f32 {
+        if self.integral > 0.0 { self.integral } else { 1.0 }
+    }
+
+    /// Sample x in [0, 1), returns x, its density and the index of its value
+    pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.count();
+        // Last value with cdf <= u, skipping the values of probability 0
+        let index = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(n - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let du = if width > 0.0 { ((u - self.cdf[index]) / width).clamp(0.0, 1.0) } else { 0.0 };
+        let x = ((index as f32 + du) / n as f32).min(1.0 - f32::EPSILON);
+        (x, self.func[index] / self.norm(), index)
+    }
+
+    /// Density of x in [0, 1)
+    pub fn pdf(&self, x: f32) -> f32 {
+        let index = ((x * self.count() as f32) as usize).min(self.count() - 1);
+        self.func[index] / self.norm()
+    }
+}
+
+/// Piecewise constant distribution over [0, 1)², proportional to the
+/// values of a function on a grid of nu x nv cells (rows of nu values)
+#[derive(Debug, Clone)]
+pub struct RtDistribution2D {
+    /// Distribution of u in each row
+    conditional: Vec<RtDistribution1D>,
+    /// Distribution of the rows
+    marginal: RtDistribution1D,
 }
 
-// From AiSamplerIterator :
-// 
-// Call once before your sampling loop. 
-// The iterator guarantees a unique sequence of sample points based on the 
-// pixel location, subpixel sample, ray-tree depth, etc. 
-// However, creating an AtSamplerIterator multiple times in the same 
-// rendering state (e.g. multiple times in the same shader_evaluate method) 
-// will produce identical sampling patterns. 
-// The iterator will automatically switch to using a single sample 
-// if invoked "behind" another ray split (such as after a diffuse or glossy ray).
-impl<'a> RtSampleIterator<'a> {
-    pub fn new(sampler: &'a RtSampler) -> Self {
-        Self { 
-            stop: false, 
-            sampler,  
-            rng: ChaCha8Rng::seed_from_u64(sampler.seed), 
-            taken: 0 }
+impl RtDistribution2D {
+    pub fn new(func: &[f32], nu: usize, nv: usize) -> Self {
+        let conditional: Vec<RtDistribution1D> = (0..nv)
+            .map(|v| RtDistribution1D::new(&func[v * nu..(v + 1) * nu]))
+            .collect();
+        let row_integrals: Vec<f32> = conditional.iter().map(|row| row.integral()).collect();
+        let marginal = RtDistribution1D::new(&row_integrals);
+        Self { conditional, marginal }
     }
 
-    fn take(&mut self) -> Vec<f32> {
-        self.taken += 1;
-        let res = vec![0.0; usize::from(self.sampler.ndim)];
-        res.into_iter().map(|_| self.rng.gen()).collect()
+    /// Sample (u, v) in [0, 1)², returns it with its density
+    pub fn sample(&self, u1: f32, u2: f32) -> ((f32, f32), f32) {
+        let (v, pdf_v, row) = self.marginal.sample(u2);
+        let (u, pdf_u, _) = self.conditional[row].sample(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    /// Density of (u, v) in [0, 1)²
+    pub fn pdf(&self, u: f32, v: f32) -> f32 {
+        let row = ((v * self.conditional.len() as f32) as usize).min(self.conditional.len() - 1);
+        self.marginal.pdf(v) * self.conditional[row].pdf(u)
     }
 }
 
-impl<'a> Iterator for RtSampleIterator<'a> {
-    type Item = Vec<f32>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.stop {
-            return None
-        }
 
-        let sample = self.take();
+// ========================================
+//  Hashing
+// ========================================
+
+/// Integer hash (Chris Wellons' lowbias32)
+pub fn hash_u32(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^ (h >> 16)
+}
+
+/// Hash of a seed and a value
+pub fn hash_combine(seed: u32, value: u32) -> u32 {
+    hash_u32(seed ^ value.wrapping_add(0x9e3779b9).wrapping_add(seed << 6).wrapping_add(seed >> 2))
+}
+
+/// Float in [0, 1) from the 24 high bits
+fn to_unit_float(bits: u32) -> f32 {
+    (bits >> 8) as f32 / (1 << 24) as f32
+}
 
-        // Can we keep generating ?
-        if self.taken >= self.sampler.nsamples {
-            self.stop = true;
+/// Random permutation of [0, len) (Kensler, Correlated Multi-Jittered Sampling)
+fn permute(mut i: u32, len: u32, seed: u32) -> u32 {
+    if len <= 1 {
+        return 0
+    }
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            break;
         }
+    }
+    (i.wrapping_add(seed)) % len
+}
+
+
+// ========================================
+//  Low-discrepancy sequences
+// ========================================
+
+/// Largest float below 1
+const ONE_MINUS_EPSILON: f32 = 0.99999994;
+
+/// Bases of the Halton sequence, dimensions above use independent samples
+const HALTON_PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
 
-        Some(sample)
+/// Radical inverse of `index` in `base`, with the digits randomly
+/// permuted depending on the previous ones (Owen scrambling)
+fn scrambled_radical_inverse(base: u32, mut index: u32, seed: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0_f64;
+    let mut reversed = 0_u64;
+    while index > 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        let digit_seed = hash_combine(seed, reversed as u32);
+        reversed = reversed * u64::from(base) + u64::from(permute(digit, base, digit_seed));
+        inv_base_n *= inv_base;
+        index = next;
     }
+    // The permutations of the remaining zero digits give a uniform value
+    let tail = to_unit_float(hash_combine(seed, reversed as u32)) as f64;
+    (((reversed as f64 + tail) * inv_base_n) as f32).min(ONE_MINUS_EPSILON)
+}
+
+/// Direction numbers of the second dimension of Sobol (polynomial x + 1)
+const SOBOL_DIRECTIONS_1: [u32; 32] = {
+    let mut directions = [0_u32; 32];
+    let mut v = 1_u32 << 31;
+    let mut bit = 0;
+    while bit < 32 {
+        directions[bit] = v;
+        v ^= v >> 1;
+        bit += 1;
+    }
+    directions
+};
+
+/// First two dimensions of the Sobol sequence, as 32 bits fractions
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0_u32;
+    let mut bits = index;
+    while bits != 0 {
+        let bit = bits.trailing_zeros();
+        y ^= SOBOL_DIRECTIONS_1[bit as usize];
+        bits &= bits - 1;
+    }
+    // The first dimension is the van der Corput sequence
+    (index.reverse_bits(), y)
+}
+
+/// Owen scrambling of a 32 bits fraction (Burley, Practical Hash-based Owen Scrambling)
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
 }
 
 
 // ========================================
-//  Utility functions
+//  Sampler
 // ========================================
 
-// from AiSamplerGetSample
-//
-// Get the next sample in an iterator.
-// Call this in a loop to obtain new samples until it returns false.
-pub fn RtGetSample(it: &mut RtSampleIterator<'_>, sample: &mut Vec<f32>) -> bool {
-    let mut x: usize = 0;
-    let res = (*it).next();
-    if res.is_some() {
-        for el in res.unwrap() {
-            sample[x] = el;
-            x += 1;
-        }
-        return true;
-    } else {
-        return false;
+/// Sequence used to generate the samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtSamplerType {
+    /// Uncorrelated random values
+    Independent,
+    /// Jittered strata, shuffled in each dimension
+    Stratified,
+    /// Halton sequence, scrambled per pixel
+    Halton,
+    /// Owen-scrambled Sobol sequence, shuffled in each dimension
+    #[default]
+    Sobol,
+}
+
+impl RtSamplerType {
+    pub const ALL: [RtSamplerType; 4] = [Self::Independent, Self::Stratified, Self::Halton, Self::Sobol];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Independent => "independent",
+            Self::Stratified => "stratified",
+            Self::Halton => "halton",
+            Self::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|sampler| sampler.name() == name)
     }
 }
 
-// from AiSamplerGetSampleCount
-//
-// Get the number of samples taken from this iterator.
-// Call this after the loop is done to see exactly how many samples were taken.
-pub fn RtGetSampleCount(it: &RtSampleIterator<'_>) -> u8 {
-    it.taken 
-    // * it.sampler.nsamples
+/// Dimensions of the camera samples
+pub const RT_DIM_PIXEL: u32 = 0;
+pub const RT_DIM_LENS: u32 = 2;
+/// Dimensions of a bounce start at RT_DIM_BOUNCE + bounces * RT_DIMS_PER_BOUNCE
+pub const RT_DIM_BOUNCE: u32 = 4;
+pub const RT_DIMS_PER_BOUNCE: u32 = 6;
+/// Dimensions used at each bounce
+pub const RT_DIM_LIGHT_PICK: u32 = 0;
+pub const RT_DIM_LIGHT: u32 = 1;
+pub const RT_DIM_BSDF: u32 = 3;
+pub const RT_DIM_RUSSIAN_ROULETTE: u32 = 5;
+
+/// Generates the sample values of the pixels
+/// 
+/// The values only depend on the pixel, the sample index and the 
+/// dimension, so they don't depend on the order of evaluation. 
+/// Dimensions are decorrelated from each other and from one pixel 
+/// to another.
+#[derive(Debug, Clone, Copy)]
+pub struct RtSampler {
+    pub sampler_type: RtSamplerType,
+    pub seed: u32,
+    /// Number of samples per pixel, used to stratify
+    pub nsamples: u32,
+}
+
+impl RtSampler {
+    pub fn new(sampler_type: RtSamplerType, seed: u32, nsamples: u32) -> RtSampler {
+        RtSampler { 
+            sampler_type,
+            seed, 
+            nsamples: nsamples.max(1),
+        }
+    }
+
+    /// Seed of a dimension of a pixel
+    fn get_seed(&self, x: u16, y: u16, dimension: u32) -> u32 {
+        let pixel = u32::from(x) | (u32::from(y) << 16);
+        hash_combine(hash_combine(self.seed, pixel), dimension)
+    }
+
+    /// Value in [0, 1) of a dimension of the sample `index` of the pixel (x, y)
+    pub fn get_1d(&self, x: u16, y: u16, index: u32, dimension: u32) -> f32 {
+        let seed = self.get_seed(x, y, dimension);
+        match self.sampler_type {
+            RtSamplerType::Independent => to_unit_float(hash_combine(seed, index)),
+            RtSamplerType::Stratified => {
+                let stratum = permute(index % self.nsamples, self.nsamples, seed);
+                let jitter = to_unit_float(hash_combine(seed, index));
+                (stratum as f32 + jitter) / self.nsamples as f32
+            }
+            RtSamplerType::Halton => {
+                let Some(&base) = HALTON_PRIMES.get(dimension as usize) else {
+                    return to_unit_float(hash_combine(seed, index))
+                };
+                scrambled_radical_inverse(base, index, seed)
+            }
+            RtSamplerType::Sobol => {
+                let shuffled = nested_uniform_scramble(index, seed);
+                let (x, _) = sobol_2d(shuffled);
+                to_unit_float(nested_uniform_scramble(x, hash_u32(seed)))
+            }
+        }
+    }
+
+    /// Values in [0, 1)² of two dimensions, stratified together
+    /// 
+    /// The second value uses `dimension + 1`.
+    pub fn get_2d(&self, x: u16, y: u16, index: u32, dimension: u32) -> (f32, f32) {
+        let seed = self.get_seed(x, y, dimension);
+        match self.sampler_type {
+            RtSamplerType::Stratified => {
+                // Jittered grid of at least nsamples cells
+                let nx = (self.nsamples as f32).sqrt().ceil() as u32;
+                let ny = self.nsamples.div_ceil(nx);
+                let cell = permute(index % (nx * ny), nx * ny, seed);
+                let jitter_x = to_unit_float(hash_combine(seed, index));
+                let jitter_y = to_unit_float(hash_combine(hash_u32(seed), index));
+                (((cell % nx) as f32 + jitter_x) / nx as f32, 
+                 ((cell / nx) as f32 + jitter_y) / ny as f32)
+            }
+            RtSamplerType::Sobol => {
+                let shuffled = nested_uniform_scramble(index, seed);
+                let (x, y) = sobol_2d(shuffled);
+                (to_unit_float(nested_uniform_scramble(x, hash_u32(seed))), 
+                 to_unit_float(nested_uniform_scramble(y, hash_u32(seed ^ 0x5bd1e995))))
+            }
+            RtSamplerType::Independent | RtSamplerType::Halton => (
+                self.get_1d(x, y, index, dimension), 
+                self.get_1d(x, y, index, dimension + 1)
+            ),
+        }
+    }
 }
 
-// from AiSamplerGetSampleInvCount
-// 
-// Get the inverse sample count.
-// Call this after the loop is done to normalize your integral. 
-// This avoids calling AiSamplerGetSampleCount(), checking for 
-// 0 and inverting the result.
-pub fn RtSamplerGetSampleInvCount(it: &RtSampleIterator<'_>) -> f32 {
-    let nb_samples = RtGetSampleCount(it) as f32;
-    if nb_samples == 0.0 { 1.0 } else { 1.0 / nb_samples }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Index of the interval of width 1 / n holding the value
+    fn stratum(value: f32, n: u32) -> u32 {
+        (value * n as f32) as u32
+    }
+
+    /// Checks that each value falls in its own stratum
+    fn assert_one_per_stratum(strata: impl Iterator<Item = u32>, nstrata: u32) {
+        let mut seen = vec![false; nstrata as usize];
+        for s in strata {
+            assert!(s < nstrata, "stratum {s} out of {nstrata}");
+            assert!(!seen[s as usize], "stratum {s} sampled twice");
+            seen[s as usize] = true;
+        }
+    }
+
+    #[test]
+    fn samples_in_unit_range() {
+        for sampler_type in RtSamplerType::ALL {
+            for nsamples in [1, 7, 64] {
+                let sampler = RtSampler::new(sampler_type, 42, nsamples);
+                for (x, y) in [(0, 0), (3, 7), (u16::MAX, u16::MAX)] {
+                    for dimension in [0, 1, 5, 63, 64, 200] {
+                        for index in (0..2 * nsamples).chain([1000, u32::MAX]) {
+                            let v = sampler.get_1d(x, y, index, dimension);
+                            assert!((0.0..1.0).contains(&v), "{} : {v}", sampler_type.name());
+                            let (u1, u2) = sampler.get_2d(x, y, index, dimension);
+                            assert!((0.0..1.0).contains(&u1) && (0.0..1.0).contains(&u2),
+                                    "{} : ({u1}, {u2})", sampler_type.name());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stratified_one_per_stratum() {
+        for nsamples in [1, 2, 5, 16, 33, 100] {
+            let sampler = RtSampler::new(RtSamplerType::Stratified, 7, nsamples);
+            for (x, y, dimension) in [(0, 0, 0), (10, 3, 4), (5, 9, 17)] {
+                let values = (0..nsamples).map(|i| sampler.get_1d(x, y, i, dimension));
+                assert_one_per_stratum(values.map(|v| stratum(v, nsamples)), nsamples);
+
+                // The 2D grid has at least nsamples cells
+                let nx = (nsamples as f32).sqrt().ceil() as u32;
+                let ny = nsamples.div_ceil(nx);
+                let cells = (0..nsamples).map(|i| {
+                    let (u1, u2) = sampler.get_2d(x, y, i, dimension);
+                    stratum(u1, nx) + nx * stratum(u2, ny)
+                });
+                assert_one_per_stratum(cells, nx * ny);
+            }
+        }
+    }
+
+    #[test]
+    fn sobol_one_per_stratum() {
+        for log_n in 0..9 {
+            let n = 1_u32 << log_n;
+            let sampler = RtSampler::new(RtSamplerType::Sobol, 3, n);
+            for (x, y, dimension) in [(0, 0, 0), (10, 3, 4), (5, 9, 17)] {
+                let values = (0..n).map(|i| sampler.get_1d(x, y, i, dimension));
+                assert_one_per_stratum(values.map(|v| stratum(v, n)), n);
+
+                // The 2D points are a (0, m, 2)-net : one point in each
+                // elementary interval of area 1 / n
+                let points: Vec<(f32, f32)> = (0..n).map(|i| sampler.get_2d(x, y, i, dimension)).collect();
+                for log_nx in 0..=log_n {
+                    let (nx, ny) = (1 << log_nx, 1 << (log_n - log_nx));
+                    let cells = points.iter().map(|&(u1, u2)| stratum(u1, nx) + nx * stratum(u2, ny));
+                    assert_one_per_stratum(cells, n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distribution_1d_sample_and_pdf_agree() {
+        let func = [0.0, 1.0, 3.0, 0.0, 0.5, 2.0, 0.0];
+        let distribution = RtDistribution1D::new(&func);
+        let n = func.len();
+        let total: f32 = func.iter().sum();
+        assert!((distribution.integral() - total / n as f32).abs() < 1e-6);
+
+        // The density integrates to 1
+        let pdf_sum: f32 = (0..n).map(|i| distribution.pdf((i as f32 + 0.5) / n as f32)).sum();
+        assert!((pdf_sum / n as f32 - 1.0).abs() < 1e-6);
+
+        // Evenly spaced values of u give the expected number of samples in each cell
+        const NB_SAMPLES: usize = 7000;
+        let mut counts = vec![0; n];
+        for i in 0..NB_SAMPLES {
+            let u = i as f32 / NB_SAMPLES as f32;
+            let (x, pdf, index) = distribution.sample(u);
+            assert!((0.0..1.0).contains(&x));
+            assert_eq!(index, ((x * n as f32) as usize).min(n - 1), "u = {u}");
+            assert!(pdf > 0.0, "u = {u} sampled a cell of probability 0");
+            assert_eq!(pdf, distribution.pdf(x), "u = {u}");
+            counts[index] += 1;
+        }
+        for (count, f) in counts.iter().zip(func) {
+            let expected = NB_SAMPLES as f32 * f / total;
+            assert!((*count as f32 - expected).abs() <= 1.0, "{count} samples instead of {expected}");
+        }
+    }
+
+    #[test]
+    fn distribution_1d_zero_function_is_uniform() {
+        let distribution = RtDistribution1D::new(&[0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(distribution.integral(), 0.0);
+        for u in [0.0, 0.3, 0.6, ONE_MINUS_EPSILON] {
+            let (x, pdf, _) = distribution.sample(u);
+            assert!((x - u).abs() < 1e-6);
+            assert_eq!(pdf, 1.0);
+            assert_eq!(distribution.pdf(x), 1.0);
+        }
+    }
+
+    #[test]
+    fn distribution_2d_sample_and_pdf_agree() {
+        let (nu, nv) = (4, 3);
+        let func = [
+            1.0, 2.0, 0.0, 1.0,
+            0.0, 0.0, 0.0, 0.0,
+            4.0, 0.5, 1.0, 3.0,
+        ];
+        let distribution = RtDistribution2D::new(&func, nu, nv);
+        let total: f32 = func.iter().sum();
+
+        // The density integrates to 1
+        let pdf_sum: f32 = (0..nu * nv)
+            .map(|i| distribution.pdf(((i % nu) as f32 + 0.5) / nu as f32, ((i / nu) as f32 + 0.5) / nv as f32))
+            .sum();
+        assert!((pdf_sum / (nu * nv) as f32 - 1.0).abs() < 1e-5);
+
+        const NB_SAMPLES: usize = 200;
+        let mut counts = vec![0; nu * nv];
+        for i in 0..NB_SAMPLES {
+            for j in 0..NB_SAMPLES {
+                let (u1, u2) = (i as f32 / NB_SAMPLES as f32, j as f32 / NB_SAMPLES as f32);
+                let ((u, v), pdf) = distribution.sample(u1, u2);
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+                assert!(pdf > 0.0, "({u1}, {u2}) sampled a cell of probability 0");
+                assert!((pdf - distribution.pdf(u, v)).abs() <= 1e-5 * pdf, "({u1}, {u2})");
+                let cell = ((u * nu as f32) as usize).min(nu - 1) + nu * ((v * nv as f32) as usize).min(nv - 1);
+                counts[cell] += 1;
+            }
+        }
+        let nb_samples = (NB_SAMPLES * NB_SAMPLES) as f32;
+        for (count, f) in counts.iter().zip(func) {
+            let expected = nb_samples * f / total;
+            // Rounding : one sample per row for the conditional, one row for the marginal
+            assert!((*count as f32 - expected).abs() <= 2.0 * NB_SAMPLES as f32, "{count} samples instead of {expected}");
+        }
+    }
 }
@@ -9,18 +9,23 @@ use std::io::Read;
 use std::collections::HashMap;
 use std::io::BufReader;
 use std::fs::File;
-use std::path::Display;
+use std::path::{Display, Path, PathBuf};
 use std::str::FromStr;
 use std::any::{Any, TypeId};
 use itertools::Itertools;
 use quick_xml::Reader;
 use quick_xml::events::{Event, BytesStart};
+use quick_xml::events::attributes::{Attribute, AttrError};
 use log::{debug, error, info, warn};
 
-use crate::rt_camera::RtCamera;
+use crate::rt_camera::{RtCamera, RtLens, RtProjection};
+use crate::rt_bvh::RtBvh;
+use crate::rt_sampler::{RtSampler, RtSamplerType};
+use crate::rt_filter::{RtFilter, RtFilterType};
+use crate::rt_ray::{RtRay, RtRayHit};
 use crate::rt_objects::rt_object_base::*;
 use crate::rt_shaders::rt_shader_base::RtShader;
-use crate::rt_shaders::DEFAULT_SHADER;
+use crate::rt_shaders::{DEFAULT_SHADER, DEFAULT_LIGHT, DEFAULT_SKY};
 use crate::{RtPoint3, RtRGBA, RtVec3};
 
 // TODO : for the placeholder geometry !
@@ -28,24 +33,148 @@ use crate::{RtPoint3, RtRGBA, RtVec3};
 use crate::rt_shaders::lambert::LambertShader;
 use crate::rt_shaders::metal::Metal;
 use crate::rt_shaders::glass::Glass;
-use crate::rt_objects::rt_geometries::RtSphere;
+use crate::rt_shaders::lightShader::LightShader;
+use crate::rt_shaders::emission::EmissionShader;
+use crate::rt_objects::rt_geometries::{RtSphere, RtPolymesh};
+use crate::rt_objects::rt_lights::*;
+use crate::rt_obj_loader::load_obj_meshes;
+use crate::rt_image::RtImage;
+use crate::rt_ies::RtIesProfile;
 
 
 // ========================================
 //  Render settings
 // ========================================
 
+/// Strategy used to compute direct lighting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtDirectLighting {
+    /// Combine light and BSDF sampling with multiple importance sampling
+    #[default]
+    Mis,
+    /// Only sample the lights, rays sampled by the BSDFs ignore the lights they hit
+    LightSampling,
+    /// Only sample the BSDFs, except for delta lights that can't be hit
+    BsdfSampling,
+}
+
+impl RtDirectLighting {
+    pub const ALL: [RtDirectLighting; 3] = [Self::Mis, Self::LightSampling, Self::BsdfSampling];
+
+    /// Name used in the XML scenes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mis => "mis",
+            Self::LightSampling => "lights",
+            Self::BsdfSampling => "bsdf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|strategy| strategy.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct RtRenderSettings {
     pub render_spp: u8,
-    pub max_bounces: u8,
+    /// Maximum number of bounces of a path, 0 for direct lighting only
+    pub max_bounces: u16,
+    /// Maximum number of bounces of each type
+    pub diffuse_depth: u16,
+    pub specular_depth: u16,
+    pub transmission_depth: u16,
+    /// Number of bounces before paths can be terminated by Russian roulette
+    pub rr_depth: u16,
+    /// Size of the buckets (tiles) in pixels
+    pub bucket_size: u16,
+    /// Number of render threads, 0 to use all cores
+    pub threads: usize,
+    /// Resolution of the image in pixels
+    pub width: u16,
+    pub height: u16,
+    /// Width of a pixel divided by its height
+    pub pixel_aspect: f32,
+    /// Sampling strategy for direct lighting
+    pub direct_lighting: RtDirectLighting,
+    /// Sequence of the pixel samples
+    pub sampler: RtSamplerType,
+    /// Seed of the sampler, renders with the same seed are identical
+    pub seed: u32,
+    /// Noise level under which a pixel stops being sampled, 0 to 
+    /// disable adaptive sampling and always use render_spp samples
+    pub adaptive_threshold: f32,
+    /// Maximum number of samples of a pixel with adaptive sampling,
+    /// render_spp is then the minimum
+    pub max_spp: u16,
+    /// Reconstruction filter of the pixels and its width in pixels
+    pub filter: RtFilterType,
+    pub filter_width: f32,
 }
 
+pub const RT_DEFAULT_BUCKET_SIZE: u16 = 32;
+pub const RT_DEFAULT_IMAGE_WIDTH: u16 = 400;
+pub const RT_DEFAULT_IMAGE_HEIGHT: u16 = 400;
+pub const RT_DEFAULT_RR_DEPTH: u16 = 3;
+
 impl RtRenderSettings {
-    pub fn new(render_spp: u8, max_bounces: u8) -> Self {
-        Self { render_spp, max_bounces }
+    /// Settings with the same depth for every bounce type
+    pub fn new(render_spp: u8, max_bounces: u16) -> Self {
+        Self { 
+            render_spp, max_bounces,
+            diffuse_depth: max_bounces,
+            specular_depth: max_bounces,
+            transmission_depth: max_bounces,
+            rr_depth: RT_DEFAULT_RR_DEPTH,
+            bucket_size: RT_DEFAULT_BUCKET_SIZE,
+            threads: 0,
+            width: RT_DEFAULT_IMAGE_WIDTH,
+            height: RT_DEFAULT_IMAGE_HEIGHT,
+            pixel_aspect: 1.0,
+            direct_lighting: RtDirectLighting::default(),
+            sampler: RtSamplerType::default(),
+            seed: 0,
+            adaptive_threshold: 0.0,
+            max_spp: u16::from(render_spp),
+            filter: RtFilterType::default(),
+            filter_width: RtFilterType::default().default_width(),
+        }
+    }
+
+    /// Number of threads to launch
+    pub fn get_nb_threads(&self) -> usize {
+        if self.threads > 0 {
+            self.threads
+        } else {
+            std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        }
     }
 
-    pub fn update(&mut self, render_spp: u8, max_bounces: u8) {
+    /// Whether pixels get a variable number of samples
+    pub fn is_adaptive(&self) -> bool {
+        self.adaptive_threshold > 0.0
+    }
+
+    /// Maximum number of samples of a pixel
+    pub fn get_max_spp(&self) -> u32 {
+        if self.is_adaptive() {
+            u32::from(self.max_spp).max(u32::from(self.render_spp))
+        } else {
+            u32::from(self.render_spp)
+        }
+    }
+
+    /// Sampler of the pixels
+    pub fn get_sampler(&self) -> RtSampler {
+        RtSampler::new(self.sampler, self.seed, self.get_max_spp())
+    }
+
+    /// Reconstruction filter of the pixels
+    pub fn get_filter(&self) -> RtFilter {
+        RtFilter::new(self.filter, self.filter_width)
+    }
+
+    pub fn update(&mut self, render_spp: u8, max_bounces: u16) {
         self.render_spp = render_spp;
         self.max_bounces = max_bounces;
     }
@@ -62,34 +191,93 @@ pub struct RtScene {
     pub settings: RtRenderSettings,
     camera: RtCamera,
     shapes: RtObjectList,
-    lights: RtObjectList,
+    lights: Vec<Box<dyn RtLight>>,
+    /// Acceleration structure over the shapes, built by finalize()
+    bvh: Option<RtBvh>,
+    /// Acceleration structure over the lights that rays can hit,
+    /// `finite_lights` maps its primitives to the list of lights
+    light_bvh: Option<RtBvh>,
+    finite_lights: Vec<usize>,
 }
 
 impl RtScene {
+    /// Creates an empty scene, the camera follows the resolution of the settings
     pub fn new(settings: RtRenderSettings, camera: RtCamera) -> Self {
-        Self {
+        let mut scene = Self {
             settings, camera,
             shapes: RtObjectList::new(),
-            lights: RtObjectList::new(),
-        }
+            lights: Vec::new(),
+            bvh: None,
+            light_bvh: None,
+            finite_lights: Vec::new(),
+        };
+        scene.update_camera_resolution();
+        scene
     }
     
     // === SETTERS ===
 
     pub fn set_settings(&mut self, settings: RtRenderSettings) {
         self.settings = settings;
+        self.update_camera_resolution();
     }
 
     pub fn set_camera(&mut self, camera: RtCamera) {
         self.camera = camera;
+        self.update_camera_resolution();
+    }
+
+    /// Rebuild the camera if it does not match the resolution of the settings
+    fn update_camera_resolution(&mut self) {
+        let (width, height, pixel_aspect) = 
+            (self.settings.width, self.settings.height, self.settings.pixel_aspect);
+        if !self.camera.has_resolution(width, height, pixel_aspect) {
+            self.camera = self.camera.with_resolution(width, height, pixel_aspect);
+        }
     }
 
+    /// Add a shape. The scene needs to be finalized again afterwards.
     pub fn add_shape(&mut self, shape: Box<dyn RtObject>) {
-        self.shapes.add_object(shape)
+        self.shapes.add_object(shape);
+        self.bvh = None;
     }
 
-    pub fn add_light(&mut self, light: Box<dyn RtObject>) {
-        self.lights.add_object(light)
+    /// Add a light. The scene needs to be finalized again afterwards.
+    pub fn add_light(&mut self, light: Box<dyn RtLight>) {
+        self.lights.push(light);
+        self.light_bvh = None;
+    }
+
+    /// Build the acceleration structure once all shapes are added.
+    pub fn finalize(&mut self) {
+        let now = std::time::Instant::now();
+        let bboxes: Vec<_> = self.list_shapes().iter().map(|shape| shape.get_bounding_box()).collect();
+        let bvh = RtBvh::build(&bboxes);
+        info!("> BVH built in {} sec ({} shapes, {} nodes)", 
+            now.elapsed().as_secs_f64(), bboxes.len(), bvh.num_nodes());
+        self.bvh = Some(bvh);
+
+        // Scenes without infinite lights are lit by a default sky
+        if !self.lights.iter().any(|light| light.is_infinite()) {
+            let object_params = ObjectParams::new(
+                String::from("default_sky"), String::from("physical_sky"), Box::new(DEFAULT_SKY));
+            self.add_light(Box::new(RtPhysicalSkyLight::new(object_params, 45.0, 0.0, 3.0)));
+        }
+
+        // Infinite lights are never hit, they stay out of the light BVH
+        self.finite_lights = (0..self.lights.len())
+            .filter(|&index| !self.lights[index].is_infinite())
+            .collect();
+        let bboxes: Vec<_> = self.finite_lights.iter()
+            .map(|&index| self.lights[index].get_bounding_box())
+            .collect();
+        let light_bvh = RtBvh::build(&bboxes);
+        info!("> Light BVH built ({} lights, {} nodes)", bboxes.len(), light_bvh.num_nodes());
+        self.light_bvh = Some(light_bvh);
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.bvh.is_some()
     }
 
     // === GETTERS ===
@@ -102,8 +290,81 @@ impl RtScene {
         self.shapes.list_objects()
     }
 
-    pub fn list_lights(&self) -> &Vec<Box<dyn RtObject>> {
-        self.lights.list_objects()
+    pub fn list_lights(&self) -> &Vec<Box<dyn RtLight>> {
+        &self.lights
+    }
+
+    // === INTERSECTIONS ===
+
+    /// Find the closest shape hit by the ray before max_dist
+    /// 
+    /// Falls back to testing every shape if the scene is not finalized.
+    pub fn closest_hit(&self, ray: &RtRay, max_dist: f32) -> Option<(RtRayHit, &dyn RtObject)> {
+        let shapes = self.list_shapes();
+        let mut closest_hit: Option<RtRayHit> = None;
+        let mut intersect = |index: usize, max_dist: f32| {
+            let hit = shapes[index].get_intersection_in_range(ray, max_dist)?;
+            let dist = hit.dist;
+            closest_hit = Some(hit);
+            Some(dist)
+        };
+        let closest = match &self.bvh {
+            Some(bvh) => bvh.closest_hit(ray, max_dist, intersect),
+            None => {
+                let mut closest = None;
+                let mut max_dist = max_dist;
+                for index in 0..shapes.len() {
+                    if let Some(dist) = intersect(index, max_dist) {
+                        max_dist = dist;
+                        closest = Some((index, dist));
+                    }
+                }
+                closest
+            }
+        };
+        let (index, _) = closest?;
+        Some((closest_hit?, shapes[index].as_ref()))
+    }
+
+    /// Find the closest light hit by the ray before max_dist
+    /// 
+    /// Falls back to testing every light if the scene is not finalized.
+    pub fn closest_light_hit(&self, ray: &RtRay, max_dist: f32) -> Option<(RtRayHit, &dyn RtLight)> {
+        let lights = self.list_lights();
+        let mut closest_hit: Option<RtRayHit> = None;
+        let mut intersect = |index: usize, max_dist: f32| {
+            let hit = lights[index].get_intersection_in_range(ray, max_dist)?;
+            let dist = hit.dist;
+            closest_hit = Some(hit);
+            Some(dist)
+        };
+        let closest = match &self.light_bvh {
+            Some(bvh) => bvh.closest_hit(ray, max_dist, |index, max_dist| intersect(self.finite_lights[index], max_dist))
+                .map(|(index, dist)| (self.finite_lights[index], dist)),
+            None => {
+                let mut closest = None;
+                let mut max_dist = max_dist;
+                for index in 0..lights.len() {
+                    if let Some(dist) = intersect(index, max_dist) {
+                        max_dist = dist;
+                        closest = Some((index, dist));
+                    }
+                }
+                closest
+            }
+        };
+        let (index, _) = closest?;
+        Some((closest_hit?, lights[index].as_ref()))
+    }
+
+    /// Is there any shape hit by the ray before max_dist
+    pub fn any_hit(&self, ray: &RtRay, max_dist: f32) -> bool {
+        let shapes = self.list_shapes();
+        let occluded = |index: usize, max_dist: f32| shapes[index].is_occluding(ray, max_dist);
+        match &self.bvh {
+            Some(bvh) => bvh.any_hit(ray, max_dist, occluded),
+            None => (0..shapes.len()).any(|index| occluded(index, max_dist))
+        }
     }
 }
 
@@ -117,6 +378,7 @@ const XML_ELEMENTS_LIST: &[&str] = &[
     "camera",
     "shader",
     "shape",
+    "light",
 ];
 
 struct XMLParam {
@@ -135,28 +397,34 @@ impl XMLParam {
         self.param_values.entry(key).or_insert(value);
     }
 
-    fn get_value(&self, k: &str) -> String {
+    fn get_value(&self, k: &str) -> Result<String, String> {
         if self.param_values.contains_key(k) {
-            self.param_values[k].clone()
+            Ok(self.param_values[k].clone())
         } else {
-            panic!("Key {} not in the parameter", k);
+            Err(format!("Key {} not in the parameter {}", k, self.param_name))
         }
     }
 
-    fn extract_param_value<T: FromStr + std::fmt::Debug>(&self, k: &str, extract_type: TypeId) -> T {
-        let str_value = self.get_value(k);
-        let parsed_value: T = match str_value.parse() {
-            Ok(v) => v,
-            Err(_) => panic!("Value {}:{} cannot be parsed to {:?}", k, str_value, extract_type)
-        };
-        parsed_value
+    fn extract_param_value<T: FromStr + std::fmt::Debug>(&self, k: &str, extract_type: TypeId) -> Result<T, String> {
+        let str_value = self.get_value(k)?;
+        match str_value.parse() {
+            Ok(v) => Ok(v),
+            Err(_) => Err(format!("Value {}:{} cannot be parsed to {:?}", k, str_value, extract_type))
+        }
+    }
+
+    fn extract_param_array<T: FromStr>(&self, k: &str) -> Result<Vec<T>, String> {
+        self.get_value(k)?
+            .split_whitespace()
+            .map(|v| v.parse().map_err(|_| format!("Value {}:{} cannot be parsed", k, v)))
+            .collect()
     }
 
     fn get_id(&self) -> Result<String, String> {
         if self.param_type != "node" {
             Err(format!("Parameter type is {}, not node", self.param_type))
         } else {
-            Ok(self.extract_param_value("id", TypeId::of::<String>()))
+            self.extract_param_value("id", TypeId::of::<String>())
         }
     }
     
@@ -164,7 +432,7 @@ impl XMLParam {
         if self.param_type != "string" {
             Err(format!("Parameter type is {}, not string", self.param_type))
         } else {
-            Ok(self.extract_param_value("value", TypeId::of::<String>()))
+            self.extract_param_value("value", TypeId::of::<String>())
         }
     }
 
@@ -172,7 +440,7 @@ impl XMLParam {
         if self.param_type != "int" {
             Err(format!("Parameter type is {}, not int", self.param_type))
         } else {
-            Ok(self.extract_param_value("value", TypeId::of::<u8>()))
+            self.extract_param_value("value", TypeId::of::<u8>())
         }
     }
 
@@ -180,7 +448,15 @@ impl XMLParam {
         if self.param_type != "int" {
             Err(format!("Parameter type is {}, not int", self.param_type))
         } else {
-            Ok(self.extract_param_value("value", TypeId::of::<u16>()))
+            self.extract_param_value("value", TypeId::of::<u16>())
+        }
+    }
+    
+    fn get_u32(&self) -> Result<u32, String> {
+        if self.param_type != "int" {
+            Err(format!("Parameter type is {}, not int", self.param_type))
+        } else {
+            self.extract_param_value("value", TypeId::of::<u32>())
         }
     }
     
@@ -188,7 +464,7 @@ impl XMLParam {
         if self.param_type != "float" {
             Err(format!("Parameter type is {}, not float", self.param_type))
         } else {
-            Ok(self.extract_param_value("value", TypeId::of::<f32>()))
+            self.extract_param_value("value", TypeId::of::<f32>())
         }
     }
 
@@ -197,9 +473,9 @@ impl XMLParam {
             Err(format!("Parameter type is {}, not rgb", self.param_type))
         } else {
             Ok(RtRGBA::new(
-                self.extract_param_value("r", TypeId::of::<f32>()), 
-                self.extract_param_value("g", TypeId::of::<f32>()), 
-                self.extract_param_value("b", TypeId::of::<f32>()), 
+                self.extract_param_value("r", TypeId::of::<f32>())?, 
+                self.extract_param_value("g", TypeId::of::<f32>())?, 
+                self.extract_param_value("b", TypeId::of::<f32>())?, 
             ))
         }
     }
@@ -209,21 +485,61 @@ impl XMLParam {
             Err(format!("Parameter type is {}, not point", self.param_type))
         } else {
             Ok(RtPoint3::new(
-                self.extract_param_value("x", TypeId::of::<f32>()), 
-                self.extract_param_value("y", TypeId::of::<f32>()), 
-                self.extract_param_value("z", TypeId::of::<f32>()), 
+                self.extract_param_value("x", TypeId::of::<f32>())?, 
+                self.extract_param_value("y", TypeId::of::<f32>())?, 
+                self.extract_param_value("z", TypeId::of::<f32>())?, 
             ))
         }
     }
 
+    fn get_u32_array(&self) -> Result<Vec<u32>, String> {
+        if self.param_type != "int_array" {
+            Err(format!("Parameter type is {}, not int_array", self.param_type))
+        } else {
+            self.extract_param_array("value")
+        }
+    }
+
+    fn get_f32_array(&self) -> Result<Vec<f32>, String> {
+        if self.param_type != "float_array" {
+            Err(format!("Parameter type is {}, not float_array", self.param_type))
+        } else {
+            self.extract_param_array("value")
+        }
+    }
+
+    fn get_point_array(&self) -> Result<Vec<RtPoint3>, String> {
+        if self.param_type != "point_array" {
+            Err(format!("Parameter type is {}, not point_array", self.param_type))
+        } else {
+            let values: Vec<f32> = self.extract_param_array("value")?;
+            if !values.len().is_multiple_of(3) {
+                return Err(format!("Parameter {} has {} values, not a multiple of 3", self.param_name, values.len()));
+            }
+            Ok(values.chunks(3).map(|p| RtPoint3::new(p[0], p[1], p[2])).collect())
+        }
+    }
+
+    fn get_vec3_array(&self) -> Result<Vec<RtVec3>, String> {
+        if self.param_type != "vec3_array" {
+            Err(format!("Parameter type is {}, not vec3_array", self.param_type))
+        } else {
+            let values: Vec<f32> = self.extract_param_array("value")?;
+            if !values.len().is_multiple_of(3) {
+                return Err(format!("Parameter {} has {} values, not a multiple of 3", self.param_name, values.len()));
+            }
+            Ok(values.chunks(3).map(|v| RtVec3::new(v[0], v[1], v[2])).collect())
+        }
+    }
+
     fn get_vec3(&self) -> Result<RtVec3, String> {
         if self.param_type != "vec3" {
             Err(format!("Parameter type is {}, not vec3", self.param_type))
         } else {
             Ok(RtVec3::new(
-                self.extract_param_value("x", TypeId::of::<f32>()), 
-                self.extract_param_value("y", TypeId::of::<f32>()), 
-                self.extract_param_value("z", TypeId::of::<f32>()), 
+                self.extract_param_value("x", TypeId::of::<f32>())?, 
+                self.extract_param_value("y", TypeId::of::<f32>())?, 
+                self.extract_param_value("z", TypeId::of::<f32>())?, 
             ))
         }
     }
@@ -249,6 +565,7 @@ struct XMLSceneElement {
     name: String,                   // "shader", "shape", "camera" ...
     identifier: Option<String>,     // unique identifier (e.g. "root/geo/shapes/my_shape")
     element_type: Option<String>,   // sub-type (e.g. shader->glass, shape->sphere)
+    attributes: HashMap<String, String>,  // other attributes of the tag (e.g. file="...")
     parameters: Vec<XMLParam>       // list of parameters
 }
 
@@ -270,7 +587,11 @@ impl std::fmt::Display for XMLSceneElement {
 
 impl XMLSceneElement {
     fn new(name: String, identifier: Option<String>, element_type: Option<String>) -> Self {
-        Self { name, identifier, element_type, parameters: Vec::new() }
+        Self { name, identifier, element_type, attributes: HashMap::new(), parameters: Vec::new() }
+    }
+
+    fn add_attribute(&mut self, key: String, value: String) {
+        self.attributes.insert(key, value);
     }
 
     fn add_parameter(&mut self, parameter: XMLParam) {
@@ -322,6 +643,15 @@ impl XMLSceneElement {
         return Err(String::from("No parameter named {parameter}"));
     }
 
+    fn get_u32_parameter(&self, parameter: &str) -> Result<u32, String> {
+        for p in &self.parameters {
+            if p.param_name == parameter {
+                return p.get_u32();
+            }
+        }
+        Err(format!("No parameter named {parameter}"))
+    }
+
     fn get_float_parameter(&self, parameter: &str) -> Result<f32, String> {
         for p in &self.parameters {
             if p.param_name == parameter {
@@ -357,6 +687,34 @@ impl XMLSceneElement {
         }
         return Err(String::from("No parameter named {parameter}"));
     }
+
+    fn get_u32_array_parameter(&self, parameter: &str) -> Result<Vec<u32>, String> {
+        match self.get_parameter(parameter) {
+            Some(p) => p.get_u32_array(),
+            None => Err(format!("No parameter named {parameter}"))
+        }
+    }
+
+    fn get_float_array_parameter(&self, parameter: &str) -> Result<Vec<f32>, String> {
+        match self.get_parameter(parameter) {
+            Some(p) => p.get_f32_array(),
+            None => Err(format!("No parameter named {parameter}"))
+        }
+    }
+
+    fn get_point_array_parameter(&self, parameter: &str) -> Result<Vec<RtPoint3>, String> {
+        match self.get_parameter(parameter) {
+            Some(p) => p.get_point_array(),
+            None => Err(format!("No parameter named {parameter}"))
+        }
+    }
+
+    fn get_vec_array_parameter(&self, parameter: &str) -> Result<Vec<RtVec3>, String> {
+        match self.get_parameter(parameter) {
+            Some(p) => p.get_vec3_array(),
+            None => Err(format!("No parameter named {parameter}"))
+        }
+    }
 }
 
 // We declare a XML scene as a list of XML scene elements
@@ -384,112 +742,103 @@ impl XMLScene {
         self.0.push(tag);
     }
 
-    fn process_attributes(node: BytesStart<'_>) -> Result<XMLParam, ()> {
-        let mut pName: Option<String> = None;
-        let mut pType: Option<String> = None;
+    /// Key and value of an attribute
+    fn read_attribute(attribute: Result<Attribute<'_>, AttrError>) -> Result<(String, String), String> {
+        let attribute = attribute.map_err(|e| format!("Could not read attribute : {e}"))?;
+        let key = std::str::from_utf8(attribute.key.as_ref())
+            .map_err(|e| format!("Could not read attribute name : {e}"))?;
+        let value = std::str::from_utf8(attribute.value.as_ref())
+            .map_err(|e| format!("Could not read attribute {key} : {e}"))?;
+        Ok((String::from(key), String::from(value)))
+    }
+
+    fn process_attributes(node: BytesStart<'_>) -> Result<XMLParam, String> {
+        let attributes = node.attributes()
+            .map(Self::read_attribute)
+            .collect::<Result<Vec<(String, String)>, String>>()
+            .map_err(|e| format!("Could not parse param : {e}"))?;
         // Find name and type
-        for attribute in node.attributes() {
-            let mut key = String::new();
-            _ = attribute.clone().unwrap().key.0.read_to_string(&mut key);
-            let value = attribute.clone().unwrap().value;
-            let value = std::str::from_utf8(value.as_ref()).unwrap();
-            if key == "type" {
-                pType = Some(String::from(value));
-            } else if key == "name" {
-                pName = Some(String::from(value));
-            }
-        }
-        if pType.is_none() || pName.is_none() {
+        let find = |k: &str| attributes.iter().find(|(key, _)| key == k).map(|(_, value)| value.clone());
+        let (Some(pType), Some(pName)) = (find("type"), find("name")) else {
             // Missing info
-            error!("Could not parse param !");
-            return Err(())
-        }
-        let mut xml_param = XMLParam::new(pType.unwrap(), pName.unwrap());
-        for attribute in node.attributes() {
-            let mut key = String::new();
-            _ = attribute.clone().unwrap().key.0.read_to_string(&mut key);
-            let value = attribute.clone().unwrap().value;
-            let value = std::str::from_utf8(value.as_ref()).unwrap();
+            return Err(String::from("Could not parse param : missing type or name"))
+        };
+        let mut xml_param = XMLParam::new(pType, pName);
+        for (key, value) in attributes {
             if key == "type" || key == "name" { continue };
-            xml_param.add_value(key, String::from(value));
+            xml_param.add_value(key, value);
         }
         debug!("    Parse param : {}", xml_param);
         Ok(xml_param)
     }
 
-    fn get_tag(tag_name: String, node: &BytesStart<'_>) -> Option<XMLSceneElement> {
+    fn get_tag(tag_name: String, node: &BytesStart<'_>) -> Result<XMLSceneElement, String> {
         // Check the tag is registered
-        if XML_ELEMENTS_LIST.contains(&tag_name.as_str()) {
-            debug!(" +Parse tag {}", tag_name);
-        } else {
-            error!(" +Unknown tag {}", tag_name);
-            return None
+        if !XML_ELEMENTS_LIST.contains(&tag_name.as_str()) {
+            return Err(format!("Unknown tag <{}>, expected one of {}", tag_name, XML_ELEMENTS_LIST.join(", ")))
         }
+        debug!(" +Parse tag {}", tag_name);
         // Get tag header info
         let mut identifier = None;
         let mut element_type = None;
+        let mut attributes = HashMap::new();
         for attribute in node.attributes() {
-            let mut key = String::new();
-            if attribute.is_err() {
-                panic!("{}", attribute.err().unwrap() );
-            }
-            _ = attribute.clone().unwrap().key.0.read_to_string(&mut key);
-            let value = attribute.clone().unwrap().value;
-            let value = std::str::from_utf8(value.as_ref()).unwrap();
+            let (key, value) = Self::read_attribute(attribute).map_err(|e| format!("Tag {tag_name} : {e}"))?;
             match key.as_str() {
                 "name" => {
-                    identifier = Some(String::from(value));
+                    identifier = Some(value);
                 },
                 "type" => {
-                    element_type = Some(String::from(value));
+                    element_type = Some(value);
+                }
+                _ => {
+                    attributes.insert(key, value);
                 }
-                _ => {}
             }
         }
-        Some(XMLSceneElement::new(tag_name, identifier, element_type))
+        let mut element = XMLSceneElement::new(tag_name, identifier, element_type);
+        for (key, value) in attributes {
+            element.add_attribute(key, value);
+        }
+        Ok(element)
     }
 
     fn parse_tag(node: BytesStart<'_>, reader: &mut Reader<BufReader<File>>) -> Option<Result<XMLSceneElement, String>> {
         let mut tag_name = String::new();
         node.name().as_ref().read_to_string(&mut tag_name);
-        let mut xml_tag = Self::get_tag(tag_name.clone(), &node);
+        let mut xml_tag = match Self::get_tag(tag_name.clone(), &node) {
+            Ok(xml_tag) => xml_tag,
+            Err(e) => return Some(Err(e))
+        };
 
         let mut buffer = Vec::new();
         loop {
             match reader.read_event_into(&mut buffer) {
-                Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+                Err(e) => return Some(Err(format!("Error at position {}: {:?}", reader.buffer_position(), e))),
                 Ok(Event::Eof) => return None,  // Break
                 Ok(Event::Start(e)) => {
                     // New tag group starting here
-                    panic!("Imbricated XML tags : not handled");
+                    return Some(Err(String::from("Imbricated XML tags : not handled")));
                 }
                 Ok(Event::Empty(e)) => {
-                    if xml_tag.is_none() {
-                        continue;       
-                    }
                     match e.name().as_ref() {
                         b"parameter" => {
-                            let param = Self::process_attributes(e);
-                            if param.is_ok() {
-                                xml_tag.as_mut().unwrap().add_parameter(param.unwrap());
+                            match Self::process_attributes(e) {
+                                Ok(param) => xml_tag.add_parameter(param),
+                                Err(e) => return Some(Err(format!("Tag {tag_name} : {e}")))
                             }
                         },
-                        _ => { panic!("unknown tag {:?}", e.name()) },
+                        _ => { return Some(Err(format!("unknown tag {:?}", e.name()))) },
                     }
                 }
                 Ok(Event::End(e)) => {
                     let mut end_tag_name = String::new();
                     node.name().as_ref().read_to_string(&mut end_tag_name);
                     if end_tag_name.as_bytes() == tag_name.as_bytes() {
-                        if xml_tag.is_some() {
-                            debug!(" -Closing tag {}", end_tag_name);
-                            return Some(Ok(xml_tag.unwrap()))
-                        } else {
-                            error!(" -Closing unknown tag {}", end_tag_name);
-                            return Some(Err(String::from(format!("Unknown tag {}", end_tag_name))))
-                        }
+                        debug!(" -Closing tag {}", end_tag_name);
+                        return Some(Ok(xml_tag))
                     } else {
-                        panic!("Closing wrong tag {}", end_tag_name);
+                        return Some(Err(format!("Closing wrong tag {}", end_tag_name)));
                     }
                 }
                 // Ignore other events
@@ -500,25 +849,26 @@ impl XMLScene {
         }
     }
 
-    fn parse(path: &str) -> XMLScene {
+    fn parse(path: &str) -> Result<XMLScene, String> {
         let mut xml_scene = Self::new();
 
-        let mut reader = Reader::from_file(path).unwrap();
+        let mut reader = Reader::from_file(path).map_err(|e| format!("Cannot open {path} : {e}"))?;
         let mut buffer = Vec::new();
         let mut current_xml_element: Option<XMLSceneElement> = None;
 
         loop {
             match reader.read_event_into(&mut buffer) {
-                Err(e) => panic!("Error at position {}: {:?}", reader.buffer_position(), e),
+                Err(e) => return Err(format!("Error at position {}: {:?}", reader.buffer_position(), e)),
                 Ok(Event::Eof) => break,
                 Ok(Event::Start(e)) => {
                     match e.name().as_ref() {
                         // Scene
                         b"scene" => { debug!("Tag : scene") },
                         _ => {
-                            let tag = Self::parse_tag(e, &mut reader);
-                            if tag.is_none() { break } else if tag.as_ref().unwrap().is_ok() {
-                                xml_scene.add_tag(tag.unwrap().unwrap());
+                            match Self::parse_tag(e, &mut reader) {
+                                None => break,
+                                Some(Ok(tag)) => xml_scene.add_tag(tag),
+                                Some(Err(e)) => return Err(e)
                             }
                         }
                     }
@@ -528,10 +878,10 @@ impl XMLScene {
                         b"scene" => {
                             // Scene parsing is finished
                         },
-                        _ => { panic!("Error while parsing the scene {:?}", e.name()) }
+                        _ => { return Err(format!("Error while parsing the scene {:?}", e.name())) }
                     }
                 }
-                Ok(Event::Empty(e)) => { panic!("Error while parsing the scene {:?}", e.name()) }
+                Ok(Event::Empty(e)) => { return Err(format!("Error while parsing the scene {:?}", e.name())) }
                 // Other Events are not important for us
                 Ok(_) => (),
             }
@@ -541,7 +891,7 @@ impl XMLScene {
         }
 
         // Return scene
-        xml_scene
+        Ok(xml_scene)
     }
 
     fn get_settings(&self) -> Option<RtRenderSettings> {
@@ -558,21 +908,91 @@ impl XMLScene {
             error!("Could not read render settings : {}", p_spp.err().unwrap());
             return None
         }
-        let mut p_bounces = render_scene.as_ref().unwrap().get_u8_parameter("max_bounces");
-        if p_spp.is_err() {
+        let mut p_bounces = render_scene.as_ref().unwrap().get_u16_parameter("max_bounces");
+        if p_bounces.is_err() {
             error!("Could not read number of bounces : {}", p_bounces.err().unwrap());
             return None;
         }
-        return Some(RtRenderSettings::new(p_spp.unwrap(), p_bounces.unwrap()))
+        let mut settings = RtRenderSettings::new(p_spp.unwrap(), p_bounces.unwrap());
+        // Optional settings
+        if let Ok(depth) = render_scene.as_ref().unwrap().get_u16_parameter("diffuse_depth") {
+            settings.diffuse_depth = depth;
+        }
+        if let Ok(depth) = render_scene.as_ref().unwrap().get_u16_parameter("specular_depth") {
+            settings.specular_depth = depth;
+        }
+        if let Ok(depth) = render_scene.as_ref().unwrap().get_u16_parameter("transmission_depth") {
+            settings.transmission_depth = depth;
+        }
+        if let Ok(depth) = render_scene.as_ref().unwrap().get_u16_parameter("rr_depth") {
+            settings.rr_depth = depth;
+        }
+        if let Ok(bucket_size) = render_scene.as_ref().unwrap().get_u16_parameter("bucket_size") {
+            settings.bucket_size = bucket_size;
+        }
+        if let Ok(threads) = render_scene.as_ref().unwrap().get_u16_parameter("threads") {
+            settings.threads = usize::from(threads);
+        }
+        if let Ok(width) = render_scene.as_ref().unwrap().get_u16_parameter("width") {
+            settings.width = width.max(1);
+        }
+        if let Ok(height) = render_scene.as_ref().unwrap().get_u16_parameter("height") {
+            settings.height = height.max(1);
+        }
+        if let Ok(name) = render_scene.as_ref().unwrap().get_string_parameter("direct_lighting") {
+            match RtDirectLighting::from_name(&name) {
+                Some(direct_lighting) => settings.direct_lighting = direct_lighting,
+                None => warn!("Unknown direct lighting strategy {name}, using {}", settings.direct_lighting.name())
+            }
+        }
+        if let Ok(seed) = render_scene.as_ref().unwrap().get_u32_parameter("seed") {
+            settings.seed = seed;
+        }
+        if let Ok(name) = render_scene.as_ref().unwrap().get_string_parameter("sampler") {
+            match RtSamplerType::from_name(&name) {
+                Some(sampler) => settings.sampler = sampler,
+                None => warn!("Unknown sampler {name}, using {}", settings.sampler.name())
+            }
+        }
+        if let Ok(threshold) = render_scene.as_ref().unwrap().get_float_parameter("adaptive_threshold") {
+            settings.adaptive_threshold = threshold.max(0.0);
+        }
+        if let Ok(max_spp) = render_scene.as_ref().unwrap().get_u16_parameter("max_spp") {
+            settings.max_spp = max_spp;
+        }
+        if let Ok(name) = render_scene.as_ref().unwrap().get_string_parameter("filter") {
+            match RtFilterType::from_name(&name) {
+                Some(filter) => {
+                    settings.filter = filter;
+                    settings.filter_width = filter.default_width();
+                }
+                None => warn!("Unknown filter {name}, using {}", settings.filter.name())
+            }
+        }
+        if let Ok(filter_width) = render_scene.as_ref().unwrap().get_float_parameter("filter_width") {
+            if filter_width > 0.0 {
+                settings.filter_width = filter_width;
+            } else {
+                warn!("Ignoring invalid filter width {filter_width}");
+            }
+        }
+        if let Ok(pixel_aspect) = render_scene.as_ref().unwrap().get_float_parameter("pixel_aspect") {
+            if pixel_aspect > 0.0 {
+                settings.pixel_aspect = pixel_aspect;
+            } else {
+                warn!("Ignoring invalid pixel aspect {pixel_aspect}");
+            }
+        }
+        Some(settings)
     }
 
-    fn get_camera(&self) -> Option<RtCamera> {
+    fn get_camera(&self, settings: &RtRenderSettings) -> Option<RtCamera> {
         let mut camera: Option<&XMLSceneElement> = None;
         for el in &self.0 {
             if el.name == "camera" { camera = Some(el); break; }
         }
         if camera.is_none() {
-            error!("No render settings found in the scene !");
+            error!("No camera found in the scene !");
             return None
         }
         let mut vfov = camera.as_ref().unwrap().get_float_parameter("v_fov");
@@ -582,29 +1002,49 @@ impl XMLScene {
             error!("Could not read camera !");
             return None
         }
+        // Optional lens parameters
+        let mut lens = RtLens::default();
+        if let Ok(aperture_size) = camera.as_ref().unwrap().get_float_parameter("aperture_size") {
+            lens.aperture_size = aperture_size.max(0.0);
+        }
+        if let Ok(focus_distance) = camera.as_ref().unwrap().get_float_parameter("focus_distance") {
+            lens.focus_distance = focus_distance.max(0.0);
+        }
+        if let Ok(aperture_blades) = camera.as_ref().unwrap().get_u16_parameter("aperture_blades") {
+            lens.aperture_blades = aperture_blades;
+        }
+        if let Ok(aperture_rotation) = camera.as_ref().unwrap().get_float_parameter("aperture_rotation") {
+            lens.aperture_rotation = aperture_rotation;
+        }
+        let mut projection = RtProjection::default();
+        if let Some(name) = camera.as_ref().unwrap().element_type.as_ref() {
+            match RtProjection::from_name(name) {
+                Some(p) => projection = p,
+                None => warn!("Unknown camera type {name}, using {}", projection.name())
+            }
+        }
         // Create and return camera
         return Some(RtCamera::new(
-            1.0, 400, 
+            settings.width, settings.height, settings.pixel_aspect,
             vfov.unwrap(), 
             lookfrom.unwrap(), 
             lookat.unwrap(), 
             RtVec3::new(0.0, 1.0, 0.0)
-        ))
+        ).with_projection(projection).with_lens(lens))
     }
 
-    fn get_shaders(&self) -> HashMap<String, Box<dyn RtShader>> {
+    fn get_shaders(&self) -> Result<HashMap<String, Box<dyn RtShader>>, String> {
         // shader_id -> shader
         let mut shaders_list: HashMap<String, Box<dyn RtShader>> = HashMap::new();
         for el in &self.0 {
             if el.name == "shader" {
                 if el.element_type.as_ref().is_none() || el.identifier.as_ref().is_none() {
-                    error!("Shader {} has no type or ID", el.name);
-                    continue;
+                    return Err(format!("Shader {} has no type or ID", el.name));
                 }
                 if el.element_type.as_ref().unwrap() == "lambert" {
                     let color = el.get_rgb_parameter("color");
                     if color.is_err() {
-                        error!("Shader {} : cannot read parameter", el.name); continue;
+                        return Err(format!("Shader {} : cannot read parameter", el.identifier.as_ref().unwrap()));
                     }
                     shaders_list.entry(el.identifier.as_ref().unwrap().clone()).or_insert(
                         Box::new(LambertShader { color: color.unwrap() }));
@@ -613,7 +1053,7 @@ impl XMLScene {
                     let color = el.get_rgb_parameter("color");
                     let fuzz = el.get_float_parameter("fuzz");
                     if color.is_err() || fuzz.is_err() {
-                        error!("Shader {} : cannot read parameter", el.name); continue;
+                        return Err(format!("Shader {} : cannot read parameter", el.identifier.as_ref().unwrap()));
                     }
                     shaders_list.entry(el.identifier.as_ref().unwrap().clone()).or_insert(
                         Box::new(Metal { color: color.unwrap(), fuzz: fuzz.unwrap() }));
@@ -621,86 +1061,444 @@ impl XMLScene {
                 else if el.element_type.as_ref().unwrap() == "glass" {
                     let ior = el.get_float_parameter("ior");
                     if ior.is_err() {
-                        error!("Shader {} : cannot read parameter", el.name); continue;
+                        return Err(format!("Shader {} : cannot read parameter", el.identifier.as_ref().unwrap()));
                     }
                     shaders_list.entry(el.identifier.as_ref().unwrap().clone()).or_insert(
                         Box::new(Glass { ior:ior.unwrap() }));
                 }
+                else if el.element_type.as_ref().unwrap() == "light" {
+                    let color = el.get_rgb_parameter("color");
+                    let intensity = el.get_float_parameter("intensity");
+                    if color.is_err() || intensity.is_err() {
+                        return Err(format!("Shader {} : cannot read parameter", el.identifier.as_ref().unwrap()));
+                    }
+                    shaders_list.entry(el.identifier.as_ref().unwrap().clone()).or_insert(
+                        Box::new(LightShader { color: color.unwrap(), intensity: intensity.unwrap() }));
+                }
+                else if el.element_type.as_ref().unwrap() == "emission" {
+                    let shader = Self::get_emission_shader(el)
+                        .map_err(|e| format!("Shader {} : cannot read parameter ({})", el.identifier.as_ref().unwrap(), e))?;
+                    shaders_list.entry(el.identifier.as_ref().unwrap().clone()).or_insert(Box::new(shader));
+                }
             }
         }
 
-        shaders_list
+        Ok(shaders_list)
     }
 
 
-    fn as_rt_scene(&self) -> Result<RtScene, &str> {
-        // Create the scene
-        let settings = self.get_settings();
-        let camera = self.get_camera();
-        if settings.is_none() || camera.is_none() {
-            return Err("Could not find camera or render settings !");
+    /// Emission shader, from the `color`, `intensity` and
+    /// `exposure` (in stops) parameters, all optional
+    fn get_emission_shader(el: &XMLSceneElement) -> Result<EmissionShader, String> {
+        let mut shader = EmissionShader { color: RtRGBA::WHITE, intensity: 1.0 };
+        if el.get_parameter("color").is_some() {
+            shader.color = el.get_rgb_parameter("color")?;
+        }
+        if el.get_parameter("intensity").is_some() {
+            shader.intensity = el.get_float_parameter("intensity")?;
+        }
+        if el.get_parameter("exposure").is_some() {
+            shader.intensity *= el.get_float_parameter("exposure")?.exp2();
+        }
+        Ok(shader)
+    }
+
+    fn get_sphere(el: &XMLSceneElement, object_params: ObjectParams) -> Result<Box<dyn RtObject>, String> {
+        Ok(Box::new(RtSphere {
+            object_params,
+            center: el.get_point_parameter("center")?,
+            radius: el.get_float_parameter("radius")?
+        }))
+    }
+
+    /// Polymesh parameters follow the usual layout :
+    /// - vlist  : vertex positions
+    /// - vidxs  : vertex indices of each polygon
+    /// - nsides : number of vertices per polygon (optional, triangles by default)
+    /// - nlist, nidxs : vertex normals and their indices (optional)
+    /// - uvlist, uvidxs : UV coordinates and their indices (optional)
+    /// 
+    /// When nidxs or uvidxs are omitted, vidxs are used instead.
+    fn get_polymesh(el: &XMLSceneElement, object_params: ObjectParams) -> Result<RtPolymesh, String> {
+        let vlist = el.get_point_array_parameter("vlist")?;
+        let vidxs = el.get_u32_array_parameter("vidxs")?;
+        let nsides = match el.get_parameter("nsides") {
+            Some(p) => p.get_u32_array()?,
+            None => vec![3; vidxs.len() / 3]
+        };
+        let mut mesh = RtPolymesh::new(object_params, vlist, &nsides, &vidxs)?;
+        if el.get_parameter("nlist").is_some() {
+            let nlist = el.get_vec_array_parameter("nlist")?;
+            let nidxs = el.get_u32_array_parameter("nidxs").unwrap_or_else(|_| vidxs.clone());
+            mesh.set_normals(nlist, &nsides, &nidxs)?;
+        }
+        if el.get_parameter("uvlist").is_some() {
+            let uvlist = el.get_float_array_parameter("uvlist")?;
+            if !uvlist.len().is_multiple_of(2) {
+                return Err(format!("uvlist has {} values, not a multiple of 2", uvlist.len()));
+            }
+            let uvlist = uvlist.chunks(2).map(|uv| [uv[0], uv[1]]).collect();
+            let uvidxs = el.get_u32_array_parameter("uvidxs").unwrap_or_else(|_| vidxs.clone());
+            mesh.set_uvs(uvlist, &nsides, &uvidxs)?;
+        }
+        info!("Polymesh {} : {} triangles", mesh.get_name(), mesh.num_triangles());
+        Ok(mesh)
+    }
+
+    /// OBJ file, given as a `file` attribute or string parameter.
+    /// Relative paths are relative to the scene file.
+    /// 
+    /// The linked shader overrides the OBJ materials.
+    fn get_obj_meshes(el: &XMLSceneElement, object_params: ObjectParams, 
+                      scene_dir: &Path, use_shader: bool) -> Result<Vec<RtPolymesh>, String> {
+        let file = match el.attributes.get("file") {
+            Some(file) => file.clone(),
+            None => el.get_string_parameter("file")?
+        };
+        let shader = if use_shader { Some(object_params.shader.as_ref()) } else { None };
+        let meshes = load_obj_meshes(&scene_dir.join(file), &object_params.name, shader)?;
+        info!("OBJ {} : {} meshes", object_params.name, meshes.len());
+        Ok(meshes)
+    }
+
+    /// Emission parameters shared by the lights :
+    /// - units     : "radiance" (default) or "power"
+    /// - two_sided : 1 to emit from both sides of quads, disks and meshes
+    /// - spread    : angular spread in (0, 1], 1 by default
+    fn get_emission(el: &XMLSceneElement) -> Result<RtEmission, String> {
+        let mut emission = RtEmission::default();
+        if el.get_parameter("units").is_some() {
+            let units = el.get_string_parameter("units")?;
+            emission.units = RtLightUnits::from_name(&units).ok_or(format!("Unknown light units {units}"))?;
+        }
+        if el.get_parameter("two_sided").is_some() {
+            emission.two_sided = el.get_u8_parameter("two_sided")? != 0;
+        }
+        if el.get_parameter("spread").is_some() {
+            emission.spread = el.get_float_parameter("spread")?;
+            if !(emission.spread > 0.0 && emission.spread <= 1.0) {
+                return Err(format!("Spread {} out of (0, 1]", emission.spread));
+            }
+        }
+        Ok(emission)
+    }
+
+    /// Sampling strategy of the light, "solid_angle" (default) or "uniform"
+    fn get_light_sampling(el: &XMLSceneElement) -> Result<RtLightSampling, String> {
+        match el.get_parameter("sampling") {
+            Some(p) => {
+                let sampling = p.get_string()?;
+                RtLightSampling::from_name(&sampling).ok_or(format!("Unknown light sampling {sampling}"))
+            }
+            None => Ok(RtLightSampling::default())
+        }
+    }
+
+    /// Light shader : the linked `shader`, or a light shader built from
+    /// the `color`, `intensity` and `exposure` (in stops) parameters
+    fn get_light_shader(el: &XMLSceneElement, shaders: &HashMap<String, Box<dyn RtShader>>) -> Result<Box<dyn RtShader>, String> {
+        if el.get_parameter("shader").is_some() {
+            let shader_id = el.get_linked_parameter_id("shader")?;
+            return match shaders.get(shader_id.as_str()) {
+                Some(shader) => Ok(shader.clone_dyn()),
+                None => Err(format!("Unknown shader {shader_id}"))
+            }
+        }
+        let mut shader = DEFAULT_LIGHT.clone();
+        if el.get_parameter("color").is_some() {
+            shader.color = el.get_rgb_parameter("color")?;
+        }
+        if el.get_parameter("intensity").is_some() {
+            shader.intensity = el.get_float_parameter("intensity")?;
+        }
+        if el.get_parameter("exposure").is_some() {
+            shader.intensity *= el.get_float_parameter("exposure")?.exp2();
+        }
+        Ok(Box::new(shader))
+    }
+
+    /// File of a light, given as a `file` attribute or string parameter.
+    /// Relative paths are relative to the scene file.
+    fn get_light_file(el: &XMLSceneElement, scene_dir: &Path) -> Result<Option<PathBuf>, String> {
+        let file = match el.attributes.get("file") {
+            Some(file) => file.clone(),
+            None => match el.get_parameter("file") {
+                Some(p) => p.get_string()?,
+                None => return Ok(None)
+            }
+        };
+        Ok(Some(scene_dir.join(file)))
+    }
+
+    /// Environment map of a skydome (optional)
+    fn get_skydome_map(el: &XMLSceneElement, scene_dir: &Path) -> Result<Option<RtImage>, String> {
+        let Some(file) = Self::get_light_file(el, scene_dir)? else {
+            return Ok(None)
+        };
+        let map = RtImage::open(&file)?;
+        info!("Skydome map : {}x{}", map.width, map.height);
+        Ok(Some(map))
+    }
+
+    /// Normalized `direction` parameter, pointing down by default
+    fn get_light_direction(el: &XMLSceneElement) -> Result<RtVec3, String> {
+        if el.get_parameter("direction").is_none() {
+            return Ok(RtVec3::new(0.0, -1.0, 0.0))
+        }
+        let direction = el.get_vec_parameter("direction")?;
+        if direction.length_squared() <= 0.0 {
+            return Err(String::from("Null light direction"));
+        }
+        Ok(direction.normalize())
+    }
+
+    /// Lights and their parameters :
+    /// - point  : center, radius (optional, 0 for a point)
+    /// - sphere : center, radius
+    /// - quad   : corner, edge_u, edge_v (emits on the side of edge_u x edge_v)
+    /// - disk   : center, normal, radius
+    /// - mesh   : same parameters as the polymesh shapes
+    /// - skydome : file (optional, .hdr or .exr lat-long map), rotation (degrees around y)
+    /// - physical_sky : see get_physical_sky
+    /// - spot   : position, direction (down by default), cone_angle (degrees, 45 by default),
+    ///   penumbra_angle (degrees, 0 by default)
+    /// - distant : direction of the light rays (down by default), angle (angular diameter in degrees,
+    ///   0 by default for parallel rays)
+    /// - ies    : file (.ies), position, direction of the nadir of the profile (down by default)
+    fn get_lights(el: &XMLSceneElement, object_params: ObjectParams, scene_dir: &Path) -> Result<Vec<Box<dyn RtLight>>, String> {
+        let emission = Self::get_emission(el)?;
+        let sampling = Self::get_light_sampling(el)?;
+        let light: Box<dyn RtLight> = match object_params.object_type.as_str() {
+            "point" | "sphere" => {
+                let radius = match el.get_parameter("radius") {
+                    Some(p) => p.get_f32()?,
+                    None if object_params.object_type == "point" => 0.0,
+                    None => return Err(String::from("Sphere light without radius"))
+                };
+                Box::new(RtPointLight {
+                    center: el.get_point_parameter("center")?,
+                    radius: radius.max(0.0),
+                    object_params, emission, sampling
+                })
+            }
+            "quad" => Box::new(RtQuadLight {
+                corner: el.get_point_parameter("corner")?,
+                edge_u: el.get_vec_parameter("edge_u")?,
+                edge_v: el.get_vec_parameter("edge_v")?,
+                object_params, emission, sampling
+            }),
+            "disk" => Box::new(RtDiskLight {
+                center: el.get_point_parameter("center")?,
+                normal: el.get_vec_parameter("normal")?.normalize(),
+                radius: el.get_float_parameter("radius")?,
+                object_params, emission
+            }),
+            "mesh" => Box::new(RtMeshLight::new(Self::get_polymesh(el, object_params)?, emission)),
+            "skydome" => {
+                let rotation = match el.get_parameter("rotation") {
+                    Some(p) => p.get_f32()?,
+                    None => 0.0
+                };
+                Box::new(RtSkydomeLight::new(object_params, Self::get_skydome_map(el, scene_dir)?, rotation))
+            }
+            "physical_sky" => return Self::get_physical_sky(el, object_params),
+            "spot" => {
+                let (mut cone_angle, mut penumbra_angle) = (45.0, 0.0);
+                if el.get_parameter("cone_angle").is_some() {
+                    cone_angle = el.get_float_parameter("cone_angle")?;
+                }
+                if el.get_parameter("penumbra_angle").is_some() {
+                    penumbra_angle = el.get_float_parameter("penumbra_angle")?;
+                }
+                Box::new(RtSpotLight {
+                    position: el.get_point_parameter("position")?,
+                    direction: Self::get_light_direction(el)?,
+                    cone_angle, penumbra_angle,
+                    object_params, emission
+                })
+            }
+            "distant" => {
+                let mut angle = 0.0;
+                if el.get_parameter("angle").is_some() {
+                    angle = el.get_float_parameter("angle")?;
+                }
+                Box::new(RtDistantLight {
+                    direction: -Self::get_light_direction(el)?,
+                    angle: angle.max(0.0),
+                    irradiance: RtRGBA::WHITE,
+                    object_params
+                })
+            }
+            "ies" => {
+                let file = Self::get_light_file(el, scene_dir)?.ok_or("No IES file")?;
+                let profile = RtIesProfile::open(&file)?;
+                info!("IES profile : {} x {} angles, {} cd", 
+                    profile.vertical_angles.len(), profile.horizontal_angles.len(), profile.max_candela());
+                Box::new(RtIesLight::new(object_params, el.get_point_parameter("position")?,
+                    Self::get_light_direction(el)?, profile, emission))
+            }
+            other => return Err(format!("Light type {other} not implemented"))
+        };
+        Ok(vec![light])
+    }
+
+    /// Shape with a `light` parameter set to 1, registered as a light to
+    /// be sampled for direct lighting : spheres become sphere lights, 
+    /// polymeshes and OBJ meshes become mesh lights. Their shader gives 
+    /// their emission, e.g. an emission shader, and the emission parameters 
+    /// of the lights (units, two_sided, spread, sampling) apply. 
+    /// Like the other lights, they don't reflect light.
+    fn get_shape_lights(el: &XMLSceneElement, object_params: ObjectParams, 
+                        scene_dir: &Path, use_shader: bool) -> Result<Vec<Box<dyn RtLight>>, String> {
+        let emission = Self::get_emission(el)?;
+        let lights: Vec<Box<dyn RtLight>> = match object_params.object_type.as_str() {
+            "sphere" => vec![Box::new(RtPointLight {
+                center: el.get_point_parameter("center")?,
+                radius: el.get_float_parameter("radius")?.max(0.0),
+                sampling: Self::get_light_sampling(el)?,
+                object_params, emission
+            })],
+            "polymesh" => vec![Box::new(RtMeshLight::new(Self::get_polymesh(el, object_params)?, emission))],
+            "obj" => Self::get_obj_meshes(el, object_params, scene_dir, use_shader)?
+                .into_iter()
+                .map(|mesh| Box::new(RtMeshLight::new(mesh, emission)) as Box<dyn RtLight>)
+                .collect(),
+            other => return Err(format!("Geometry type {other} can't be a light"))
+        };
+        Ok(lights)
+    }
+
+    /// Physical sky and its sun, parameters (all optional) :
+    /// - sun_elevation : degrees over the horizon (45 by default)
+    /// - sun_azimuth : degrees from -z towards +x (0 by default)
+    /// - turbidity : haze of the atmosphere, from 1.7 to 10 (3 by default)
+    /// - sun : 0 for the sky only (1 by default)
+    /// - sun_angle : angular diameter of the sun in degrees (0.53 by default)
+    fn get_physical_sky(el: &XMLSceneElement, object_params: ObjectParams) -> Result<Vec<Box<dyn RtLight>>, String> {
+        let (mut elevation, mut azimuth, mut turbidity) = (45.0, 0.0, 3.0);
+        if el.get_parameter("sun_elevation").is_some() {
+            elevation = el.get_float_parameter("sun_elevation")?;
         }
-        let mut scene = RtScene::new(settings.unwrap(), camera.unwrap());
+        if el.get_parameter("sun_azimuth").is_some() {
+            azimuth = el.get_float_parameter("sun_azimuth")?;
+        }
+        if el.get_parameter("turbidity").is_some() {
+            turbidity = el.get_float_parameter("turbidity")?;
+        }
+        let mut has_sun = true;
+        if el.get_parameter("sun").is_some() {
+            has_sun = el.get_u8_parameter("sun")? != 0;
+        }
+        let mut sun_angle = 0.53;
+        if el.get_parameter("sun_angle").is_some() {
+            sun_angle = el.get_float_parameter("sun_angle")?;
+        }
+
+        let name = object_params.name.clone();
+        let sky = RtPhysicalSkyLight::new(object_params, elevation, azimuth, turbidity);
+        let mut lights: Vec<Box<dyn RtLight>> = Vec::new();
+        if has_sun {
+            lights.push(Box::new(sky.get_sun_light(format!("{name}_sun"), sun_angle.max(0.0))));
+        }
+        lights.push(Box::new(sky));
+        Ok(lights)
+    }
+
+    fn as_rt_scene(&self, scene_dir: &Path) -> Result<RtScene, String> {
+        // Create the scene
+        let settings = self.get_settings().ok_or("Could not find render settings !")?;
+        let camera = self.get_camera(&settings).ok_or("Could not find camera !")?;
+        let mut scene = RtScene::new(settings, camera);
 
         // Get shaders
-        let shaders = self.get_shaders();
+        let shaders = self.get_shaders()?;
         info!("Found {} shaders", shaders.len());
 
         // Add geometry in the scene
         for el in &self.0 {
             if el.name == "shape" {
                 if el.element_type.is_none() || el.identifier.is_none() {
-                    error!("Could not get shape type or ID !"); continue;
-                }
-                if el.element_type.as_ref().unwrap() != "sphere" {
-                    error!("Geometry type {} not implemented !", el.element_type.as_ref().unwrap());
-                    continue;
+                    return Err(String::from("Could not get shape type or ID !"));
                 }
-                // Shader
-                let shader_id = el.get_linked_parameter_id("shader");
-                let shape_shader = shaders.get(shader_id.as_ref().unwrap().as_str());
+                let is_obj = el.element_type.as_ref().unwrap() == "obj";
+                // Shader (optional for OBJ files that come with their materials)
+                let shader_id = el.get_linked_parameter_id("shader").unwrap_or_default();
+                let shape_shader = shaders.get(shader_id.as_str());
                 let mut shader: Box<dyn RtShader> = Box::new(DEFAULT_SHADER.clone());
-                if shape_shader.is_none() {
+                if let Some(shape_shader) = shape_shader {
+                    shader = shape_shader.clone_dyn();
+                } else if !is_obj {
                     warn!("No shader linked to shape {}", el.identifier.as_ref().unwrap());
-                } else {
-                    shader = shaders[shader_id.as_ref().unwrap().as_str()].clone_dyn();
                 }
-                debug!("Shape {} -> shader {}", el.identifier.as_ref().unwrap(), shader_id.unwrap());
+                debug!("Shape {} -> shader {}", el.identifier.as_ref().unwrap(), shader_id);
+                let object_params = ObjectParams::new(
+                    el.identifier.as_ref().unwrap().clone(), 
+                    el.element_type.as_ref().unwrap().clone(), 
+                    shader);
+                // Shapes registered as lights
+                if el.get_parameter("light").is_some_and(|p| p.get_u8().is_ok_and(|light| light != 0)) {
+                    Self::get_shape_lights(el, object_params, scene_dir, shape_shader.is_some())
+                        .map_err(|e| format!("Could not read shape {} : {}", el.identifier.as_ref().unwrap(), e))?
+                        .into_iter().for_each(|light| scene.add_light(light));
+                    continue;
+                }
+                // Imported shapes
+                if is_obj {
+                    Self::get_obj_meshes(el, object_params, scene_dir, shape_shader.is_some())
+                        .map_err(|e| format!("Could not read shape {} : {}", el.identifier.as_ref().unwrap(), e))?
+                        .into_iter().for_each(|mesh| scene.add_shape(Box::new(mesh)));
+                    continue;
+                }
                 // New shape
-                scene.add_shape(
-                    Box::new(RtSphere {
-                        object_params: ObjectParams::new(
-                            el.identifier.as_ref().unwrap().clone(), 
-                            el.element_type.as_ref().unwrap().clone(), 
-                            shader),
-                        center: el.get_point_parameter("center").unwrap(),
-                        radius: el.get_float_parameter("radius").unwrap()
-                    })
-                );
+                let shape = match el.element_type.as_ref().unwrap().as_str() {
+                    "sphere" => Self::get_sphere(el, object_params),
+                    "polymesh" => Self::get_polymesh(el, object_params)
+                        .map(|mesh| Box::new(mesh) as Box<dyn RtObject>),
+                    other => Err(format!("Geometry type {} not implemented !", other))
+                };
+                let shape = shape.map_err(|e| format!("Could not read shape {} : {}", el.identifier.as_ref().unwrap(), e))?;
+                scene.add_shape(shape);
             }
         }
 
         info!("Found {} shapes", scene.shapes.len());
 
+        // Add lights in the scene
+        for el in &self.0 {
+            if el.name == "light" {
+                let (Some(light_type), Some(light_id)) = (el.element_type.as_ref(), el.identifier.as_ref()) else {
+                    return Err(String::from("Could not get light type or ID !"));
+                };
+                let shader = Self::get_light_shader(el, &shaders)
+                    .map_err(|e| format!("Could not read light {} : {}", light_id, e))?;
+                let object_params = ObjectParams::new(light_id.clone(), light_type.clone(), shader);
+                Self::get_lights(el, object_params, scene_dir)
+                    .map_err(|e| format!("Could not read light {} : {}", light_id, e))?
+                    .into_iter().for_each(|light| scene.add_light(light));
+            }
+        }
+
+        info!("Found {} lights", scene.list_lights().len());
+
         // Return the scene
         Ok(scene)
     }
 }
 
 
-pub fn open_xml_scene(path: &str) -> Option<RtScene> {
+/// Load a XML scene. Any element of the scene that cannot be
+/// read (shape, shader, light, imported file) makes it fail.
+pub fn open_xml_scene(path: &str) -> Result<RtScene, String> {
     info!("Opening XML render scene : {path}");
     let now = std::time::Instant::now();
 
-    let xml_scene = XMLScene::parse(path);
+    let xml_scene = XMLScene::parse(path).map_err(|e| format!("Could not parse scene {path} : {e}"))?;
     debug!("Scene : \n{}", xml_scene);
     
-    let scene = xml_scene.as_rt_scene();
-    if scene.is_err() {
-        error!("Could not parse scene {path} : {:?}", scene.err());
-        return None;
-    }
+    let scene_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    let mut scene = xml_scene.as_rt_scene(scene_dir).map_err(|e| format!("Could not parse scene {path} : {e}"))?;
+    scene.finalize();
 
     info!("> Scene took {} sec to open", now.elapsed().as_secs_f64());
-    Some(scene.unwrap())
+    Ok(scene)
 }
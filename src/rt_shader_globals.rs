@@ -17,6 +17,8 @@ pub struct RtShaderGlobals {
     pub x: u16,
     /// Y raster-space coordinate of this ray tree
     pub y: u16,
+    /// Index of the pixel sample of this ray tree
+    pub si: u32,
 
     /// Shading point in world-space
     pub P: RtPoint3,
@@ -33,7 +35,7 @@ pub struct RtShaderGlobals {
 
     // Bounces
     /// recursion level for the ray that created this hit
-    pub bounces: u8,
+    pub bounces: u16,
 
     // object being shaded
     // pub object: RtObjectNode
@@ -41,13 +43,15 @@ pub struct RtShaderGlobals {
     // Normals
     /// shading normal
     pub N: RtVec3,
-    // TODO : Nf, Ng, Ngf, Ns ...
+    /// geometric normal
+    pub Ng: RtVec3,
+    // TODO : Nf, Ngf, Ns ...
 
     // UV coordinates
-    // U surface parameter
-    // pub u: RtVec3,
-    // V surface parameter
-    // pub v: RtVec3,
+    /// U surface parameter
+    pub u: f32,
+    /// V surface parameter
+    pub v: f32,
     
     // Shading context
     // pub shading_context: u8,
@@ -59,12 +63,16 @@ impl RtShaderGlobals {
         Self {
             x, 
             y, 
+            si: 0,
             P: RtPoint3::default(), 
             Po: String::default(),
             ray_origin: RtPoint3::default(), 
             ray_dir: RtVec3::default(), 
             bounces: 0, 
-            N: RtVec3::default()
+            N: RtVec3::default(),
+            Ng: RtVec3::default(),
+            u: 0.0,
+            v: 0.0
         }
     }
     
@@ -75,21 +83,25 @@ impl RtShaderGlobals {
                Po: String, 
                ray_origin: RtPoint3, 
                ray_dir: RtVec3, 
-               bounces: u8, 
+               bounces: u16, 
                N: RtVec3) -> Self {
-        Self { x, y, P, Po, ray_origin, ray_dir, bounces, N }
+        Self { x, y, si: 0, P, Po, ray_origin, ray_dir, bounces, N, Ng: N, u: 0.0, v: 0.0 }
     }
 
     pub fn from_intersection(ray: &RtRay, Po: String, intersection: RtPoint3) -> Self {
         Self {
             x: ray.x,
             y: ray.y,
+            si: ray.si,
             P: intersection,
             Po,
             ray_origin: ray.origin,
             ray_dir: ray.dir,
             bounces: ray.bounces, 
-            N: RtVec3::default()
+            N: RtVec3::default(),
+            Ng: RtVec3::default(),
+            u: 0.0,
+            v: 0.0
         }
     }
 
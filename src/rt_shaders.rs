@@ -6,10 +6,12 @@
 /// =====================================================
 
 pub mod rt_shader_base;
+pub mod rt_bsdf;
 pub mod staticColor;
 pub mod stateVector;
 pub mod lambert;
 pub mod lightShader;
+pub mod emission;
 pub mod metal;
 pub mod glass;
 
@@ -19,6 +21,7 @@ use lambert::LambertShader;
 use metal::Metal;
 use glass::Glass;
 use lightShader::LightShader;
+use emission::EmissionShader;
 
 use crate::RtRGBA;
 
@@ -28,6 +31,7 @@ pub enum RtSurfaceShadersTypes {
     Lambert(LambertShader),
     Glass(Glass),
     Metal(Metal),
+    Emission(EmissionShader),
 }
 
 pub enum RtLightShadersTypes {
@@ -36,3 +40,5 @@ pub enum RtLightShadersTypes {
 
 pub const DEFAULT_SHADER: StaticColorShader = StaticColorShader { color: RtRGBA::ERRCOLOR };
 pub const DEFAULT_LIGHT: LightShader = LightShader { color: RtRGBA::WHITE, intensity: 1.0 };
+/// Shader of the sky lighting the scenes without infinite lights
+pub const DEFAULT_SKY: LightShader = LightShader { color: RtRGBA::WHITE, intensity: 8.0 };
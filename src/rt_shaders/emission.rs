@@ -0,0 +1,41 @@
+/// =====================================================
+///                    Raito Render
+/// 
+/// Module description :
+///   Emission shader
+/// =====================================================
+
+use crate::rt_shaders::rt_shader_base::*;
+use crate::rt_shader_globals::*;
+use crate::rt_types::*;
+use crate::rt_shaders::rt_bsdf::*;
+
+
+// ========================================
+//  Shader structure
+// ========================================
+
+/// Glowing surface, emitting `color * intensity` without reflecting light
+/// 
+/// Any shape can use it. Shapes registered as lights are also sampled 
+/// for direct lighting, the others only light the scene when rays hit them.
+#[derive(Clone, Debug)]
+pub struct EmissionShader {
+    pub color: RtRGBA,
+    pub intensity: f32
+}
+
+
+// ========================================
+//  Shader implementation
+// ========================================
+
+impl RtShader for EmissionShader {
+    fn clone_dyn(&self) -> Box<dyn RtShader> {
+        Box::new(self.clone())
+    }
+    
+    fn evaluate(&self, _sg: &RtShaderGlobals) -> RtClosure {
+        RtClosure::emission(self.color * self.intensity)
+    }
+}
@@ -2,19 +2,13 @@
 ///                    Raito Render
 /// 
 /// Module description :
-///   Metal shader
+///   Glass shader
 /// =====================================================
 
 use crate::rt_shaders::rt_shader_base::*;
+use crate::rt_shaders::rt_bsdf::*;
 use crate::rt_shader_globals::*;
 use crate::rt_types::*;
-use crate::rt_ray::*;
-use crate::rt_scene::*;
-use crate::rt_render::*;
-use crate::random_float;
-
-const MAX_RAY_LENGTH: f32 = 10000.0;
-const NB_SAMPLES: u8 = 10;
 
 // ========================================
 //  Shader structure
@@ -30,62 +24,76 @@ pub struct Glass {
 //  Utility functions
 // ========================================
 
-fn random_unit_vector() -> RtVec3 {
-    // Take a random point
-    let mut vec = RtVec3::random_range(-1.0, 1.0);
-    while vec.length_squared() >= 1.0 {
-        vec = RtVec3::random_range(-1.0, 1.0);
-    }
-    vec
-}
-
-fn reflectance(cosine: f32, ior: f32) -> f32 {
+fn reflectance(cosine: f32, eta: f32) -> f32 {
     // Use Schlick's approximation for reflectance.
-    let r0 = (1.0 - ior) / (1.0 + ior);
-    r0 + (1.0 - r0*r0) * (1.0 - cosine).powf(5.0)
+    let r0 = (1.0 - eta) / (1.0 + eta);
+    let r0 = r0 * r0;
+    r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0)
 }
 
+
 // ========================================
-//  Shader implementation
+//  BSDF
 // ========================================
 
-impl RtShader for Glass {
-    fn clone_dyn(&self) -> Box<dyn RtShader> {
-        Box::new(self.clone())
-    }
+/// Smooth dielectric, reflects or refracts the ray
+pub struct DielectricBsdf {
+    /// Outward normal of the surface
+    pub N: RtVec3,
+    pub ior: f32,
+}
 
-    fn evaluate(&self, scene: &RtScene, sg: &RtShaderGlobals) -> RtRGBA {
-        // Result
-        let mut out_color = RtRGBA::BLACK;
+impl RtBsdf for DielectricBsdf {
+    fn eval(&self, _wo: &RtVec3, _wi: &RtVec3) -> RtRGBA {
+        RtRGBA::BLACK
+    }
 
-        let front_face = RtVec3::dot(sg.ray_dir, sg.N) < 0.0;
-        
-        let mut ray = RtMakeRay(sg, RtRayType::RT_RAY_UNKNOWN, RtVec3::default(), MAX_RAY_LENGTH);
-        // Reflect the ray
+    fn sample(&self, wo: &RtVec3, u1: f32, _u2: f32) -> Option<RtBsdfSample> {
+        let front_face = RtVec3::dot(*wo, self.N) > 0.0;
         let eta = if front_face { 1.0 / self.ior } else { self.ior };
-        let Nf = if front_face { sg.N } else { -sg.N };
+        let Nf = if front_face { self.N } else { -self.N };
 
-        let unit_direction = sg.ray_dir;
-        let cos_theta: f32 = RtVec3::dot(-unit_direction, Nf).min(1.0);
+        let cos_theta: f32 = RtVec3::dot(*wo, Nf).min(1.0);
         let sin_theta: f32 = (1.0 - cos_theta * cos_theta).sqrt();
-
         let tir = (eta * sin_theta) > 1.0;
 
-        if tir || reflectance(cos_theta, eta) > random_float() {
-            RtReflectRay(&mut ray, &unit_direction, &Nf, &sg);
+        // Pick reflection or refraction with the Fresnel reflectance
+        let (wi, lobe) = if tir || reflectance(cos_theta, eta) > u1 {
+            (reflect(wo, &Nf), RtLobe::Specular)
         } else {
-            RtRefractRay(&mut ray, &unit_direction, &Nf, eta, &sg);
-        }
+            let r_out_perp: RtVec3 = eta * (cos_theta * Nf - *wo);
+            let r_out_parallel: RtVec3 = - (1.0 - r_out_perp.length_squared()).abs().sqrt() * Nf;
+            ((r_out_perp + r_out_parallel).normalize(), RtLobe::Transmission)
+        };
+        Some(RtBsdfSample {
+            wi,
+            weight: RtRGBA::WHITE,
+            pdf: 1.0,
+            is_delta: true,
+            lobe,
+        })
+    }
 
-        // Avoid self intersections
-        ray.origin = ray.origin + RT_EPSILON * ray.dir;
+    fn pdf(&self, _wo: &RtVec3, _wi: &RtVec3) -> f32 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
 
-        // Trace rays
-        let hit = RtTraceRay(scene, &ray);
-        if hit.is_some() {
-            out_color += hit.unwrap().colorOutput;
-        }
 
-        out_color
+// ========================================
+//  Shader implementation
+// ========================================
+
+impl RtShader for Glass {
+    fn clone_dyn(&self) -> Box<dyn RtShader> {
+        Box::new(self.clone())
+    }
+
+    fn evaluate(&self, sg: &RtShaderGlobals) -> RtClosure {
+        RtClosure::bsdf(Box::new(DielectricBsdf { N: sg.N, ior: self.ior }))
     }
 }
@@ -6,14 +6,10 @@
 /// =====================================================
 
 use crate::rt_shaders::rt_shader_base::*;
+use crate::rt_shaders::rt_bsdf::*;
 use crate::rt_shader_globals::*;
 use crate::rt_types::*;
-use crate::rt_ray::*;
-use crate::rt_scene::*;
-use crate::rt_render::*;
-
-const MAX_RAY_LENGTH: f32 = 10000.0;
-const NB_SAMPLES: u8 = 1;
+use crate::rt_sampler::*;
 
 // ========================================
 //  Shader structure
@@ -26,20 +22,47 @@ pub struct LambertShader {
 
 
 // ========================================
-//  Utility functions
+//  BSDF
 // ========================================
 
-fn sample_in_hemisphere(n: RtVec3) -> RtVec3 {
-    // Take a random point
-    let mut vec = RtVec3::random_range(-1.0, 1.0);
-    while vec.length_squared() >= 1.0 {
-        vec = RtVec3::random_range(-1.0, 1.0);
+/// Diffuse reflection, sampled with a cosine distribution
+pub struct LambertBsdf {
+    /// Normal on the side of the viewer
+    pub N: RtVec3,
+    pub color: RtRGBA,
+}
+
+impl RtBsdf for LambertBsdf {
+    fn eval(&self, _wo: &RtVec3, wi: &RtVec3) -> RtRGBA {
+        let cos_theta = RtVec3::dot(*wi, self.N);
+        if cos_theta <= 0.0 {
+            return RtRGBA::BLACK
+        }
+        self.color * (RT_ONEOVERPI * cos_theta)
+    }
+
+    fn sample(&self, _wo: &RtVec3, u1: f32, u2: f32) -> Option<RtBsdfSample> {
+        let wi = sample_cosine_hemisphere(&self.N, u1, u2);
+        let cos_theta = RtVec3::dot(wi, self.N);
+        if cos_theta <= 0.0 {
+            return None
+        }
+        // albedo / pi * cos / pdf = albedo
+        Some(RtBsdfSample {
+            wi,
+            weight: self.color,
+            pdf: pdf_cosine_hemisphere(cos_theta),
+            is_delta: false,
+            lobe: RtLobe::Diffuse,
+        })
+    }
+
+    fn pdf(&self, _wo: &RtVec3, wi: &RtVec3) -> f32 {
+        pdf_cosine_hemisphere(RtVec3::dot(*wi, self.N))
     }
-    vec = (n + vec).normalize();
-    // Make sure the sampled ray is in the correct hemisphere
-    if RtVec3::dot(vec, n) > 0.0 { vec } else { -vec }
 }
 
+
 // ========================================
 //  Shader implementation
 // ========================================
@@ -49,34 +72,10 @@ impl RtShader for LambertShader {
         Box::new(self.clone())
     }
     
-    fn evaluate(&self, scene: &RtScene, sg: &RtShaderGlobals) -> RtRGBA {
-        // Result
-        let mut out_color = RtRGBA::BLACK;
-        
-        // Diffuse contribution
-        let mut diffuse = RtRGBA::BLACK;
-        for _ in 0..NB_SAMPLES {
-            // Create ray
-            let mut ray = RtMakeRay(sg, RtRayType::RT_RAY_UNKNOWN, RtVec3::default(), MAX_RAY_LENGTH);
-            ray.origin = ray.origin + RT_EPSILON * sg.N;  // Avoid self intersections
-            ray.dir = sample_in_hemisphere(sg.N);
-    
-            // Trace rays
-            // let hit = RtTraceToLights(scene, &ray);
-            let hit = RtTraceRay(scene, &ray);
-            if hit.is_some() {
-                let hit = hit.unwrap();
-                // return hit.colorOutput / (hit.P - sg.P).length();
-                // return hit.colorOutput * (hit.P - sg.P).length().powf(2.0)
-                // diffuse += albedo * sg->Li * sg->we * AI_ONEOVERPI * max(0, LdotN);
-                // Ray distance : (hit.P - sg.P).length_squared()
-                diffuse += self.color * hit.colorOutput * RT_ONEOVERPI * RtVec3::dot(ray.dir, sg.N);
-            } else {
-                diffuse += self.color * RtRGBA::ERRCOLOR * RT_ONEOVERPI * RtVec3::dot(ray.dir, sg.N);
-            }
-        }
-        out_color += diffuse / (NB_SAMPLES as f32);
-
-        out_color
+    fn evaluate(&self, sg: &RtShaderGlobals) -> RtClosure {
+        RtClosure::bsdf(Box::new(LambertBsdf {
+            N: face_forward(&sg.N, &sg.ray_dir),
+            color: self.color,
+        }))
     }
 }
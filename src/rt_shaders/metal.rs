@@ -6,14 +6,10 @@
 /// =====================================================
 
 use crate::rt_shaders::rt_shader_base::*;
+use crate::rt_shaders::rt_bsdf::*;
 use crate::rt_shader_globals::*;
 use crate::rt_types::*;
-use crate::rt_ray::*;
-use crate::rt_scene::*;
-use crate::rt_render::*;
-
-const MAX_RAY_LENGTH: f32 = 10000.0;
-const NB_SAMPLES: u8 = 10;
+use crate::rt_sampler::*;
 
 // ========================================
 //  Shader structure
@@ -30,15 +26,90 @@ pub struct Metal {
 //  Utility functions
 // ========================================
 
-fn random_unit_vector() -> RtVec3 {
-    // Take a random point
-    let mut vec = RtVec3::random_range(-1.0, 1.0);
-    while vec.length_squared() >= 1.0 {
-        vec = RtVec3::random_range(-1.0, 1.0);
+impl Metal {
+    /// Exponent of the glossy Phong lobe, inverse of fuzz = sqrt(2 / (exponent + 2))
+    fn get_exponent(&self) -> f32 {
+        let fuzz = self.fuzz.min(1.0);
+        (2.0 / (fuzz * fuzz) - 2.0).max(0.0)
     }
-    vec
 }
 
+
+// ========================================
+//  BSDFs
+// ========================================
+
+/// Perfect mirror, lights are only found by the reflected ray
+pub struct MirrorBsdf {
+    /// Normal on the side of the viewer
+    pub N: RtVec3,
+    pub color: RtRGBA,
+}
+
+impl RtBsdf for MirrorBsdf {
+    fn eval(&self, _wo: &RtVec3, _wi: &RtVec3) -> RtRGBA {
+        RtRGBA::BLACK
+    }
+
+    fn sample(&self, wo: &RtVec3, _u1: f32, _u2: f32) -> Option<RtBsdfSample> {
+        Some(RtBsdfSample {
+            wi: reflect(wo, &self.N),
+            weight: self.color,
+            pdf: 1.0,
+            is_delta: true,
+            lobe: RtLobe::Specular,
+        })
+    }
+
+    fn pdf(&self, _wo: &RtVec3, _wi: &RtVec3) -> f32 {
+        0.0
+    }
+
+    fn is_delta(&self) -> bool {
+        true
+    }
+}
+
+/// Glossy reflection, the BSDF times the cosine is color * lobe
+/// around the mirror direction
+pub struct PhongBsdf {
+    /// Normal on the side of the viewer
+    pub N: RtVec3,
+    pub color: RtRGBA,
+    pub exponent: f32,
+}
+
+impl RtBsdf for PhongBsdf {
+    fn eval(&self, wo: &RtVec3, wi: &RtVec3) -> RtRGBA {
+        if RtVec3::dot(*wi, self.N) <= 0.0 {
+            return RtRGBA::BLACK
+        }
+        self.color * self.pdf(wo, wi)
+    }
+
+    fn sample(&self, wo: &RtVec3, u1: f32, u2: f32) -> Option<RtBsdfSample> {
+        let reflected = reflect(wo, &self.N);
+        let wi = sample_phong_lobe(&reflected, self.exponent, u1, u2);
+        // Directions below the surface are absorbed
+        if RtVec3::dot(wi, self.N) <= 0.0 {
+            return None
+        }
+        Some(RtBsdfSample {
+            wi,
+            weight: self.color,
+            pdf: pdf_phong_lobe(RtVec3::dot(wi, reflected), self.exponent),
+            is_delta: false,
+            lobe: RtLobe::Specular,
+        })
+    }
+
+    fn pdf(&self, wo: &RtVec3, wi: &RtVec3) -> f32 {
+        let reflected = reflect(wo, &self.N);
+        pdf_phong_lobe(RtVec3::dot(*wi, reflected), self.exponent)
+    }
+}
+
+
 // ========================================
 //  Shader implementation
 // ========================================
@@ -48,23 +119,12 @@ impl RtShader for Metal {
         Box::new(self.clone())
     }
     
-    fn evaluate(&self, scene: &RtScene, sg: &RtShaderGlobals) -> RtRGBA {
-        // Result
-        let mut out_color = RtRGBA::BLACK;
-        
-        let mut ray = RtMakeRay(sg, RtRayType::RT_RAY_UNKNOWN, RtVec3::default(), MAX_RAY_LENGTH);
-        ray.origin = ray.origin + RT_EPSILON * sg.N;  // Avoid self intersections
-        // Reflect the ray
-        RtReflectRay(&mut ray, &sg.ray_dir, &sg.N, &sg);
-        ray.dir = ray.dir + self.fuzz * random_unit_vector();
-
-        // Trace rays
-        let hit = RtTraceRay(scene, &ray);
-        if hit.is_some() {
-            let hit = hit.unwrap();
-            out_color += self.color * hit.colorOutput;
+    fn evaluate(&self, sg: &RtShaderGlobals) -> RtClosure {
+        let N = face_forward(&sg.N, &sg.ray_dir);
+        if self.fuzz <= 0.0 {
+            RtClosure::bsdf(Box::new(MirrorBsdf { N, color: self.color }))
+        } else {
+            RtClosure::bsdf(Box::new(PhongBsdf { N, color: self.color, exponent: self.get_exponent() }))
         }
-
-        out_color
     }
 }
@@ -0,0 +1,100 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Defines BSDFs, returned by the shaders in a closure.
+///   They don't trace rays : the integrator in rt_render
+///   samples and evaluates them to drive the paths.
+///
+///   Directions point away from the shading point : `wo`
+///   towards the viewer and `wi` towards the light.
+/// =====================================================
+
+use crate::rt_types::*;
+
+
+// ========================================
+//  BSDF
+// ========================================
+
+/// Type of the lobe a direction was sampled from,
+/// each type has its own depth limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtLobe {
+    Diffuse,
+    /// Glossy or mirror reflection
+    Specular,
+    /// Through the surface
+    Transmission,
+}
+
+/// Direction sampled from a BSDF
+pub struct RtBsdfSample {
+    /// Sampled direction (normalized)
+    pub wi: RtVec3,
+    /// BSDF times the cosine, divided by the pdf
+    pub weight: RtRGBA,
+    /// Density of `wi` in solid angle (1 for delta lobes)
+    pub pdf: f32,
+    /// Sampled from a delta lobe (mirror, glass) that eval and pdf can't represent
+    pub is_delta: bool,
+    pub lobe: RtLobe,
+}
+
+/// Bidirectional scattering distribution function
+pub trait RtBsdf {
+    /// BSDF value times the cosine between `wi` and the normal
+    fn eval(&self, wo: &RtVec3, wi: &RtVec3) -> RtRGBA;
+
+    /// Sample an incoming direction, `u1` and `u2` are uniform samples in [0, 1)
+    fn sample(&self, wo: &RtVec3, u1: f32, u2: f32) -> Option<RtBsdfSample>;
+
+    /// Density in solid angle of sampling `wi`
+    fn pdf(&self, wo: &RtVec3, wi: &RtVec3) -> f32;
+
+    /// BSDFs made only of delta lobes can't be evaluated,
+    /// so lights are not sampled for them
+    fn is_delta(&self) -> bool {
+        false
+    }
+}
+
+
+// ========================================
+//  Closure
+// ========================================
+
+/// Result of a shader evaluation
+pub struct RtClosure {
+    /// How the surface scatters light, None for black surfaces
+    pub bsdf: Option<Box<dyn RtBsdf>>,
+    /// Radiance emitted by the surface
+    pub emission: RtRGBA,
+}
+
+impl RtClosure {
+    /// Surface that only scatters light
+    pub fn bsdf(bsdf: Box<dyn RtBsdf>) -> Self {
+        Self { bsdf: Some(bsdf), emission: RtRGBA::BLACK }
+    }
+
+    /// Surface that only emits light
+    pub fn emission(emission: RtRGBA) -> Self {
+        Self { bsdf: None, emission }
+    }
+}
+
+
+// ========================================
+//  Utility functions
+// ========================================
+
+/// Mirror direction of `wo` around `normal`
+pub fn reflect(wo: &RtVec3, normal: &RtVec3) -> RtVec3 {
+    (2.0 * RtVec3::dot(*wo, *normal) * *normal - *wo).normalize()
+}
+
+/// Flip `normal` to the side the ray `ray_dir` comes from
+pub fn face_forward(normal: &RtVec3, ray_dir: &RtVec3) -> RtVec3 {
+    if RtVec3::dot(*normal, *ray_dir) > 0.0 { -*normal } else { *normal }
+}
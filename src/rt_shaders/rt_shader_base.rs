@@ -6,15 +6,18 @@
 /// =====================================================
 
 use crate::rt_shader_globals::*;
-use crate::rt_types::*;
-use crate::rt_scene::*;
+use crate::rt_shaders::rt_bsdf::RtClosure;
 
 
-pub trait RtShader {
+/// Shaders are shared between render threads
+pub trait RtShader: Send + Sync {
     // We need a cloning function on this trait for the XML scene parsing
     fn clone_dyn(&self) -> Box<dyn RtShader>;
 
     // fn Init(&mut self, scene: &mut RenderScene);
     // fn Update(&mut self, scene: &mut RtRenderScene);
-    fn evaluate(&self, scene: &RtScene, sg: &RtShaderGlobals) -> RtRGBA;
+
+    /// Closure describing the surface at the shading point.
+    /// Shaders don't trace rays, the integrator does.
+    fn evaluate(&self, sg: &RtShaderGlobals) -> RtClosure;
 }
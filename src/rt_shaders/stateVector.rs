@@ -8,7 +8,7 @@
 use crate::rt_shaders::rt_shader_base::*;
 use crate::rt_shader_globals::*;
 use crate::rt_types::*;
-use crate::rt_scene::*;
+use crate::rt_shaders::rt_bsdf::*;
 
 
 // ========================================
@@ -30,7 +30,7 @@ impl RtShader for StateVectorShader {
         Box::new(self.clone())
     }
     
-    fn evaluate(&self, _scene: &RtScene, sg: &RtShaderGlobals) -> RtRGBA {
+    fn evaluate(&self, sg: &RtShaderGlobals) -> RtClosure {
         // TODO : Switch depending on the value of self.output
         // - N : sg.N
         // - P : sg.P
@@ -38,10 +38,10 @@ impl RtShader for StateVectorShader {
 
         // N
         // From [-1; 1] to [0; 1]
-        RtRGBA::from_rgb(
+        RtClosure::emission(RtRGBA::from_rgb(
             0.5 * (1.0 + sg.N.x), 
             0.5 * (1.0 + sg.N.y), 
             0.5 * (1.0 + sg.N.z)
-        )
+        ))
     }
 }
@@ -8,7 +8,7 @@
 use crate::rt_shaders::rt_shader_base::*;
 use crate::rt_shader_globals::*;
 use crate::rt_types::*;
-use crate::rt_scene::*;
+use crate::rt_shaders::rt_bsdf::*;
 
 
 // ========================================
@@ -30,7 +30,7 @@ impl RtShader for StaticColorShader {
         Box::new(self.clone())
     }
     
-    fn evaluate(&self, _scene: &RtScene, _sg: &RtShaderGlobals) -> RtRGBA {
-        self.color
+    fn evaluate(&self, _sg: &RtShaderGlobals) -> RtClosure {
+        RtClosure::emission(self.color)
     }
 }
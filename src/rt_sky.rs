@@ -0,0 +1,127 @@
+/// =====================================================
+///                    Raito Render
+///
+/// Module description :
+///   Analytic daylight model, giving the radiance of
+///   the physical sky and the irradiance of the sun.
+/// =====================================================
+
+use crate::rt_types::*;
+
+
+// ========================================
+//  Units
+// ========================================
+
+/// Luminance (in cd/m²) of a radiance of 1 in the render
+pub const RT_SKY_LUMINANCE_UNIT: f32 = 100_000.0;
+
+/// Illuminance of the sun outside of the atmosphere, in lux
+pub const RT_SUN_ILLUMINANCE: f32 = 128_000.0;
+
+
+// ========================================
+//  Preetham sky
+// ========================================
+
+/// Clear sky of "A Practical Analytic Model for Daylight",
+/// Preetham, Shirley & Smits (1999)
+///
+/// The sky is driven by the direction of the sun and the turbidity
+/// (haze) of the atmosphere : 2 is a very clear sky, 10 a hazy one.
+/// The sun itself is not part of the sky, see `get_sun_irradiance`.
+#[derive(Debug, Clone)]
+pub struct RtPreethamSky {
+    /// Normalized direction towards the sun
+    pub sun_dir: RtVec3,
+    pub turbidity: f32,
+    /// Luminance and chromaticity (Y, x, y) at the zenith, divided
+    /// by their Perez distribution at the zenith
+    zenith: [f32; 3],
+    /// Perez distribution coefficients of Y, x and y
+    perez: [[f32; 5]; 3],
+}
+
+impl RtPreethamSky {
+    /// Range of turbidity of the model
+    pub const MIN_TURBIDITY: f32 = 1.7;
+    pub const MAX_TURBIDITY: f32 = 10.0;
+
+    /// Sky lit by the sun in the direction `sun_dir`. Under the
+    /// horizon, the sky is computed for a sun on the horizon.
+    pub fn new(sun_dir: RtVec3, turbidity: f32) -> Self {
+        let sun_dir = sun_dir.normalize();
+        let t = turbidity.clamp(Self::MIN_TURBIDITY, Self::MAX_TURBIDITY);
+        let theta_s = sun_dir.y.clamp(-1.0, 1.0).acos().min(0.5 * RT_PI);
+        let (t2, th, th2, th3) = (t * t, theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+
+        // Zenith luminance (kcd/m²) and chromaticity
+        let chi = (4.0 / 9.0 - t / 120.0) * (RT_PI - 2.0 * theta_s);
+        let zenith_Y = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let zenith_y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let zenith_values = [zenith_Y * 1000.0, zenith_x, zenith_y];
+        let zenith = [0, 1, 2].map(|i| zenith_values[i] / Self::perez(&perez[i], 1.0, theta_s));
+        Self { sun_dir, turbidity: t, zenith, perez }
+    }
+
+    /// Perez distribution, `cos_theta` being the cosine of the angle
+    /// to the zenith and `gamma` the angle to the sun
+    fn perez(coefs: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+        let [a, b, c, d, e] = *coefs;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+
+    /// Radiance of the sky in the direction `dir` (normalized).
+    /// Under the horizon, the radiance of the horizon is used.
+    pub fn get_radiance(&self, dir: &RtVec3) -> RtRGBA {
+        let cos_theta = dir.y.max(0.001);
+        let gamma = RtVec3::dot(*dir, self.sun_dir).clamp(-1.0, 1.0).acos();
+        let [Y, x, y] = [0, 1, 2].map(|i| self.zenith[i] * Self::perez(&self.perez[i], cos_theta, gamma));
+        if Y <= 0.0 || y <= 0.0 {
+            return RtRGBA::BLACK
+        }
+        // xyY to linear sRGB, in render units
+        let Y = Y / RT_SKY_LUMINANCE_UNIT;
+        let X = x / y * Y;
+        let Z = (1.0 - x - y) / y * Y;
+        RtRGBA::new(
+            (3.2406 * X - 1.5372 * Y - 0.4986 * Z).max(0.0),
+            (-0.9689 * X + 1.8758 * Y + 0.0415 * Z).max(0.0),
+            (0.0557 * X - 0.2040 * Y + 1.0570 * Z).max(0.0),
+        )
+    }
+
+    /// Irradiance of the sun on a surface facing it, after its travel
+    /// through the atmosphere. Black when the sun is under the horizon.
+    ///
+    /// Only the scattering by the air molecules (Rayleigh) and by the
+    /// aerosols (Angström) are accounted for.
+    pub fn get_sun_irradiance(&self) -> RtRGBA {
+        if self.sun_dir.y <= 0.0 {
+            return RtRGBA::BLACK
+        }
+        // Relative optical mass of the atmosphere in the direction of the sun
+        let theta_s = self.sun_dir.y.acos();
+        let mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        // Transmittance at the wavelengths (in µm) of the primaries
+        let irradiance = |lambda: f32| {
+            let rayleigh = (-mass * 0.008735 * lambda.powf(-4.08)).exp();
+            let aerosols = (-mass * beta * lambda.powf(-1.3)).exp();
+            rayleigh * aerosols * RT_SUN_ILLUMINANCE / RT_SKY_LUMINANCE_UNIT
+        };
+        RtRGBA::new(irradiance(0.650), irradiance(0.570), irradiance(0.475))
+    }
+}
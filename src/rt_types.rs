@@ -13,7 +13,7 @@ use std::ops::Neg;
 
 use egui::Color32;
 
-use crate::{random_float, random_float_range};
+use crate::rt_sampler::RtRng;
 
 // ========================================
 //  Constants
@@ -54,20 +54,20 @@ impl RtRGBA {
         }
     }
 
-    pub fn random() -> Self {
+    pub fn random(rng: &mut RtRng) -> Self {
         Self {
-            r: random_float(),
-            g: random_float(),
-            b: random_float(),
+            r: rng.random_float(),
+            g: rng.random_float(),
+            b: rng.random_float(),
             a: 1.0
         }
     }
     
-    pub fn random_range(a: f32, b: f32) -> Self {
+    pub fn random_range(rng: &mut RtRng, a: f32, b: f32) -> Self {
         Self {
-            r: random_float_range(a, b),
-            g: random_float_range(a, b),
-            b: random_float_range(a, b),
+            r: rng.random_float_range(a, b),
+            g: rng.random_float_range(a, b),
+            b: rng.random_float_range(a, b),
             a: 1.0
         }
     }
@@ -81,6 +81,21 @@ impl RtRGBA {
         }
     }
 
+    /// Relative luminance of a linear color (Rec.709 primaries)
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    /// Encode linear values with the sRGB transfer function
+    pub fn linear_to_srgb(self) -> RtRGBA {
+        RtRGBA {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a
+        }
+    }
+
     #[inline]
     pub const fn from_rgb(r: f32, g: f32, b: f32) -> Self {
         Self { r, g, b, a: 1.0 }
@@ -99,11 +114,9 @@ impl RtRGBA {
     // Would be better on const function but not supported byy rust yet
     pub fn to_color32(&self) -> Color32 {
         // TODO : handle A ?
-        Color32::from_rgb(
-            (self.r * 255.0) as u8, 
-            (self.g * 255.0) as u8, 
-            (self.b * 255.0) as u8
-        )
+        // Clamp first, casting values above 1.0 would saturate and NaN would give 0
+        let to_u8 = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Color32::from_rgb(to_u8(self.r), to_u8(self.g), to_u8(self.b))
     }
 
     pub fn r(&self) -> f32 {
@@ -126,6 +139,17 @@ impl RtRGBA {
     pub const ERRCOLOR : Self   = Self::from_rgb(0.9, 0.5, 0.6);
 }
 
+/// sRGB transfer function (IEC 61966-2-1)
+pub fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        0.0
+    } else if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
 impl std::ops::Add<RtRGBA> for RtRGBA {
     type Output = Self;
     /// Implements Add for RtRGBA * RtRGBA
@@ -224,6 +248,19 @@ impl RtPoint3 {
     }
 }
 
+impl std::ops::Index<usize> for RtPoint3 {
+    type Output = f32;
+    /// Access a component by its axis (0: x, 1: y, 2: z)
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("RtPoint3 index out of bounds : {axis}")
+        }
+    }
+}
+
 impl std::fmt::Display for RtPoint3 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<RtPoint3 ({}, {}, {})>", self.x, self.y, self.z)
@@ -257,6 +294,19 @@ impl Neg for RtVec3 {
     }
 }
 
+impl std::ops::Index<usize> for RtVec3 {
+    type Output = f32;
+    /// Access a component by its axis (0: x, 1: y, 2: z)
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("RtVec3 index out of bounds : {axis}")
+        }
+    }
+}
+
 impl std::ops::Add<RtVec3> for RtVec3 {
     type Output = Self;
     /// Implements Add for RtVec3 * RtVec3
@@ -382,4 +432,118 @@ impl RtVec3 {
             z: u.x * v.y - u.y * v.x
         }
     }
+
+    /// Two vectors forming an orthonormal basis with this normalized vector
+    /// 
+    /// From "Building an Orthonormal Basis, Revisited" (Duff et al. 2017)
+    pub fn coordinate_system(&self) -> (RtVec3, RtVec3) {
+        let sign = 1.0_f32.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            RtVec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            RtVec3::new(b, sign + self.y * self.y * a, -self.y)
+        )
+    }
+}
+
+
+// ========================================
+//  Bounding boxes
+// ========================================
+
+/// Axis-aligned bounding box
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct RtAABB {
+    pub min: RtPoint3,
+    pub max: RtPoint3
+}
+
+impl Default for RtAABB {
+    /// Empty bounding box
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+impl RtAABB {
+    pub const EMPTY: Self = Self {
+        min: RtPoint3 { x: f32::INFINITY, y: f32::INFINITY, z: f32::INFINITY },
+        max: RtPoint3 { x: f32::NEG_INFINITY, y: f32::NEG_INFINITY, z: f32::NEG_INFINITY }
+    };
+
+    pub fn new(min: RtPoint3, max: RtPoint3) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box holding all the points
+    pub fn from_points(points: &[RtPoint3]) -> Self {
+        points.iter().fold(Self::EMPTY, |bbox, p| bbox.union_point(p))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &RtAABB) -> Self {
+        Self {
+            min: RtPoint3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: RtPoint3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z))
+        }
+    }
+
+    pub fn union_point(&self, p: &RtPoint3) -> Self {
+        Self {
+            min: RtPoint3::new(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z)),
+            max: RtPoint3::new(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z))
+        }
+    }
+
+    pub fn diagonal(&self) -> RtVec3 {
+        self.max - self.min
+    }
+
+    pub fn centroid(&self) -> RtPoint3 {
+        self.min + 0.5 * self.diagonal()
+    }
+
+    /// Surface area, used by the SAH
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0
+        }
+        let d = self.diagonal();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /// Axis along which the box is the largest
+    pub fn max_extent(&self) -> usize {
+        let d = self.diagonal();
+        if d.x > d.y && d.x > d.z { 0 } else if d.y > d.z { 1 } else { 2 }
+    }
+
+    /// Slab test against a ray, given the inverse of its direction.
+    /// 
+    /// Returns the entry distance if the ray hits the box before max_dist
+    pub fn intersect(&self, origin: &RtPoint3, inv_dir: &RtVec3, max_dist: f32) -> Option<f32> {
+        let mut t_min: f32 = 0.0;
+        let mut t_max = max_dist;
+        let bounds = [(self.min.x, self.max.x, origin.x, inv_dir.x),
+                      (self.min.y, self.max.y, origin.y, inv_dir.y),
+                      (self.min.z, self.max.z, origin.z, inv_dir.z)];
+        for (min, max, o, inv_d) in bounds {
+            let mut t0 = (min - o) * inv_d;
+            let mut t1 = (max - o) * inv_d;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN values (ray parallel to a slab on its boundary) are ignored
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None
+            }
+        }
+        Some(t_min)
+    }
 }